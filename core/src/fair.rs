@@ -0,0 +1,260 @@
+//! Provably fair rolls using commit-reveal seeds.
+//!
+//! The server picks a secret seed and publishes `sha256(server_seed)` before
+//! anyone rolls. Every roll is `HMAC-SHA256(server_seed, "client_seeds:nonce")`
+//! reduced to `1..=roll_between`. Once the game is over the seed is revealed
+//! so anyone holding the feed can recompute each roll and check the hash.
+
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::Seat;
+
+type HmacSha256 = Hmac<Sha256>;
+
+//client seeds are echoed back in the feed, keep them short
+pub const MAX_CLIENT_SEED_LEN: usize = 64;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Fairness {
+    server_seed: String,
+    client_seeds: Vec<String>,
+    nonce: u64,
+}
+
+//the seed stays secret until it's revealed, logs only get what the feed already has
+impl fmt::Debug for Fairness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Fairness")
+            .field("commitment", &self.commitment())
+            .field("client_seeds", &self.client_seeds)
+            .field("nonce", &self.nonce)
+            .finish()
+    }
+}
+
+impl Fairness {
+    pub fn new<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self {
+            server_seed: new_seed(rng),
+            client_seeds: Vec::new(),
+            nonce: 0,
+        }
+    }
+
+    pub fn commitment(&self) -> String {
+        commit(&self.server_seed)
+    }
+
+    pub fn set_client_seed(&mut self, seat: Seat, seed: String) {
+        if self.client_seeds.len() <= seat {
+            self.client_seeds.resize(seat + 1, String::new());
+        }
+        self.client_seeds[seat] = seed;
+    }
+
+    pub fn client_seeds(&self) -> &[String] {
+        &self.client_seeds
+    }
+
+    /// Derives the next roll, returns it with the nonce it was derived from.
+    pub fn next_roll(&mut self, roll_between: u32) -> (u32, u64) {
+        let nonce = self.nonce;
+        self.nonce += 1;
+
+        (
+            roll(&self.server_seed, &self.client_seeds, nonce, roll_between),
+            nonce,
+        )
+    }
+
+    /// Reveals the current server seed and commits to a fresh one for the
+    /// next game, returns the revealed seed.
+    pub fn reveal<R: Rng + ?Sized>(&mut self, rng: &mut R) -> String {
+        self.nonce = 0;
+        std::mem::replace(&mut self.server_seed, new_seed(rng))
+    }
+}
+
+pub fn new_seed<R: Rng + ?Sized>(rng: &mut R) -> String {
+    let mut seed = [0u8; 32];
+    rng.fill(&mut seed);
+
+    hex::encode(seed)
+}
+
+pub fn commit(server_seed: &str) -> String {
+    hex::encode(Sha256::digest(server_seed.as_bytes()))
+}
+
+/// Seeds are joined with `:` before hashing, so a seed containing one could
+/// stand in for two (`["a:b", "c"]` and `["a", "b:c"]` hash the same).
+pub fn valid_client_seed(seed: &str) -> bool {
+    seed.len() <= MAX_CLIENT_SEED_LEN && !seed.contains(':')
+}
+
+pub fn roll(server_seed: &str, client_seeds: &[String], nonce: u64, roll_between: u32) -> u32 {
+    let mut mac = HmacSha256::new_from_slice(server_seed.as_bytes())
+        .expect("hmac accepts keys of any length");
+    mac.update(format!("{}:{nonce}", client_seeds.join(":")).as_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+
+    (u64::from_be_bytes(bytes) % u64::from(roll_between.max(1))) as u32 + 1
+}
+
+pub fn verify_roll(
+    server_seed: &str,
+    client_seeds: &[String],
+    nonce: u64,
+    roll_between: u32,
+    rolled: u32,
+) -> bool {
+    roll(server_seed, client_seeds, nonce, roll_between) == rolled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn seeds(seeds: &[&str]) -> Vec<String> {
+        seeds.iter().map(|seed| seed.to_string()).collect()
+    }
+
+    #[test]
+    fn commitment_is_the_sha256_of_the_seed() {
+        assert_eq!(
+            commit("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut fairness = Fairness::new(&mut rng);
+        let commitment = fairness.commitment();
+        let revealed = fairness.reveal(&mut rng);
+        assert_eq!(commit(&revealed), commitment);
+        assert_ne!(fairness.commitment(), commitment);
+    }
+
+    #[test]
+    fn debug_hides_the_server_seed() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut fairness = Fairness::new(&mut rng);
+        let debug = format!("{fairness:?}");
+        let server_seed = fairness.reveal(&mut rng);
+        assert!(!debug.contains(&server_seed));
+        assert!(debug.contains(&commit(&server_seed)));
+    }
+
+    #[test]
+    fn roll_known_answers() {
+        //HMAC-SHA256("server-seed", "alice:bob:<nonce>"), first 8 bytes big endian
+        let client_seeds = seeds(&["alice", "bob"]);
+        assert_eq!(roll("server-seed", &client_seeds, 0, 1000), 309);
+        assert_eq!(roll("server-seed", &client_seeds, 1, 1000), 250);
+        assert_eq!(roll("server-seed", &client_seeds, 0, 6), 1);
+    }
+
+    #[test]
+    fn roll_stays_in_range() {
+        let client_seeds = seeds(&["alice", "bob"]);
+        for roll_between in [1, 2, 3, 6, 100, 1000, u32::MAX] {
+            for nonce in 0..200 {
+                let rolled = roll("server-seed", &client_seeds, nonce, roll_between);
+                assert!((1..=roll_between).contains(&rolled));
+            }
+        }
+        //a range of 0 can't happen in a game, it still rolls a 1 instead of dividing by zero
+        assert_eq!(roll("server-seed", &client_seeds, 0, 0), 1);
+    }
+
+    #[test]
+    fn nonces_count_up_and_start_over_after_a_reveal() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut fairness = Fairness::new(&mut rng);
+        fairness.set_client_seed(1, "bob".to_string());
+        assert_eq!(fairness.client_seeds(), ["", "bob"]);
+
+        let nonces: Vec<u64> = (0..3).map(|_| fairness.next_roll(100).1).collect();
+        assert_eq!(nonces, [0, 1, 2]);
+        fairness.reveal(&mut rng);
+        assert_eq!(fairness.next_roll(100).1, 0);
+    }
+
+    #[test]
+    fn verify_accepts_the_real_roll() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut fairness = Fairness::new(&mut rng);
+        fairness.set_client_seed(0, "alice".to_string());
+        fairness.set_client_seed(1, "bob".to_string());
+        let client_seeds = fairness.client_seeds().to_vec();
+        let rolls: Vec<(u32, u64)> = (0..5).map(|_| fairness.next_roll(1000)).collect();
+        let server_seed = fairness.reveal(&mut rng);
+
+        for (rolled, nonce) in rolls {
+            assert!(verify_roll(
+                &server_seed,
+                &client_seeds,
+                nonce,
+                1000,
+                rolled
+            ));
+        }
+    }
+
+    #[test]
+    fn verify_rejects_tampering() {
+        let client_seeds = seeds(&["alice", "bob"]);
+        let rolled = roll("server-seed", &client_seeds, 3, 1000);
+        assert!(verify_roll("server-seed", &client_seeds, 3, 1000, rolled));
+
+        //a different roll, seed, range or nonce (a replayed or skipped one) no longer checks out
+        assert!(!verify_roll(
+            "server-seed",
+            &client_seeds,
+            3,
+            1000,
+            rolled % 1000 + 1
+        ));
+        assert!(!verify_roll("other-seed", &client_seeds, 3, 1000, rolled));
+        assert!(!verify_roll("server-seed", &client_seeds, 3, 999, rolled));
+        assert!(!verify_roll("server-seed", &client_seeds, 2, 1000, rolled));
+        assert!(!verify_roll("server-seed", &client_seeds, 4, 1000, rolled));
+        //the server swapping in its own client seed
+        assert!(!verify_roll(
+            "server-seed",
+            &seeds(&["alice", "server"]),
+            3,
+            1000,
+            rolled
+        ));
+        assert!(!verify_roll(
+            "server-seed",
+            &seeds(&["bob", "alice"]),
+            3,
+            1000,
+            rolled
+        ));
+    }
+
+    #[test]
+    fn client_seeds_with_a_separator_are_rejected() {
+        //these hash the same, which is why a ':' is never accepted
+        assert_eq!(
+            roll("server-seed", &seeds(&["a:b", "c"]), 0, 1000),
+            roll("server-seed", &seeds(&["a", "b:c"]), 0, 1000)
+        );
+        assert!(!valid_client_seed("a:b"));
+        assert!(!valid_client_seed(&"x".repeat(MAX_CLIENT_SEED_LEN + 1)));
+        assert!(valid_client_seed(&"x".repeat(MAX_CLIENT_SEED_LEN)));
+        assert!(valid_client_seed("V1StGXR8_Z5jdHi6B-myT"));
+        assert!(valid_client_seed(""));
+    }
+}
@@ -0,0 +1,518 @@
+//! deathroll rules shared by the server (PvP) and the frontend (PvE).
+//!
+//! Everything in here is plain state, no sockets, timers or UI, so the same
+//! rules run on both sides and a rule change only has to be made once.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub mod fair;
+
+pub type Seat = usize;
+
+//1v1 is the classic game, groups of up to 10 deathroll together
+pub const MIN_SEATS: usize = 2;
+pub const MAX_SEATS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Phase {
+    //waiting for every seat to be taken
+    Waiting,
+    Playing,
+    GameOver,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleError {
+    InvalidStartRoll,
+    InvalidSeats,
+    NotStarted,
+    AlreadyStarted,
+    GameOver,
+    GameNotOver,
+    NotYourTurn,
+    //the seat already rolled a 1 this game
+    Eliminated,
+    //a series has to be an odd number of games
+    InvalidSeries,
+    SeriesOver,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Rolled {
+        seat: Seat,
+        roll: u32,
+        roll_between: u32,
+        next: Seat,
+    },
+    //`seat` is out, `next` starts over from the start roll
+    Eliminated {
+        seat: Seat,
+        roll_between: u32,
+        next: Seat,
+    },
+    //`seat` was the last one out, `winner` is the last player standing
+    Died {
+        seat: Seat,
+        roll_between: u32,
+        winner: Seat,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deathroll {
+    start_roll: u32,
+    roll: u32,
+    turn: Seat,
+    start_seat: Seat,
+    phase: Phase,
+    //seats still in the current game
+    alive: Vec<bool>,
+    scores: Vec<u32>,
+    //None plays on forever
+    #[serde(default)]
+    best_of: Option<u32>,
+}
+
+impl Deathroll {
+    pub fn new(start_roll: u32) -> Result<Self, RuleError> {
+        Self::with_seats(start_roll, MIN_SEATS)
+    }
+
+    pub fn with_seats(start_roll: u32, seats: usize) -> Result<Self, RuleError> {
+        //a start roll of 1 (or 0) would be over before it began
+        if start_roll < 2 {
+            return Err(RuleError::InvalidStartRoll);
+        }
+        if !(MIN_SEATS..=MAX_SEATS).contains(&seats) {
+            return Err(RuleError::InvalidSeats);
+        }
+
+        Ok(Self {
+            start_roll,
+            roll: start_roll,
+            turn: 0,
+            start_seat: 0,
+            phase: Phase::Waiting,
+            alive: vec![true; seats],
+            scores: vec![0; seats],
+            best_of: None,
+        })
+    }
+
+    /// Turns the session into a best-of series, `games` must be odd.
+    pub fn with_best_of(mut self, best_of: Option<u32>) -> Result<Self, RuleError> {
+        if best_of.is_some_and(|games| games % 2 == 0) {
+            return Err(RuleError::InvalidSeries);
+        }
+        self.best_of = best_of;
+
+        Ok(self)
+    }
+
+    pub fn start(&mut self) -> Result<(), RuleError> {
+        if self.phase != Phase::Waiting {
+            return Err(RuleError::AlreadyStarted);
+        }
+        self.phase = Phase::Playing;
+
+        Ok(())
+    }
+
+    pub fn roll<R: Rng + ?Sized>(&mut self, seat: Seat, rng: &mut R) -> Result<Outcome, RuleError> {
+        self.roll_with(seat, |roll_between| rng.gen_range(1..=roll_between))
+    }
+
+    /// Takes a turn for `seat`, `die` is handed the current maximum and must
+    /// return a number between 1 and that maximum.
+    pub fn roll_with(
+        &mut self,
+        seat: Seat,
+        die: impl FnOnce(u32) -> u32,
+    ) -> Result<Outcome, RuleError> {
+        self.check_playing(seat)?;
+        if seat != self.turn {
+            return Err(RuleError::NotYourTurn);
+        }
+
+        let roll_between = self.roll;
+        let roll = die(roll_between).clamp(1, roll_between);
+
+        if roll == 1 {
+            Ok(self.eliminate(seat, roll_between))
+        } else {
+            let next = self.next_seat(seat);
+            self.roll = roll;
+            self.turn = next;
+
+            Ok(Outcome::Rolled {
+                seat,
+                roll,
+                roll_between,
+                next,
+            })
+        }
+    }
+
+    /// Takes `seat` out of the game in progress as if they had rolled a 1.
+    pub fn forfeit(&mut self, seat: Seat) -> Result<Outcome, RuleError> {
+        self.check_playing(seat)?;
+
+        Ok(self.eliminate(seat, self.roll))
+    }
+
+    fn check_playing(&self, seat: Seat) -> Result<(), RuleError> {
+        match self.phase {
+            Phase::Waiting => return Err(RuleError::NotStarted),
+            Phase::GameOver => return Err(RuleError::GameOver),
+            Phase::Playing => {}
+        }
+        if !self.is_alive(seat) {
+            return Err(RuleError::Eliminated);
+        }
+
+        Ok(())
+    }
+
+    fn eliminate(&mut self, seat: Seat, roll_between: u32) -> Outcome {
+        self.alive[seat] = false;
+
+        let mut standing = (0..self.seats()).filter(|seat| self.alive[*seat]);
+        match (standing.next(), standing.next()) {
+            (Some(winner), None) => {
+                self.scores[winner] += 1;
+                self.roll = 1;
+                self.phase = Phase::GameOver;
+
+                Outcome::Died {
+                    seat,
+                    roll_between,
+                    winner,
+                }
+            }
+            _ => {
+                //the next player starts a fresh round, unless someone else was mid turn
+                if self.turn == seat {
+                    self.turn = self.next_seat(seat);
+                    self.roll = self.start_roll;
+                }
+
+                Outcome::Eliminated {
+                    seat,
+                    roll_between,
+                    next: self.turn,
+                }
+            }
+        }
+    }
+
+    /// Starts the next game of the session with every seat back in, the seat
+    /// after the last game's starter goes first.
+    pub fn rematch(&mut self) -> Result<Seat, RuleError> {
+        if self.phase != Phase::GameOver {
+            return Err(RuleError::GameNotOver);
+        }
+        if self.series_winner().is_some() {
+            return Err(RuleError::SeriesOver);
+        }
+        self.alive.fill(true);
+        self.start_seat = next_seat(self.start_seat, self.seats());
+        self.turn = self.start_seat;
+        self.roll = self.start_roll;
+        self.phase = Phase::Playing;
+
+        Ok(self.start_seat)
+    }
+
+    /// The next seat after `seat` that is still in the game.
+    pub fn next_seat(&self, seat: Seat) -> Seat {
+        let mut next = next_seat(seat, self.seats());
+        while !self.alive[next] && next != seat {
+            next = next_seat(next, self.seats());
+        }
+
+        next
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn turn(&self) -> Seat {
+        self.turn
+    }
+
+    pub fn start_seat(&self) -> Seat {
+        self.start_seat
+    }
+
+    pub fn roll_between(&self) -> u32 {
+        self.roll
+    }
+
+    pub fn start_roll(&self) -> u32 {
+        self.start_roll
+    }
+
+    pub fn seats(&self) -> usize {
+        self.alive.len()
+    }
+
+    pub fn is_alive(&self, seat: Seat) -> bool {
+        self.alive.get(seat).copied().unwrap_or(false)
+    }
+
+    pub fn scores(&self) -> &[u32] {
+        &self.scores
+    }
+
+    pub fn best_of(&self) -> Option<u32> {
+        self.best_of
+    }
+
+    /// Whoever has won the series once a game is over. A seat needs a majority
+    /// of the games, with more than two seats that might not happen, so after
+    /// `best_of` games the single leader wins and a tie plays on.
+    pub fn series_winner(&self) -> Option<Seat> {
+        let best_of = self.best_of?;
+        if self.phase != Phase::GameOver {
+            return None;
+        }
+
+        let played: u32 = self.scores.iter().sum();
+        let most = self.scores.iter().copied().max()?;
+        let mut leaders = (0..self.seats()).filter(|seat| self.scores[*seat] == most);
+        match (leaders.next(), leaders.next()) {
+            (Some(leader), None) if most > best_of / 2 || played >= best_of => Some(leader),
+            _ => None,
+        }
+    }
+}
+
+pub fn next_seat(seat: Seat, seats: usize) -> Seat {
+    (seat + 1) % seats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started(start_roll: u32, seats: usize) -> Deathroll {
+        let mut game = Deathroll::with_seats(start_roll, seats).unwrap();
+        game.start().unwrap();
+        game
+    }
+
+    #[test]
+    fn start_roll_bounds() {
+        assert_eq!(Deathroll::new(0).unwrap_err(), RuleError::InvalidStartRoll);
+        assert_eq!(Deathroll::new(1).unwrap_err(), RuleError::InvalidStartRoll);
+        assert_eq!(Deathroll::new(2).unwrap().roll_between(), 2);
+        assert_eq!(Deathroll::new(u32::MAX).unwrap().start_roll(), u32::MAX);
+
+        assert_eq!(
+            Deathroll::with_seats(100, 1).unwrap_err(),
+            RuleError::InvalidSeats
+        );
+        assert_eq!(
+            Deathroll::with_seats(100, MAX_SEATS + 1).unwrap_err(),
+            RuleError::InvalidSeats
+        );
+        assert_eq!(
+            Deathroll::with_seats(100, MAX_SEATS).unwrap().seats(),
+            MAX_SEATS
+        );
+    }
+
+    #[test]
+    fn no_rolls_before_start_or_twice_started() {
+        let mut game = Deathroll::new(100).unwrap();
+        assert_eq!(game.phase(), Phase::Waiting);
+        assert_eq!(game.roll_with(0, |_| 50), Err(RuleError::NotStarted));
+        assert_eq!(game.forfeit(0), Err(RuleError::NotStarted));
+
+        game.start().unwrap();
+        assert_eq!(game.start(), Err(RuleError::AlreadyStarted));
+    }
+
+    #[test]
+    fn turns_alternate_and_the_range_shrinks() {
+        let mut game = started(100, 2);
+
+        assert_eq!(
+            game.roll_with(0, |max| {
+                assert_eq!(max, 100);
+                40
+            }),
+            Ok(Outcome::Rolled {
+                seat: 0,
+                roll: 40,
+                roll_between: 100,
+                next: 1
+            })
+        );
+        assert_eq!(game.turn(), 1);
+        assert_eq!(game.roll_between(), 40);
+
+        assert_eq!(
+            game.roll_with(1, |_| 7),
+            Ok(Outcome::Rolled {
+                seat: 1,
+                roll: 7,
+                roll_between: 40,
+                next: 0
+            })
+        );
+        assert_eq!(game.turn(), 0);
+    }
+
+    #[test]
+    fn not_your_turn() {
+        let mut game = started(100, 3);
+
+        assert_eq!(game.roll_with(1, |_| 50), Err(RuleError::NotYourTurn));
+        assert_eq!(game.roll_with(2, |_| 50), Err(RuleError::NotYourTurn));
+        //nothing moved
+        assert_eq!((game.turn(), game.roll_between()), (0, 100));
+
+        game.roll_with(0, |_| 50).unwrap();
+        assert_eq!(game.roll_with(0, |_| 20), Err(RuleError::NotYourTurn));
+        assert_eq!(
+            game.roll_with(1, |_| 20).unwrap(),
+            Outcome::Rolled {
+                seat: 1,
+                roll: 20,
+                roll_between: 50,
+                next: 2
+            }
+        );
+    }
+
+    #[test]
+    fn a_one_ends_the_game() {
+        let mut game = started(100, 2);
+        game.roll_with(0, |_| 10).unwrap();
+
+        assert_eq!(
+            game.roll_with(1, |_| 1),
+            Ok(Outcome::Died {
+                seat: 1,
+                roll_between: 10,
+                winner: 0
+            })
+        );
+        assert_eq!(game.phase(), Phase::GameOver);
+        assert_eq!(game.scores(), [1, 0]);
+        assert_eq!(game.roll_with(0, |_| 5), Err(RuleError::GameOver));
+        assert_eq!(game.forfeit(0), Err(RuleError::GameOver));
+    }
+
+    #[test]
+    fn die_is_clamped_to_the_range() {
+        let mut game = started(100, 2);
+
+        assert!(matches!(
+            game.roll_with(0, |_| 500),
+            Ok(Outcome::Rolled { roll: 100, .. })
+        ));
+        //a 0 counts as the 1 it was clamped to
+        assert!(matches!(
+            game.roll_with(1, |_| 0),
+            Ok(Outcome::Died { seat: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn eliminated_seats_are_skipped() {
+        let mut game = started(100, 3);
+        game.roll_with(0, |_| 50).unwrap();
+
+        //seat 1 is out, seat 2 starts over from the start roll
+        assert_eq!(
+            game.roll_with(1, |_| 1),
+            Ok(Outcome::Eliminated {
+                seat: 1,
+                roll_between: 50,
+                next: 2
+            })
+        );
+        assert_eq!((game.turn(), game.roll_between()), (2, 100));
+        assert_eq!(game.roll_with(1, |_| 5), Err(RuleError::Eliminated));
+
+        game.roll_with(2, |_| 30).unwrap();
+        assert_eq!(game.turn(), 0);
+        assert_eq!(
+            game.roll_with(0, |_| 1),
+            Ok(Outcome::Died {
+                seat: 0,
+                roll_between: 30,
+                winner: 2
+            })
+        );
+        assert_eq!(game.scores(), [0, 0, 1]);
+    }
+
+    #[test]
+    fn forfeit_out_of_turn_keeps_the_turn() {
+        let mut game = started(100, 3);
+        game.roll_with(0, |_| 50).unwrap();
+
+        assert_eq!(
+            game.forfeit(2),
+            Ok(Outcome::Eliminated {
+                seat: 2,
+                roll_between: 50,
+                next: 1
+            })
+        );
+        assert_eq!((game.turn(), game.roll_between()), (1, 50));
+        assert_eq!(
+            game.roll_with(1, |_| 20).unwrap(),
+            Outcome::Rolled {
+                seat: 1,
+                roll: 20,
+                roll_between: 50,
+                next: 0
+            }
+        );
+    }
+
+    #[test]
+    fn rematch_rotates_the_starter() {
+        let mut game = started(100, 2);
+        assert_eq!(game.rematch(), Err(RuleError::GameNotOver));
+
+        game.roll_with(0, |_| 1).unwrap();
+        assert_eq!(game.rematch(), Ok(1));
+        assert_eq!((game.turn(), game.roll_between()), (1, 100));
+        assert_eq!(game.roll_with(0, |_| 5), Err(RuleError::NotYourTurn));
+    }
+
+    #[test]
+    fn best_of_series() {
+        assert_eq!(
+            Deathroll::new(100)
+                .unwrap()
+                .with_best_of(Some(2))
+                .unwrap_err(),
+            RuleError::InvalidSeries
+        );
+
+        let mut game = started(100, 2).with_best_of(Some(3)).unwrap();
+        //seat 1 loses the first, seat 0 the next two
+        game.roll_with(0, |_| 50).unwrap();
+        game.roll_with(1, |_| 1).unwrap();
+        assert_eq!(game.series_winner(), None);
+
+        assert_eq!(game.rematch(), Ok(1));
+        game.roll_with(1, |_| 50).unwrap();
+        game.roll_with(0, |_| 1).unwrap();
+        assert_eq!(game.series_winner(), None);
+
+        assert_eq!(game.rematch(), Ok(0));
+        game.roll_with(0, |_| 1).unwrap();
+        assert_eq!(game.series_winner(), Some(1));
+        assert_eq!(game.rematch(), Err(RuleError::SeriesOver));
+    }
+}
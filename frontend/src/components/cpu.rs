@@ -1,7 +1,6 @@
+use deathroll_core::{Deathroll, Outcome, Phase, Seat};
 use futures::FutureExt;
-use rand::Rng;
 use std::time::Duration;
-use std::vec;
 use web_sys::{Element, MouseEvent};
 use yew::platform::spawn_local;
 use yew::platform::time::sleep;
@@ -10,6 +9,9 @@ use yew_router::prelude::*;
 
 use crate::routes::Route;
 
+const PLAYER: Seat = 0;
+const COMPUTER: Seat = 1;
+
 pub enum Msg {
     Roll,
     Reset,
@@ -23,10 +25,8 @@ pub enum Msg {
 }
 
 pub struct PvEComponent {
-    roll_amount: u32,
+    game: Option<Deathroll>,
     player_turn: bool,
-    game_over: bool,
-    display_roll: Vec<u32>,
     player_rolling: bool,
     player_result: bool,
     game_start: bool,
@@ -49,25 +49,31 @@ impl PvEComponent {
         })
     }
 
-    fn add_to_feed(&self, slash_roll: String) -> String {
-        let prev_turn = {
-            self.display_roll
-                .len()
-                .checked_sub(2)
-                .map(|i| self.display_roll[i])
-                .unwrap()
-        };
+    fn game_over(&self) -> bool {
+        self.game
+            .as_ref()
+            .map(|game| game.phase() == Phase::GameOver)
+            .unwrap_or(false)
+    }
+
+    fn roll_between(&self) -> u32 {
+        self.game
+            .as_ref()
+            .map(|game| game.roll_between())
+            .unwrap_or(self.num_input)
+    }
 
+    fn add_to_feed(&self, slash_roll: String, roll: u32, roll_between: u32) -> String {
         let end = self.add_end();
 
-        let borrowed_roll = self.roll_amount.to_string();
+        let borrowed_roll = roll.to_string();
         let space = " (1-";
-        let prev = prev_turn.to_string();
+        let prev = roll_between.to_string();
 
         slash_roll + &borrowed_roll + space + &prev + &end
     }
     fn add_end(&self) -> String {
-        if self.game_over {
+        if self.game_over() {
             let end = ") \u{1F480}\u{1F480}\u{1F480}\u{1F480}\u{1F480}\u{1F480}";
             end.to_string()
         } else {
@@ -90,10 +96,8 @@ impl Component for PvEComponent {
         let num_input = roll_amount.trim().parse::<u32>().unwrap_or(1);
 
         Self {
-            roll_amount: num_input,
+            game: None,
             player_turn: true,
-            game_over: false,
-            display_roll: vec![num_input],
             player_rolling: false,
             player_result: false,
             game_start: true,
@@ -168,14 +172,14 @@ impl Component for PvEComponent {
             <footer>
 
             <div>
-            if !self.game_over  {<button hidden=true>{""}</button>
+            if !self.game_over()  {<button hidden=true>{""}</button>
              } else {
             <button onclick={reset_game} class="roll-button">{replay}</button>
              }
 
             </div>
             <div>
-            if self.player_turn && !self.game_over  && !self.game_start {<button onclick={on_click} class="roll-button">{"\u{1F9D9}\u{200D}\u{2642}\u{FE0F}"}{roll_emoji}
+            if self.player_turn && !self.game_over()  && !self.game_start {<button onclick={on_click} class="roll-button">{"\u{1F9D9}\u{200D}\u{2642}\u{FE0F}"}{roll_emoji}
             </button>} else {
             <button hidden=true>{""}</button>
             }
@@ -201,7 +205,7 @@ impl Component for PvEComponent {
 
                 let slash_roll: String = "\u{1F9D9}\u{200D}\u{2642}\u{FE0F} /roll ".to_owned();
                 let space = " 1-";
-                let value = self.roll_amount.to_string();
+                let value = self.roll_between().to_string();
 
                 let is_rolling = slash_roll + space + &value;
                 self.feed.push(is_rolling);
@@ -221,11 +225,8 @@ impl Component for PvEComponent {
                 log::debug!("{:?}", roll_amount);
 
                 let num_input: u32 = roll_amount.trim().parse::<u32>().unwrap_or(1);
-                self.roll_amount = num_input;
-                self.display_roll.clear();
-                self.game_over = false;
+                self.game = None;
                 self.player_turn = true;
-                self.display_roll.push(num_input);
                 self.game_start = true;
                 self.computer_result = false;
                 self.feed.clear();
@@ -237,25 +238,31 @@ impl Component for PvEComponent {
                 self.scroll_top();
 
                 self.computer_result = true;
-                self.roll_amount = roll(self.roll_amount);
-                self.display_roll.push(self.roll_amount);
-
-                //log::debug!("computer roll: {:?}", self.roll_amount);
-
-                self.computer_result = true;
-
-                if self.roll_amount == 1 {
-                    self.game_over = true;
-                    self.player_turn = true;
-
-                    let slash_roll = " \u{1F916} rolls ".to_owned();
-                    let is_rolling = self.add_to_feed(slash_roll);
-                    self.feed.push(is_rolling);
-                    self.feed.push(victory);
-                } else {
-                    let slash_roll: String = " \u{1F916} rolls ".to_owned();
-                    let is_rolling = self.add_to_feed(slash_roll);
-                    self.feed.push(is_rolling);
+                let outcome = match self.game.as_mut() {
+                    Some(game) => game.roll(COMPUTER, &mut rand::thread_rng()),
+                    None => return true,
+                };
+
+                //log::debug!("computer roll: {:?}", outcome);
+
+                match outcome {
+                    Ok(Outcome::Died { roll_between, .. }) => {
+                        self.player_turn = true;
+
+                        let slash_roll = " \u{1F916} rolls ".to_owned();
+                        let is_rolling = self.add_to_feed(slash_roll, 1, roll_between);
+                        self.feed.push(is_rolling);
+                        self.feed.push(victory);
+                    }
+                    Ok(Outcome::Rolled {
+                        roll, roll_between, ..
+                    }) => {
+                        let slash_roll: String = " \u{1F916} rolls ".to_owned();
+                        let is_rolling = self.add_to_feed(slash_roll, roll, roll_between);
+                        self.feed.push(is_rolling);
+                    }
+                    //nobody is eliminated with only two seats
+                    Ok(Outcome::Eliminated { .. }) | Err(_) => {}
                 }
 
                 self.player_turn = true;
@@ -265,30 +272,38 @@ impl Component for PvEComponent {
                 self.scroll_top();
 
                 self.computer_result = false;
-                self.roll_amount = roll(self.roll_amount);
-                self.display_roll.push(self.roll_amount);
+                let outcome = match self.game.as_mut() {
+                    Some(game) => game.roll(PLAYER, &mut rand::thread_rng()),
+                    None => return true,
+                };
 
-                //log::debug!("player roll: {:?}", self.roll_amount);
+                //log::debug!("player roll: {:?}", outcome);
 
                 self.player_rolling = false;
 
-                if self.roll_amount == 1 {
-                    self.game_over = true;
-
-                    let slash_roll = " \u{1F9D9}\u{200D}\u{2642}\u{FE0F} rolled ".to_owned();
-                    let is_rolling = self.add_to_feed(slash_roll);
-                    self.feed.push(is_rolling);
-                    self.feed.push(defeat);
-                    //log::debug!("player died");
-                } else {
-                    self.player_result = true;
-                    let is_initialized = delay_roll();
-                    ctx.link()
-                        .send_future(is_initialized.map(Msg::PlayerResult));
-
-                    let slash_roll: String = " \u{1F9D9}\u{200D}\u{2642}\u{FE0F} rolls ".to_owned();
-                    let is_rolling = self.add_to_feed(slash_roll);
-                    self.feed.push(is_rolling);
+                match outcome {
+                    Ok(Outcome::Died { roll_between, .. }) => {
+                        let slash_roll = " \u{1F9D9}\u{200D}\u{2642}\u{FE0F} rolled ".to_owned();
+                        let is_rolling = self.add_to_feed(slash_roll, 1, roll_between);
+                        self.feed.push(is_rolling);
+                        self.feed.push(defeat);
+                        //log::debug!("player died");
+                    }
+                    Ok(Outcome::Rolled {
+                        roll, roll_between, ..
+                    }) => {
+                        self.player_result = true;
+                        let is_initialized = delay_roll();
+                        ctx.link()
+                            .send_future(is_initialized.map(Msg::PlayerResult));
+
+                        let slash_roll: String =
+                            " \u{1F9D9}\u{200D}\u{2642}\u{FE0F} rolls ".to_owned();
+                        let is_rolling = self.add_to_feed(slash_roll, roll, roll_between);
+                        self.feed.push(is_rolling);
+                    }
+                    //nobody is eliminated with only two seats
+                    Ok(Outcome::Eliminated { .. }) | Err(_) => {}
                 }
 
                 true
@@ -298,7 +313,7 @@ impl Component for PvEComponent {
 
                 let slash_roll: String = "\u{1F916} /roll ".to_owned();
                 let space = " 1-";
-                let value = self.roll_amount.to_string();
+                let value = self.roll_between().to_string();
 
                 let is_rolling = slash_roll + space + &value;
 
@@ -320,11 +335,10 @@ impl Component for PvEComponent {
                 true
             }
             Msg::Start => {
-                if self.num_input != 1 {
+                if let Ok(mut game) = Deathroll::new(self.num_input) {
                     //fix bug where game was not reseting correctly
-                    self.display_roll.clear();
-                    self.display_roll.push(self.num_input);
-                    self.roll_amount = self.num_input;
+                    let _ = game.start();
+                    self.game = Some(game);
                     //log::debug!("{:?}", self.num_input);
                     ctx.link().send_message(Msg::Roll);
                 } else {
@@ -349,12 +363,6 @@ impl Component for PvEComponent {
     }
 }
 
-fn roll(num: u32) -> u32 {
-    let mut rng = rand::thread_rng();
-
-    rng.gen_range(1..=num)
-}
-
 async fn delay_roll() {
     sleep(Duration::from_secs(1)).await;
 }
@@ -1,18 +1,35 @@
+use deathroll_core::{MAX_SEATS, MIN_SEATS};
+use deathroll_protocol::{
+    GameMessage, NewRoom, OnTimeout, Profile, RoomSettings, AVATAR_CHOICES, BEST_OF, BRACKETS,
+    MAX_NAME_CHARS, TURN_SECS, WAGERS,
+};
 use gloo_net::http::Request;
 use nanoid::nanoid;
+use std::time::Duration;
 
 use web_sys::HtmlInputElement;
+use yew::platform::time::sleep;
 use yew::{platform::spawn_local, prelude::*};
 use yew_router::prelude::*;
 
 use crate::routes::Route;
 
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
 pub struct Home {
     rules: bool,
     input: NodeRef,
     input_pve: NodeRef,
     pub start_roll: Option<u32>,
     pub start_roll_pve: Option<u32>,
+    settings: RoomSettings,
+    profile: Profile,
+    name_input: NodeRef,
+    //saved, or why the server refused the name
+    profile_msg: String,
+    //rooms the server refused to create, each one disappears after a few seconds
+    toasts: Vec<(u32, String)>,
+    next_toast: u32,
 }
 
 pub enum Msg {
@@ -24,18 +41,58 @@ pub enum Msg {
     NewPvpGame(u32),
     NewPveGame(u32),
     NewPveGameCustom,
+    Seats(usize),
+    TurnSecs(Option<u32>),
+    OnTimeout(OnTimeout),
+    BestOf(Option<u32>),
+    Wager(u64),
+    Public(bool),
+    Lobby,
+    Leaderboard,
+    MyGames,
+    FindOpponent(u32),
+    FindOpponentCustom,
+    ProfileLoaded(Profile),
+    Avatar(String),
+    SaveProfile,
+    ProfileSaved(Result<Profile, String>),
+    //the new room's id, or why the server refused it
+    RoomCreated(Result<String, String>),
+    DismissToast(u32),
 }
 
 impl Component for Home {
     type Message = Msg;
     type Properties = ();
-    fn create(_ctx: &yew::Context<Self>) -> Self {
+    fn create(ctx: &yew::Context<Self>) -> Self {
+        let link = ctx.link().clone();
+        spawn_local(async move {
+            if let Ok(res) = Request::get("/api/profile").send().await {
+                if let Ok(profile) = res.json().await {
+                    link.send_message(Msg::ProfileLoaded(profile));
+                }
+            }
+        });
+
         Self {
             rules: false,
             input: NodeRef::default(),
             input_pve: NodeRef::default(),
             start_roll: None,
             start_roll_pve: None,
+            settings: RoomSettings {
+                seats: MIN_SEATS,
+                turn_secs: Some(30),
+                on_timeout: OnTimeout::AutoRoll,
+                best_of: None,
+                wager: 0,
+                public: false,
+            },
+            profile: Profile::for_seat(0),
+            name_input: NodeRef::default(),
+            profile_msg: "".to_string(),
+            toasts: Vec::new(),
+            next_toast: 0,
         }
     }
     fn view(&self, ctx: &yew::Context<Self>) -> Html {
@@ -54,6 +111,13 @@ impl Component for Home {
 
         let rules = ctx.link().callback(move |_: MouseEvent| Msg::ShowRules);
 
+        let save_profile = ctx.link().callback(|_: MouseEvent| Msg::SaveProfile);
+
+        let public = !self.settings.public;
+        let toggle_public = ctx
+            .link()
+            .callback(move |_: MouseEvent| Msg::Public(public));
+
         let oninput_pvp = ctx.link().batch_callback(move |_| {
             let input = input_ref_pvp.cast::<HtmlInputElement>();
 
@@ -102,7 +166,53 @@ impl Component for Home {
             {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
 
             </header>
-            <h3>{"PvP (Multiplayer 1v1) \u{2694}\u{FE0F}"}</h3>
+            <h3>{"Profile "}{&self.profile.avatar}</h3>
+                <input
+                ref={&self.name_input}
+                placeholder="nickname"
+                value={self.profile.name.clone()}
+                type="text" maxlength={MAX_NAME_CHARS.to_string()}
+                />
+                <button onclick={save_profile}>{"\u{1F4BE} save"}</button>
+                <button onclick={ctx.link().callback(|_: MouseEvent| Msg::MyGames)}>{"\u{1F4DC} my games"}</button>
+                <br/>
+                {
+                    AVATAR_CHOICES.iter().map(|avatar| {
+                        let selected = self.profile.avatar == *avatar;
+                        html! {
+                            <button onclick={ctx.link().callback(move |_: MouseEvent| Msg::Avatar(avatar.to_string()))}>
+                                {checked(selected)}{*avatar}
+                            </button>
+                        }
+                    }).collect::<Html>()
+                }
+                <div>{&self.profile_msg}</div>
+            <h3>{"PvP (Multiplayer) \u{2694}\u{FE0F}"}</h3>
+                <button onclick={ctx.link().callback(|_: MouseEvent| Msg::Lobby)}>{"\u{1F310} browse open games"}</button>
+                <button onclick={ctx.link().callback(|_: MouseEvent| Msg::Leaderboard)}>{"\u{1F3C5} leaderboard"}</button>
+                <br/>
+                {"\u{1F50D} find opponent "}
+                {
+                    BRACKETS.iter().map(|roll| {
+                        let roll = *roll;
+                        html! {
+                            <button onclick={ctx.link().callback(move |_: MouseEvent| Msg::FindOpponent(roll))}>
+                                {roll}
+                            </button>
+                        }
+                    }).collect::<Html>()
+                }
+                <button onclick={ctx.link().callback(|_: MouseEvent| Msg::FindOpponentCustom)}>{"custom"}</button>
+                <br/>
+                {"\u{1F465} players "}
+                {
+                    (MIN_SEATS..=MAX_SEATS).map(|seats| html! {
+                        <button onclick={ctx.link().callback(move |_: MouseEvent| Msg::Seats(seats))}>
+                            {checked(self.settings.seats == seats)}{seats}
+                        </button>
+                    }).collect::<Html>()
+                }
+                <br/>
                 <button onclick={pvp_roll(100, ctx)}>{ "100" }</button>
                 <button onclick={pvp_roll(1000, ctx)}>{ "1000" }</button>
                 <button onclick={pvp_roll(10000, ctx)}>{ "10000" }</button>
@@ -121,6 +231,65 @@ impl Component for Home {
                     title="Non-negative integral number"
 
                     /> <button onclick={pvp}>{ "custom game" }</button>
+                <br/>
+                {"\u{23F3} turn clock "}
+                <button onclick={ctx.link().callback(|_: MouseEvent| Msg::TurnSecs(None))}>
+                    {checked(self.settings.turn_secs.is_none())}{"off"}
+                </button>
+                {
+                    TURN_SECS.iter().map(|secs| {
+                        let secs = *secs;
+                        html! {
+                            <button onclick={ctx.link().callback(move |_: MouseEvent| Msg::TurnSecs(Some(secs)))}>
+                                {checked(self.settings.turn_secs == Some(secs))}{format!("{secs}s")}
+                            </button>
+                        }
+                    }).collect::<Html>()
+                }
+                if self.settings.turn_secs.is_some() {
+                    <br/>
+                    {"when it runs out "}
+                    <button onclick={ctx.link().callback(|_: MouseEvent| Msg::OnTimeout(OnTimeout::AutoRoll))}>
+                        {checked(self.settings.on_timeout == OnTimeout::AutoRoll)}{"\u{1F3B2} auto roll"}
+                    </button>
+                    <button onclick={ctx.link().callback(|_: MouseEvent| Msg::OnTimeout(OnTimeout::Forfeit))}>
+                        {checked(self.settings.on_timeout == OnTimeout::Forfeit)}{"\u{1F3F3}\u{FE0F} forfeit"}
+                    </button>
+                }
+                <br/>
+                {"\u{1F3C6} series "}
+                <button onclick={ctx.link().callback(|_: MouseEvent| Msg::BestOf(None))}>
+                    {checked(self.settings.best_of.is_none())}{"endless"}
+                </button>
+                {
+                    BEST_OF.iter().map(|games| {
+                        let games = *games;
+                        html! {
+                            <button onclick={ctx.link().callback(move |_: MouseEvent| Msg::BestOf(Some(games)))}>
+                                {checked(self.settings.best_of == Some(games))}{format!("best of {games}")}
+                            </button>
+                        }
+                    }).collect::<Html>()
+                }
+                <br/>
+                {"\u{1F4B0} wager "}
+                <button onclick={ctx.link().callback(|_: MouseEvent| Msg::Wager(0))}>
+                    {checked(self.settings.wager == 0)}{"none"}
+                </button>
+                {
+                    WAGERS.iter().map(|wager| {
+                        let wager = *wager;
+                        html! {
+                            <button onclick={ctx.link().callback(move |_: MouseEvent| Msg::Wager(wager))}>
+                                {checked(self.settings.wager == wager)}{format!("{wager} gold")}
+                            </button>
+                        }
+                    }).collect::<Html>()
+                }
+                <br/>
+                <button onclick={toggle_public}>
+                    {checked(self.settings.public)}{"\u{1F310} list in the lobby"}
+                </button>
                 <h3>{"PvE (CPU) \u{1F916}"}</h3>
                 <button onclick={pve_roll(100, ctx)}>{ "100" }</button>
                 <button onclick={pve_roll(1000, ctx)}>{ "1000" }</button>
@@ -140,6 +309,13 @@ impl Component for Home {
                     title="Non-negative integral number"
 
                     /> <button onclick={pve}>{ "custom game" }</button>
+                <div class="toasts">
+                {
+                    self.toasts.iter().map(|(id, msg)| html! {
+                        <div key={*id} class="toast">{msg}</div>
+                    }).collect::<Html>()
+                }
+                </div>
         </div>
         }
     }
@@ -166,60 +342,37 @@ impl Component for Home {
             }
             Msg::NewPvpGameCustom => {
                 if self.start_roll != Some(1) {
-                    let navigator = ctx.link().navigator().unwrap();
-
-                    let game_id = nanoid!(8);
-                    let location = web_sys::window().unwrap().location();
-
-                    let host = location.host().unwrap();
-                    let protocol = location.protocol().unwrap();
-
-                    let full_url = format!("{protocol}//{host}/ws/{game_id}");
-
-                    let roll = self.start_roll;
-                    if let Some(roll) = roll {
-                        spawn_local(async move {
-                            let req = Request::post(&full_url)
-                                .header("Content-Type", "application/json")
-                                .body(serde_json::to_string(&roll).unwrap())
-                                .send()
-                                .await
-                                .unwrap();
-
-                            if req.status() == 200 {
-                                navigator.push(&Route::PvP { id: game_id });
-                            };
-                        });
+                    if let Some(roll) = self.start_roll {
+                        self.create_room(ctx, roll);
                     }
                 }
 
                 true
             }
             Msg::NewPvpGame(num) => {
-                let navigator = ctx.link().navigator().unwrap();
-
-                let game_id = nanoid!(8);
-                let location = web_sys::window().unwrap().location();
-
-                let host = location.host().unwrap();
-
-                let protocol = location.protocol().unwrap();
-
-                let full_url = format!("{protocol}//{host}/ws/{game_id}");
-
-                spawn_local(async move {
-                    let req = Request::post(&full_url)
-                        .header("Content-Type", "application/json")
-                        .body(serde_json::to_string(&num).unwrap())
-                        .send()
-                        .await
-                        .unwrap();
-
-                    if req.status() == 200 {
-                        navigator.push(&Route::PvP { id: game_id });
-                    };
+                self.create_room(ctx, num);
+                true
+            }
+            Msg::RoomCreated(Ok(game_id)) => {
+                ctx.link()
+                    .navigator()
+                    .unwrap()
+                    .push(&Route::PvP { id: game_id });
+                false
+            }
+            Msg::RoomCreated(Err(message)) => {
+                let id = self.next_toast;
+                self.next_toast += 1;
+                self.toasts
+                    .push((id, format!("\u{26A0}\u{FE0F} {message}")));
+                ctx.link().send_future(async move {
+                    sleep(TOAST_DURATION).await;
+                    Msg::DismissToast(id)
                 });
-
+                true
+            }
+            Msg::DismissToast(id) => {
+                self.toasts.retain(|(toast, _)| *toast != id);
                 true
             }
             Msg::NewPveGame(num) => {
@@ -239,16 +392,149 @@ impl Component for Home {
                 }
                 true
             }
+            Msg::Seats(seats) => {
+                self.settings.seats = seats;
+                true
+            }
+            Msg::TurnSecs(turn_secs) => {
+                self.settings.turn_secs = turn_secs;
+                true
+            }
+            Msg::OnTimeout(on_timeout) => {
+                self.settings.on_timeout = on_timeout;
+                true
+            }
+            Msg::BestOf(best_of) => {
+                self.settings.best_of = best_of;
+                true
+            }
+            Msg::Wager(wager) => {
+                self.settings.wager = wager;
+                true
+            }
+            Msg::Public(public) => {
+                self.settings.public = public;
+                true
+            }
+            Msg::Lobby => {
+                ctx.link().navigator().unwrap().push(&Route::Lobby);
+                false
+            }
+            Msg::Leaderboard => {
+                ctx.link().navigator().unwrap().push(&Route::Leaderboard);
+                false
+            }
+            Msg::MyGames => {
+                let id = "me".to_string();
+                ctx.link().navigator().unwrap().push(&Route::Player { id });
+                false
+            }
+            Msg::FindOpponent(roll) => {
+                ctx.link().navigator().unwrap().push(&Route::Queue { roll });
+                false
+            }
+            //queues for whatever is typed in the custom roll box
+            Msg::FindOpponentCustom => {
+                if let Some(roll) = self.start_roll.filter(|roll| *roll > 1) {
+                    ctx.link().navigator().unwrap().push(&Route::Queue { roll });
+                }
+                false
+            }
+            Msg::ProfileLoaded(profile) => {
+                self.profile = profile;
+                true
+            }
+            Msg::Avatar(avatar) => {
+                self.profile.avatar = avatar;
+                true
+            }
+            Msg::SaveProfile => {
+                if let Some(input) = self.name_input.cast::<HtmlInputElement>() {
+                    self.profile.name = input.value();
+                }
+                let profile = self.profile.clone();
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    let res = Request::put("/api/profile")
+                        .header("Content-Type", "application/json")
+                        .body(serde_json::to_string(&profile).unwrap())
+                        .send()
+                        .await;
+                    let saved = match res {
+                        Ok(res) if res.ok() => res.json().await.map_err(|err| err.to_string()),
+                        Ok(res) => match res.json().await {
+                            Ok(GameMessage::Error { message, .. }) => Err(message),
+                            _ => Err(format!("couldn't save, {}", res.status())),
+                        },
+                        Err(err) => Err(err.to_string()),
+                    };
+                    link.send_message(Msg::ProfileSaved(saved));
+                });
+                false
+            }
+            Msg::ProfileSaved(Ok(profile)) => {
+                self.profile = profile;
+                self.profile_msg = "\u{2705} saved".to_string();
+                true
+            }
+            Msg::ProfileSaved(Err(message)) => {
+                self.profile_msg = message;
+                true
+            }
             Msg::DoNothing => true,
         }
     }
 }
 
+impl Home {
+    //the invite link only works once the server has the room's start roll
+    fn create_room(&self, ctx: &Context<Self>, start_roll: u32) {
+        let game_id = nanoid!(8);
+        let location = web_sys::window().unwrap().location();
+
+        let host = location.host().unwrap();
+        let protocol = location.protocol().unwrap();
+
+        let full_url = format!("{protocol}//{host}/ws/{game_id}");
+
+        let new_room = NewRoom {
+            start_roll,
+            settings: self.settings.clone(),
+        };
+        let link = ctx.link().clone();
+        spawn_local(async move {
+            let res = Request::post(&full_url)
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_string(&new_room).unwrap())
+                .send()
+                .await;
+            let created = match res {
+                Ok(res) if res.ok() => Ok(game_id),
+                //a bad start roll or not enough gold for the wager
+                Ok(res) => match res.json().await {
+                    Ok(GameMessage::Error { message, .. }) => Err(message),
+                    _ => Err(format!("couldn't create the game, {}", res.status())),
+                },
+                Err(err) => Err(format!("couldn't reach the server, {err}")),
+            };
+            link.send_message(Msg::RoomCreated(created));
+        });
+    }
+}
+
 fn pvp_roll(num: u32, ctx: &yew::Context<Home>) -> Callback<MouseEvent> {
     ctx.link()
         .callback(move |_: MouseEvent| Msg::NewPvpGame(num))
 }
 
+fn checked(selected: bool) -> &'static str {
+    if selected {
+        "\u{2705} "
+    } else {
+        ""
+    }
+}
+
 fn pve_roll(num: u32, ctx: &yew::Context<Home>) -> Callback<MouseEvent> {
     ctx.link()
         .callback(move |_: MouseEvent| Msg::NewPveGame(num))
@@ -0,0 +1,130 @@
+use deathroll_protocol::{LeaderboardEntry, BRACKETS, CUSTOM_BRACKET};
+use gloo_net::http::Request;
+use yew::{platform::spawn_local, prelude::*};
+use yew_router::prelude::*;
+
+use crate::routes::Route;
+
+pub struct Leaderboard {
+    //None is the global board
+    bracket: Option<String>,
+    entries: Vec<LeaderboardEntry>,
+    loading: bool,
+}
+
+pub enum Msg {
+    Show(Option<String>),
+    Loaded(Option<String>, Vec<LeaderboardEntry>),
+    Player(String),
+    Home,
+}
+
+impl Component for Leaderboard {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(Msg::Show(None));
+
+        Self {
+            bracket: None,
+            entries: Vec::new(),
+            loading: true,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Show(bracket) => {
+                let url = match &bracket {
+                    Some(bracket) => format!("/api/leaderboard?bracket={bracket}"),
+                    None => "/api/leaderboard".to_string(),
+                };
+                self.bracket = bracket.clone();
+                self.loading = true;
+
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    let entries = match Request::get(&url).send().await {
+                        Ok(res) => res.json().await.unwrap_or_default(),
+                        Err(_) => Vec::new(),
+                    };
+                    link.send_message(Msg::Loaded(bracket, entries));
+                });
+                true
+            }
+            //a slow answer for a board that's no longer shown is dropped
+            Msg::Loaded(bracket, _) if bracket != self.bracket => false,
+            Msg::Loaded(_, entries) => {
+                self.entries = entries;
+                self.loading = false;
+                true
+            }
+            Msg::Player(id) => {
+                ctx.link().navigator().unwrap().push(&Route::Player { id });
+                false
+            }
+            Msg::Home => {
+                ctx.link().navigator().unwrap().push(&Route::Home);
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let home = ctx.link().callback(|_: MouseEvent| Msg::Home);
+        let mut boards: Vec<(String, Option<String>)> = vec![("global".to_string(), None)];
+        boards.extend(
+            BRACKETS
+                .iter()
+                .map(|roll| (roll.to_string(), Some(roll.to_string()))),
+        );
+        boards.push((CUSTOM_BRACKET.to_string(), Some(CUSTOM_BRACKET.to_string())));
+
+        html! {
+        <div>
+           <header>
+           <button onclick={home} class="title-button">{"deathroll.gg "}{"\u{1F3E0}"}</button>
+           {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
+           </header>
+           <h3>{"Leaderboard \u{1F3C5}"}</h3>
+           {
+               boards.into_iter().map(|(name, bracket)| {
+                   let selected = bracket == self.bracket;
+                   let show = ctx.link().callback(move |_: MouseEvent| Msg::Show(bracket.clone()));
+                   html! {
+                       <button onclick={show}>{if selected { "\u{2705} " } else { "" }}{name}</button>
+                   }
+               }).collect::<Html>()
+           }
+           if self.loading {
+               <div>{"loading..."}</div>
+           } else if self.entries.is_empty() {
+               <div>{"Nobody has finished a game here yet."}</div>
+           }
+           {
+               self.entries.iter().enumerate().map(|(rank, entry)| {
+                   let id = entry.player.clone();
+                   let player = ctx.link().callback(move |_: MouseEvent| Msg::Player(id.clone()));
+                   html! {
+                       <div key={entry.player.clone()}>
+                           {format!("#{} ", rank + 1)}
+                           <button onclick={player}>{short_id(&entry.player)}</button>
+                           {format!(
+                               " \u{2B50} {} ({}W {}L)",
+                               entry.rating,
+                               entry.wins,
+                               entry.games - entry.wins
+                           )}
+                       </div>
+                   }
+               }).collect::<Html>()
+           }
+        </div>
+        }
+    }
+}
+
+fn short_id(player: &str) -> &str {
+    player.get(..8).unwrap_or(player)
+}
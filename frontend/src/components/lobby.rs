@@ -0,0 +1,148 @@
+use deathroll_protocol::LobbyRoom;
+use futures::future::{abortable, AbortHandle};
+use futures::StreamExt;
+use gloo_net::websocket::{futures::WebSocket, Message};
+use yew::{platform::spawn_local, prelude::*};
+use yew_router::prelude::*;
+
+use crate::routes::Route;
+
+pub struct Lobby {
+    rooms: Vec<LobbyRoom>,
+    connected: bool,
+    //stops the subscription (and closes its socket) when the page is left
+    subscription: Option<AbortHandle>,
+}
+
+pub enum Msg {
+    Rooms(Vec<LobbyRoom>),
+    Disconnected,
+    Join(String),
+    Home,
+}
+
+impl Component for Lobby {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let location = web_sys::window().unwrap().location();
+        let host = location.host().unwrap();
+        let ws_protocol = match location.protocol().unwrap().as_str() {
+            "https:" => "wss:",
+            _ => "ws:",
+        };
+        let full_url = format!("{ws_protocol}//{host}/api/lobby/ws");
+
+        let subscription = WebSocket::open(&full_url).ok().map(|ws| {
+            let link = ctx.link().clone();
+            let (read_rooms, abort) = abortable(async move {
+                let (_write, mut read) = ws.split();
+                while let Some(Ok(msg)) = read.next().await {
+                    if let Message::Text(text) = msg {
+                        if let Ok(rooms) = serde_json::from_str(&text) {
+                            link.send_message(Msg::Rooms(rooms));
+                        }
+                    }
+                }
+                link.send_message(Msg::Disconnected);
+            });
+            spawn_local(async move {
+                let _ = read_rooms.await;
+            });
+
+            abort
+        });
+
+        Self {
+            rooms: Vec::new(),
+            connected: subscription.is_some(),
+            subscription,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Rooms(rooms) => {
+                self.rooms = rooms;
+                true
+            }
+            Msg::Disconnected => {
+                self.connected = false;
+                true
+            }
+            Msg::Join(id) => {
+                ctx.link().navigator().unwrap().push(&Route::PvP { id });
+                false
+            }
+            Msg::Home => {
+                ctx.link().navigator().unwrap().push(&Route::Home);
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let home = ctx.link().callback(|_: MouseEvent| Msg::Home);
+
+        html! {
+        <div>
+           <header>
+           <button onclick={home} class="title-button">{"deathroll.gg "}{"\u{1F3E0}"}</button>
+           {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
+           </header>
+           <h3>{"Lobby \u{1F310}"}</h3>
+           if !self.connected {
+               <div>{"\u{1f534} lobby offline, reload the page"}</div>
+           }
+           if self.rooms.is_empty() {
+               <div>{"No open games right now, create a public one from the home page."}</div>
+           }
+           {
+               self.rooms.iter().map(|room| {
+                   let id = room.game_id.clone();
+                   let join = ctx.link().callback(move |_: MouseEvent| Msg::Join(id.clone()));
+                   html! {
+                       <div key={room.game_id.clone()}>
+                           <button onclick={join}>{format!("\u{2694}\u{FE0F} {}", room.start_roll)}</button>
+                           {" "}{room_line(room)}
+                       </div>
+                   }
+               }).collect::<Html>()
+           }
+        </div>
+        }
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        if let Some(subscription) = self.subscription.take() {
+            subscription.abort();
+        }
+    }
+}
+
+fn room_line(room: &LobbyRoom) -> String {
+    let mut line = format!(
+        "\u{1F465} {}/{} \u{1F440} {} \u{23F1}\u{FE0F} {}",
+        room.joined,
+        room.seats,
+        room.spectators,
+        age(room.age_secs)
+    );
+    if let Some(games) = room.best_of {
+        line.push_str(&format!(" \u{1F3C6} best of {games}"));
+    }
+    if room.wager > 0 {
+        line.push_str(&format!(" \u{1F4B0} {} gold", room.wager));
+    }
+
+    line
+}
+
+fn age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        _ => format!("{}h ago", secs / 3600),
+    }
+}
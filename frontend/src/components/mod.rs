@@ -1,7 +1,17 @@
 //home page of website
 pub mod homepage;
+//best rated players, global and per start roll bracket
+pub mod leaderboard;
+//public rooms waiting for players
+pub mod lobby;
 //"PvE" 1v1 multiplayer 
 pub mod multiplayer;
+//match history and record of one player
+pub mod player;
+//step through a finished game from the server or a file
+pub mod replay;
+//matchmaking, waits for a stranger with the same start roll
+pub mod queue;
 //404
 pub mod notfound; 
 //single player vs computer page
@@ -1,8 +1,14 @@
 use crate::routes::Route;
+use crate::services::fairness::{short, starts_mid_game, verify_feed};
 use crate::services::feed_bus::FeedBus;
-use crate::services::websockets::{WebsocketService, WsMsg};
+use crate::services::websockets::{Backoff, WebsocketService, MAX_RETRIES};
 
-use serde::{Deserialize, Serialize};
+use deathroll_core::{fair, Seat};
+use deathroll_protocol::{
+    seat_name, FeedEvent, GameEvent, GameMessage, OnTimeout, Profile, SeriesGame, Streak,
+    TurnClock, WsMsg, PROTOCOL_VERSION,
+};
+use nanoid::nanoid;
 use std::rc::Rc;
 use std::time::Duration;
 use yew::platform::time::sleep;
@@ -16,43 +22,55 @@ use yew_router::prelude::*;
 
 use yew::{html, Callback, Component, Html, NodeRef};
 
-#[derive(Serialize, Deserialize)]
-pub enum GameMessage {
-    Spectate,
-    StartGame(String),
-    Disconnect,
-    Reconnect,
-    NoGameFound,
-    P1Join,
-    P2Join,
-    Status(String),
-    GameScore(GameScore),
-    StartRoll(String),
-    Pong,
-    GameOver(String),
-}
+const TOAST_DURATION: Duration = Duration::from_secs(4);
 
 pub enum CompMsg {
+    Join,
     Roll,
+    RequestRematch,
+    AcceptRematch,
+    Forfeit,
     HandleMsg(String),
     Copy,
     ShowRules,
+    Verify,
+    OlderFeed,
+    //the backoff delay is up, try the socket again
+    Reconnect,
+    //the retries ran out and the player asked for another round
+    Retry,
+    DismissToast(u32),
+    //one second of the turn clock, ignored unless it's from the latest clock
+    Tick(u32),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct GameScore {
-    client_feed: Vec<String>,
+#[derive(PartialEq)]
+enum Connection {
+    Online,
+    //waiting on a socket or its resync, see Backoff for how often
+    Reconnecting,
+    //out of retries
+    Offline,
 }
 
 pub struct PvPComponent {
     feed_ref: NodeRef,
-    ws: WebsocketService,
-    feed: Vec<String>,
+    //None between a socket closing and the next attempt
+    ws: Option<WebsocketService>,
+    connection: Connection,
+    backoff: Backoff,
+    //a retry is already scheduled
+    retrying: bool,
+    //commands sent while the socket was down, replayed once the feed has caught up.
+    //rolls and forfeits are never queued, the game may have moved on by then
+    pending: Vec<WsMsg>,
+    feed: Vec<FeedEvent>,
+    //the server has events from before the first one in `feed`
+    earlier: bool,
     _producer: Box<dyn Bridge<FeedBus>>,
     status_msg: String,
     spectator: bool,
     game_start: bool,
-    reconnecting: String,
     copy: bool,
     join_screen: bool,
     full_url: String,
@@ -60,9 +78,192 @@ pub struct PvPComponent {
     rules: bool,
     connected: bool,
     replay: bool,
+    outdated: bool,
+    closed: bool,
+    client_seed: String,
+    //None while spectating
+    seat: Option<Seat>,
+    fairness_msg: String,
+    //verify is paging back to the commitment of the first game in the feed
+    verifying: bool,
+    turn_clock: Option<TurnClock>,
+    clock_gen: u32,
+    rematch_requested: bool,
+    rematch_offered: bool,
+    //a finished best-of series has no rematch
+    series_over: bool,
+    //virtual gold, None until the server says
+    balance: Option<u64>,
+    //names and avatars by seat, empty until the server says
+    players: Vec<Profile>,
+    //refused commands, each one disappears after a few seconds
+    toasts: Vec<(u32, String)>,
+    next_toast: u32,
+    //(taken, total) while the room fills up
+    seats: (usize, usize),
 }
 
 impl PvPComponent {
+    fn send(&mut self, ctx: &yew::Context<Self>, msg: WsMsg) {
+        self.scroll_top();
+
+        let online = self.connection == Connection::Online;
+        let sent = match &mut self.ws {
+            Some(ws) if online => ws
+                .tx
+                .try_send(serde_json::to_string(&msg).unwrap())
+                .is_ok(),
+            _ => false,
+        };
+        if sent {
+            return;
+        }
+
+        if matches!(msg, WsMsg::Roll | WsMsg::Forfeit) {
+            self.toast(ctx, "not connected, try again once you're back online".to_string());
+        } else {
+            self.pending.push(msg);
+        }
+        //the socket looked fine but its writer is gone, start over with a fresh one
+        if online {
+            if let Some(mut ws) = self.ws.take() {
+                ws.abandon();
+            }
+            self.disconnected(ctx);
+        }
+    }
+
+    fn toast(&mut self, ctx: &yew::Context<Self>, message: String) {
+        let id = self.next_toast;
+        self.next_toast += 1;
+        self.toasts.push((id, format!("\u{26A0}\u{FE0F} {message}")));
+        ctx.link().send_future(async move {
+            sleep(TOAST_DURATION).await;
+            CompMsg::DismissToast(id)
+        });
+    }
+
+    fn connect(&mut self, ctx: &yew::Context<Self>) {
+        //the server only sends what we missed while we were gone
+        let url = match self.last_seq() {
+            0 => self.full_url.clone(),
+            seq => format!("{}&seq={}", self.full_url, seq),
+        };
+        match WebsocketService::ws_connect(&url) {
+            Ok(mut ws) => {
+                let _ = ws.tx.try_send(serde_json::to_string(&WsMsg::Ping).unwrap());
+                //our half of the seeds behind every roll, see services::fairness
+                let _ = ws.tx.try_send(
+                    serde_json::to_string(&WsMsg::ClientSeed(self.client_seed.clone())).unwrap(),
+                );
+                self.ws = Some(ws);
+            }
+            Err(err) => {
+                log::debug!("failed to open socket {}", err);
+                self.disconnected(ctx);
+            }
+        }
+    }
+
+    fn disconnected(&mut self, ctx: &yew::Context<Self>) {
+        self.ws = None;
+        //a socket that never opened closes too, one scheduled retry is enough
+        if self.retrying {
+            return;
+        }
+        match self.backoff.next_delay() {
+            Some(delay) => {
+                self.connection = Connection::Reconnecting;
+                self.retrying = true;
+                ctx.link().send_future(async move {
+                    sleep(delay).await;
+                    CompMsg::Reconnect
+                });
+            }
+            None => self.connection = Connection::Offline,
+        }
+    }
+
+    //the feed has caught up, anything clicked while offline goes out now
+    fn resynced(&mut self, ctx: &yew::Context<Self>) {
+        if self.connection == Connection::Online {
+            return;
+        }
+        self.connection = Connection::Online;
+        self.backoff.reset();
+        for msg in std::mem::take(&mut self.pending) {
+            self.send(ctx, msg);
+        }
+    }
+
+    fn connection_status(&self, ctx: &yew::Context<Self>) -> Html {
+        let retry = ctx.link().callback(move |_: MouseEvent| CompMsg::Retry);
+        let queued = match self.pending.len() {
+            0 => "".to_string(),
+            pending => format!(", {pending} queued"),
+        };
+
+        match self.connection {
+            Connection::Online => html! { <div>{"\u{1F7E2} online"}</div> },
+            //the first connect has its own screen
+            Connection::Reconnecting if self.backoff.attempt() == 0 => html! {},
+            Connection::Reconnecting => html! {
+                <div>{format!("\u{1F7E0} reconnecting {}/{}{queued}", self.backoff.attempt(), MAX_RETRIES)}</div>
+            },
+            Connection::Offline => html! {
+                <div>
+                  {format!("\u{1F534} offline{queued} ")}
+                  <button onclick={retry}>{"\u{1F504} retry"}</button>
+                </div>
+            },
+        }
+    }
+
+    //the newest feed event we have, 0 before the first one
+    fn last_seq(&self) -> u64 {
+        self.feed
+            .last()
+            .map(|feed_event| feed_event.seq)
+            .unwrap_or_default()
+    }
+
+    fn tick(&self, ctx: &yew::Context<Self>) {
+        let clock_gen = self.clock_gen;
+        ctx.link().send_future(async move {
+            sleep(Duration::from_secs(1)).await;
+            CompMsg::Tick(clock_gen)
+        });
+    }
+
+    fn toasts(&self) -> Html {
+        html! {
+          <div class="toasts">
+            {
+              self.toasts.iter().map(|(id, msg)| html! {
+                <div key={*id} class="toast">{msg}</div>
+              }).collect::<Html>()
+            }
+          </div>
+        }
+    }
+
+    fn clock_text(&self) -> String {
+        match &self.turn_clock {
+            Some(clock) => {
+                let then = match clock.on_timeout {
+                    OnTimeout::AutoRoll => "auto roll",
+                    OnTimeout::Forfeit => "forfeit",
+                };
+                format!(
+                    "\u{23F3} {} {}s then {then}",
+                    seat_name(&self.players, clock.seat),
+                    clock.remaining_ms.div_ceil(1000)
+                )
+            }
+            None => "".to_string(),
+        }
+    }
+
     fn scroll_top(&self) {
         let feed_ref = self.feed_ref.clone();
 
@@ -92,28 +293,30 @@ impl Component for PvPComponent {
 
         let game_id = url_split[3];
 
-        let full_url = format!("{}//{}/ws/{}", ws_protocol, host, game_id);
+        let full_url = format!(
+            "{}//{}/ws/{}?v={}",
+            ws_protocol, host, game_id, PROTOCOL_VERSION
+        );
 
         let cb = {
             let link = ctx.link().clone();
             move |msg| link.send_message(CompMsg::HandleMsg(msg))
         };
 
-        let game_tx: WebsocketService = WebsocketService::ws_connect(&full_url);
-        let mut game_tx_clone = game_tx.clone();
-        game_tx_clone
-            .tx
-            .try_send(serde_json::to_string(&WsMsg::Ping).unwrap())
-            .unwrap();
-        Self {
+        let client_seed = client_seed();
+        let mut component = Self {
             feed_ref: NodeRef::default(),
-            ws: game_tx,
+            ws: None,
+            connection: Connection::Reconnecting,
+            backoff: Backoff::default(),
+            retrying: false,
+            pending: Vec::new(),
             feed: Vec::new(),
+            earlier: false,
             _producer: FeedBus::bridge(Rc::new(cb)),
             status_msg: "".to_string(),
             spectator: false,
             game_start: false,
-            reconnecting: "".to_string(),
             copy: false,
             join_screen: false,
             full_url,
@@ -121,7 +324,26 @@ impl Component for PvPComponent {
             rules: false,
             connected: false,
             replay: false,
-        }
+            outdated: false,
+            closed: false,
+            client_seed,
+            seat: None,
+            fairness_msg: "".to_string(),
+            verifying: false,
+            turn_clock: None,
+            clock_gen: 0,
+            rematch_requested: false,
+            rematch_offered: false,
+            series_over: false,
+            balance: None,
+            players: Vec::new(),
+            toasts: Vec::new(),
+            next_toast: 0,
+            seats: (0, 0),
+        };
+        component.connect(ctx);
+
+        component
     }
     fn view(&self, ctx: &yew::Context<Self>) -> Html {
         self.scroll_top();
@@ -138,11 +360,46 @@ impl Component for PvPComponent {
         let location = window.location();
         let url = location.href().unwrap();
 
-        let reset_game = ctx.link().callback(move |_: MouseEvent| CompMsg::Roll);
+        let request_rematch = ctx
+            .link()
+            .callback(move |_: MouseEvent| CompMsg::RequestRematch);
+        let accept_rematch = ctx
+            .link()
+            .callback(move |_: MouseEvent| CompMsg::AcceptRematch);
+        let forfeit = ctx.link().callback(move |_: MouseEvent| CompMsg::Forfeit);
+        let join = ctx.link().callback(move |_: MouseEvent| CompMsg::Join);
 
         let rules = ctx.link().callback(move |_: MouseEvent| CompMsg::ShowRules);
 
-        if !self.connected {
+        let verify = ctx.link().callback(move |_: MouseEvent| CompMsg::Verify);
+
+        let older = ctx.link().callback(move |_: MouseEvent| CompMsg::OlderFeed);
+
+        if self.outdated {
+            html! {
+            <div>
+              <header>
+                <div>
+                <button onclick={home} class="title-button">{"deathroll.gg "}{"\u{1F3E0}"}</button>
+                  {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
+                  <h3>{"deathroll.gg has been updated \u{1F504} please reload the page"}</h3>
+                </div>
+              </header>
+              </div>
+                }
+        } else if self.closed {
+            html! {
+            <div>
+              <header>
+                <div>
+                <button onclick={home} class="title-button">{"deathroll.gg "}{"\u{1F3E0}"}</button>
+                  {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
+                  <h3>{"this game has closed \u{1F6AA} start a new one from the home page"}</h3>
+                </div>
+              </header>
+              </div>
+                }
+        } else if !self.connected {
             html! {
             <div>
               <header>
@@ -163,14 +420,14 @@ impl Component for PvPComponent {
                  }
                   {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
 
-                  <h3>{"PvP (Multiplayer 1v1) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
+                  <h3>{"PvP (Multiplayer) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
                   {"connecting... "}
                   <br/>
                   <br/>
                   <div>
                   <button onclick={close}>{" \u{274C} CANCEL "}</button>
                   <br/>
-                  {&self.reconnecting}
+                  {self.connection_status(ctx)}
                 </div>
                 </div>
               </header>
@@ -197,21 +454,21 @@ impl Component for PvPComponent {
                  }
                   {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
 
-                  <h3>{"PvP (Multiplayer 1v1) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
+                  <h3>{"PvP (Multiplayer) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
                   {"To invite someone to play, give this URL: "}
                   <br/>
                   <br/>
                   <button onclick={copy} class="url-button">{url}{" "} if !self.copy {{" \u{1F4CB}"}} else {{"\u{2705}"}}</button>
                   <br/>
                   <br/>
-                  {"Waiting for player 2 to join..."}
+                  {format!("Waiting for players to join... {}/{}", self.seats.0, self.seats.1)}
                   <br/>
                   <br/>
                   <div>
                   <button onclick={close}>{" \u{274C} CANCEL "}</button>
                   <br/>
 
-                  {&self.reconnecting}
+                  {self.connection_status(ctx)}
                 </div>
                 </div>
               </header>
@@ -239,54 +496,52 @@ impl Component for PvPComponent {
                   {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
 
                 </div>
-                <h3>{"PvP (Multiplayer 1v1) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
+                <h3>{"PvP (Multiplayer) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
                 </header>
               <div>
                 <main class="msger-feed" ref={&self.feed_ref}>
                   <div>
+                  if self.earlier {
+                    <button onclick={older} class="url-button">{"\u{2B06}\u{FE0F} earlier events"}</button>
+                  }
                   {"\u{2694}\u{FE0F} "}{&self.start_roll}
                     {
-
-                      self.feed.clone().into_iter().map(|name| {
-                        if name.contains("\u{1F9D9}\u{200D}\u{2642}\u{FE0F}") {
-                        html!{
-
-                          <div key={name.clone()}style="color:blue">
-                            {" "}{name}
-                          </div>
-                        }
-                       } else if name.contains("\u{1F9DF}") {
-                          html!{
-                            <div key={name.clone()}style="color:green">
-                              {" "}{name}
-                            </div>
-                          }
-                        } else {
-                          html!{
-                            <div key={name.clone()}style="color:black">
-                              {" "}{name}
-                            </div>
-                          }
-                        }
-                      }).collect::<Html>()
-
+                      self.feed.iter().map(|feed_event| feed_line(feed_event, &self.players)).collect::<Html>()
                     }
                   </div>
                 </main>
               </div>
+              <div>
+                <button onclick={verify} class="url-button">{"\u{1F50D} verify rolls"}</button>
+                {" "}{&self.fairness_msg}
+              </div>
+              <div>{self.clock_text()}</div>
+              if let Some(balance) = self.balance {
+                <div>{format!("\u{1F4B0} {balance} gold")}</div>
+              }
               <div>
 
                 if !self.replay  { <button onclick={on_click} class="roll-button">
                 {&self.status_msg}</button>
                 <br/>
+                <button onclick={forfeit} class="url-button">{"\u{1F3F3}\u{FE0F} forfeit"}</button>
               } else {
                 <button class="roll-button">
                 {&self.status_msg}</button>
                 <br/>
-             <button onclick={reset_game} class="roll-button">{replay}</button>
+                if self.series_over {
+                  {"\u{1F3C6} the series is over"}
+                } else if self.rematch_offered {
+                  <button onclick={accept_rematch} class="roll-button">{"\u{2705} "}{replay}</button>
+                } else if self.rematch_requested {
+                  {"\u{23F3} waiting for a rematch"}
+                } else {
+                  <button onclick={request_rematch} class="roll-button">{replay}</button>
+                }
               }
               <br/>
-                {&self.reconnecting}
+                {self.toasts()}
+                {self.connection_status(ctx)}
               </div>
             </div>
                 }
@@ -311,15 +566,16 @@ impl Component for PvPComponent {
                  }
                   {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
 
-                  <h3>{"PvP (Multiplayer 1v1) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
+                  <h3>{"PvP (Multiplayer) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
                   <div>
                   {"You have been invited to play"}
                   <br/>
                   <br/>
-                  <button onclick={on_click}>{" JOIN THE GAME "}</button>
+                  <button onclick={join}>{" JOIN THE GAME "}</button>
                   <br/>
+                  {self.toasts()}
 
-                  {&self.reconnecting}
+                  {self.connection_status(ctx)}
                 </div>
                 </div>
               </header>
@@ -346,7 +602,7 @@ impl Component for PvPComponent {
                  }
                   {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
 
-                  <h3>{"PvP (Multiplayer 1v1) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
+                  <h3>{"PvP (Multiplayer) "}{"\u{2694}\u{FE0F} "}{&self.start_roll}</h3>
                   <h3>{"The arena is full, you are spectating \u{1F50E}"}</h3>
                 </div>
               </header>
@@ -354,37 +610,25 @@ impl Component for PvPComponent {
               <div>
                 <main class="msger-feed" ref={&self.feed_ref}>
                   <div>
+                  if self.earlier {
+                    <button onclick={older} class="url-button">{"\u{2B06}\u{FE0F} earlier events"}</button>
+                  }
                   {"\u{2694}\u{FE0F} "}{&self.start_roll}
                   {
-                    self.feed.clone().into_iter().map(|name| {
-                      if name.contains("\u{1F9D9}\u{200D}\u{2642}\u{FE0F}") {
-                      html!{
-
-                        <div key={name.clone()}style="color:blue">
-                          {" "}{name}
-                        </div>
-                      }
-                     } else if name.contains("\u{1F9DF}") {
-                        html!{
-                          <div key={name.clone()}style="color:green">
-                            {" "}{name}
-                          </div>
-                        }
-                      } else {
-                        html!{
-                          <div key={name.clone()}style="color:black">
-                            {" "}{name}
-                          </div>
-                        }
-                      }
-                    }).collect::<Html>()
-                    }
+                    self.feed.iter().map(|feed_event| feed_line(feed_event, &self.players)).collect::<Html>()
+                  }
                   </div>
                 </main>
               </div>
+              <div>
+                <button onclick={verify} class="url-button">{"\u{1F50D} verify rolls"}</button>
+                {" "}{&self.fairness_msg}
+              </div>
+              <div>{self.clock_text()}</div>
 
               <br/>
-              {&self.reconnecting}
+              {self.toasts()}
+              {self.connection_status(ctx)}
             </div>
                 }
         }
@@ -392,34 +636,40 @@ impl Component for PvPComponent {
 
     fn update(&mut self, ctx: &yew::Context<Self>, msg: Self::Message) -> bool {
         match msg {
+            CompMsg::Join => {
+                self.send(ctx, WsMsg::Join);
+                true
+            }
             CompMsg::Roll => {
-                self.scroll_top();
-
-                self.ws
-                    .tx
-                    .try_send(serde_json::to_string(&WsMsg::Roll).unwrap())
-                    .unwrap();
-
-                self.replay = false;
-
+                self.send(ctx, WsMsg::Roll);
+                true
+            }
+            CompMsg::RequestRematch => {
+                self.send(ctx, WsMsg::RequestRematch);
+                true
+            }
+            CompMsg::AcceptRematch => {
+                self.send(ctx, WsMsg::AcceptRematch);
+                true
+            }
+            CompMsg::Forfeit => {
+                self.send(ctx, WsMsg::Forfeit);
                 true
             }
             CompMsg::HandleMsg(result) => {
+                let Ok(message) = serde_json::from_str::<GameMessage>(&result) else {
+                    log::debug!("dropping a frame we can't read {}", result);
+                    return false;
+                };
                 self.scroll_top();
-                self.connected = true;
-                self.reconnecting = "".to_string();
 
-                let message: GameMessage = serde_json::from_str(&result).unwrap();
+                if !matches!(message, GameMessage::Disconnect) {
+                    self.connected = true;
+                }
                 match message {
-                    GameMessage::Disconnect => {
-                        let game_tx: WebsocketService =
-                            WebsocketService::ws_connect(&self.full_url);
-                        self.reconnecting = "\u{1f534} Reconnecting...".to_string();
-                        self.ws = game_tx;
-                        spawn_local(async move {
-                            sleep(Duration::from_secs(3)).await;
-                        });
-                    }
+                    //the server hangs up after a version mismatch or once the room is closed, don't keep redialing it
+                    GameMessage::Disconnect if self.outdated || self.closed => {}
+                    GameMessage::Disconnect => self.disconnected(ctx),
                     GameMessage::Reconnect => self.game_start = true,
                     GameMessage::Spectate => self.spectator = true,
                     GameMessage::StartGame(msg) => {
@@ -431,26 +681,86 @@ impl Component for PvPComponent {
                     }
                     GameMessage::P1Join => self.join_screen = false,
                     GameMessage::P2Join => self.join_screen = true,
+                    GameMessage::Seats { joined, seats } => self.seats = (joined, seats),
                     GameMessage::Status(msg) => {
                         self.replay = false;
+                        self.rematch_requested = false;
+                        self.rematch_offered = false;
                         self.status_msg = msg;
                     }
                     GameMessage::StartRoll(roll) => self.start_roll = roll,
-                    GameMessage::GameScore(feed) => self.feed = feed.client_feed,
+                    GameMessage::FeedSnapshot { feed, earlier } => {
+                        self.feed = feed;
+                        self.earlier = earlier;
+                        self.series_over = series_over(&self.feed);
+                        self.resynced(ctx);
+                        //a page asked for on the old socket is lost with it
+                        if self.verifying {
+                            ctx.link().send_message(CompMsg::Verify);
+                        }
+                    }
+                    GameMessage::FeedDelta(events) => {
+                        //a delta can overlap what we already have, e.g. the join we reconnected with
+                        let last_seq = self.last_seq();
+                        self.feed
+                            .extend(events.into_iter().filter(|feed_event| feed_event.seq > last_seq));
+                        self.series_over = series_over(&self.feed);
+                        self.resynced(ctx);
+                    }
+                    GameMessage::OlderFeed { mut feed, earlier } => {
+                        self.earlier = earlier;
+                        feed.append(&mut self.feed);
+                        self.feed = feed;
+                        if self.verifying {
+                            ctx.link().send_message(CompMsg::Verify);
+                        }
+                    }
                     GameMessage::Pong => {
-                        let game_tx_clone = self.ws.tx.clone();
-                        spawn_local(async move {
-                            sleep(Duration::from_secs(40)).await;
-                            game_tx_clone
-                                .clone()
-                                .try_send(serde_json::to_string(&WsMsg::Ping).unwrap())
-                                .unwrap();
-                        });
+                        if let Some(ws) = &self.ws {
+                            let mut game_tx_clone = ws.tx.clone();
+                            spawn_local(async move {
+                                sleep(Duration::from_secs(40)).await;
+                                //the socket may have been replaced by then, its pings stop with it
+                                let _ = game_tx_clone
+                                    .try_send(serde_json::to_string(&WsMsg::Ping).unwrap());
+                            });
+                        }
                     }
                     GameMessage::GameOver(msg) => {
                         self.status_msg = msg;
                         self.replay = true;
                     }
+                    //the socket closes right after this, Disconnect takes care of reconnecting
+                    GameMessage::ServerRestarted => self.connection = Connection::Reconnecting,
+                    GameMessage::Balance(balance) => self.balance = Some(balance),
+                    GameMessage::Players(players) => self.players = players,
+                    GameMessage::YourSeat(seat) => self.seat = Some(seat),
+                    GameMessage::RematchRequested => self.rematch_requested = true,
+                    GameMessage::RematchOffered => self.rematch_offered = true,
+                    GameMessage::Error { code, message } => {
+                        log::debug!("server refused {:?}", code);
+                        self.toast(ctx, message);
+                    }
+                    GameMessage::VersionMismatch { server_version } => {
+                        log::debug!(
+                            "protocol mismatch, client {} server {}",
+                            PROTOCOL_VERSION,
+                            server_version
+                        );
+                        self.outdated = true;
+                    }
+                    GameMessage::RoomClosed => {
+                        self.closed = true;
+                        self.turn_clock = None;
+                    }
+                    GameMessage::TurnClock(clock) => {
+                        //a new clock replaces the old countdown
+                        self.clock_gen += 1;
+                        self.turn_clock = clock;
+                        if self.turn_clock.is_some() {
+                            self.tick(ctx);
+                        }
+                    }
                 }
 
                 true
@@ -476,16 +786,249 @@ impl Component for PvPComponent {
                 }
                 true
             }
+            CompMsg::Verify => {
+                //the first game's rolls can't be checked without its commitment, page back to it
+                self.verifying = self.earlier && starts_mid_game(&self.feed);
+                if self.verifying {
+                    self.fairness_msg = "\u{23F3} fetching earlier events".to_string();
+                    ctx.link().send_message(CompMsg::OlderFeed);
+                    return true;
+                }
+                let own = self.seat.map(|seat| (seat, self.client_seed.as_str()));
+                self.fairness_msg = verify_feed(&self.feed, own).summary();
+                true
+            }
+            CompMsg::Reconnect => {
+                self.retrying = false;
+                self.connect(ctx);
+                true
+            }
+            CompMsg::Retry => {
+                self.backoff.reset();
+                self.connection = Connection::Reconnecting;
+                self.connect(ctx);
+                true
+            }
+            CompMsg::OlderFeed => {
+                if let Some(feed_event) = self.feed.first() {
+                    let before = feed_event.seq;
+                    self.send(ctx, WsMsg::OlderFeed { before });
+                }
+                false
+            }
+            CompMsg::DismissToast(id) => {
+                self.toasts.retain(|(toast, _)| *toast != id);
+                true
+            }
+            CompMsg::Tick(clock_gen) => {
+                if clock_gen != self.clock_gen {
+                    return false;
+                }
+                if let Some(clock) = &mut self.turn_clock {
+                    clock.remaining_ms = clock.remaining_ms.saturating_sub(1000);
+                    if clock.remaining_ms > 0 {
+                        self.tick(ctx);
+                    }
+                }
+                true
+            }
         }
     }
     fn destroy(&mut self, _ctx: &yew::Context<Self>) {
-        let mut ws = self.ws.clone();
+        let Some(ws) = &mut self.ws else {
+            return;
+        };
+        let _ = ws.tx.try_send(serde_json::to_string(&WsMsg::Close).unwrap());
+        let mut ws = ws.clone();
         spawn_local(async move {
             ws.close().await;
         });
-        self.ws
-            .tx
-            .try_send(serde_json::to_string(&WsMsg::Close).unwrap())
-            .unwrap();
+    }
+}
+
+const CLIENT_SEED_KEY: &str = "deathroll-client-seed";
+
+//kept in local storage so a reload or a second tab rolls with the same seed as the games already in the feed
+fn client_seed() -> String {
+    let storage = window().and_then(|window| window.local_storage().ok().flatten());
+    let saved = storage
+        .as_ref()
+        .and_then(|storage| storage.get_item(CLIENT_SEED_KEY).ok().flatten())
+        .filter(|seed| fair::valid_client_seed(seed));
+    if let Some(seed) = saved {
+        return seed;
+    }
+
+    let seed = nanoid!(16);
+    if let Some(storage) = storage {
+        let _ = storage.set_item(CLIENT_SEED_KEY, &seed);
+    }
+    seed
+}
+
+const SEAT_COLORS: [&str; 10] = [
+    "blue", "green", "purple", "darkred", "teal", "darkorange", "olive", "crimson", "navy", "brown",
+];
+
+fn seat_color(seat: Seat) -> &'static str {
+    SEAT_COLORS.get(seat).copied().unwrap_or("black")
+}
+
+fn series_over(feed: &[FeedEvent]) -> bool {
+    feed.iter()
+        .any(|feed_event| matches!(feed_event.event, GameEvent::SeriesOver { .. }))
+}
+
+fn score_line(players: &[Profile], score: &[u32]) -> String {
+    score
+        .iter()
+        .enumerate()
+        .map(|(seat, wins)| format!("{} \u{1F3C6} {wins}", seat_name(players, seat)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+//render one structured feed event as a line in the game feed, keyed on its seq so older pages can be prepended
+pub(crate) fn feed_line(feed_event: &FeedEvent, players: &[Profile]) -> Html {
+    let (color, line) = match &feed_event.event {
+        GameEvent::SeriesOver {
+            winner,
+            score,
+            games,
+            longest_streak,
+        } => {
+            return series_summary(
+                feed_event.seq,
+                *winner,
+                score,
+                games,
+                longest_streak,
+                players,
+            )
+        }
+        GameEvent::Joined { seat } => (
+            seat_color(*seat),
+            format!("{} has joined the game", seat_name(players, *seat)),
+        ),
+        GameEvent::Left { seat } => (
+            seat_color(*seat),
+            format!("{} has left the game", seat_name(players, *seat)),
+        ),
+        GameEvent::NewGame { start_roll, .. } => {
+            ("black", format!("New Game \u{2694}\u{FE0F} {start_roll}"))
+        }
+        GameEvent::Rolled {
+            seat,
+            roll,
+            roll_between,
+            ..
+        } => (
+            seat_color(*seat),
+            format!("{} {roll} \u{1F3B2} (1-{roll_between})", seat_name(players, *seat)),
+        ),
+        GameEvent::SeedCommitted { commitment } => (
+            "gray",
+            format!("\u{1F512} seed committed {}", short(commitment)),
+        ),
+        GameEvent::SeedRevealed { server_seed, .. } => (
+            "gray",
+            format!("\u{1F511} seed revealed {}", short(server_seed)),
+        ),
+        GameEvent::Died {
+            seat,
+            roll_between,
+            score,
+            ..
+        } => (
+            seat_color(*seat),
+            format!(
+                "{} 1 \u{1F480} (1-{roll_between}) {}",
+                seat_name(players, *seat),
+                score_line(players, score)
+            ),
+        ),
+        GameEvent::Escrowed { seat, amount } => (
+            seat_color(*seat),
+            format!("{} \u{1F4B0} stakes {amount} gold", seat_name(players, *seat)),
+        ),
+        GameEvent::PaidOut { seat, amount } => (
+            seat_color(*seat),
+            format!("{} \u{1F4B0} wins the {amount} gold pot", seat_name(players, *seat)),
+        ),
+        GameEvent::TimedOut { seat } => (
+            seat_color(*seat),
+            format!("{} \u{231B} ran out of time", seat_name(players, *seat)),
+        ),
+        GameEvent::RematchRequested { seat } => (
+            seat_color(*seat),
+            format!("{} wants a rematch \u{1F504}", seat_name(players, *seat)),
+        ),
+        GameEvent::Eliminated {
+            seat, roll_between, ..
+        } => (
+            seat_color(*seat),
+            format!(
+                "{} 1 \u{1F480} (1-{roll_between}) is out",
+                seat_name(players, *seat)
+            ),
+        ),
+        GameEvent::Forfeited {
+            seat,
+            winner: Some(_),
+            score,
+        } => (
+            seat_color(*seat),
+            format!(
+                "{} \u{1F3F3}\u{FE0F} forfeit {}",
+                seat_name(players, *seat),
+                score_line(players, score)
+            ),
+        ),
+        GameEvent::Forfeited { seat, .. } => (
+            seat_color(*seat),
+            format!("{} \u{1F3F3}\u{FE0F} forfeit, is out", seat_name(players, *seat)),
+        ),
+    };
+
+    html! {
+      <div key={feed_event.seq} style={format!("color:{color}")}>
+        {" "}{line}
+      </div>
+    }
+}
+
+//the final result of a best-of series, one line per game
+fn series_summary(
+    seq: u64,
+    winner: Seat,
+    score: &[u32],
+    games: &[SeriesGame],
+    longest_streak: &Option<Streak>,
+    players: &[Profile],
+) -> Html {
+    html! {
+      <div key={seq} style={format!("color:{}", seat_color(winner))}>
+        {" "}{format!("\u{1F3C6} {} wins the series {}", seat_name(players, winner), score_line(players, score))}
+        {
+            games.iter().enumerate().map(|(game, series_game)| {
+                let rolls = series_game
+                    .rolls
+                    .iter()
+                    .map(|roll| format!("{} {}", seat_name(players, roll.seat), roll.roll))
+                    .collect::<Vec<String>>()
+                    .join(" \u{00B7} ");
+                html! {
+                    <div style="color:gray">
+                        {format!("game {}: {rolls} \u{2192} {}", game + 1, seat_name(players, series_game.winner))}
+                    </div>
+                }
+            }).collect::<Html>()
+        }
+        if let Some(streak) = longest_streak {
+            <div style="color:gray">
+                {format!("longest streak: {} survived {} in a row", seat_name(players, streak.seat), streak.games)}
+            </div>
+        }
+      </div>
     }
 }
@@ -0,0 +1,178 @@
+use deathroll_protocol::{seat_name, HistoryPage, MatchRecord, Profile};
+use gloo_net::http::Request;
+use yew::{platform::spawn_local, prelude::*};
+use yew_router::prelude::*;
+
+use crate::routes::Route;
+
+//games per page of the history
+const PAGE: usize = 20;
+
+pub struct Player {
+    //public id, or "me" for whoever is looking
+    id: String,
+    offset: usize,
+    page: Option<HistoryPage>,
+}
+
+pub enum Msg {
+    Load(usize),
+    Loaded(usize, HistoryPage),
+    Home,
+}
+
+impl Component for Player {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let location = web_sys::window().unwrap().location();
+        let url = location.href().unwrap();
+        let url_split: Vec<&str> = url.split('/').collect();
+        let id = url_split[4].trim().to_string();
+
+        ctx.link().send_message(Msg::Load(0));
+
+        Self {
+            id,
+            offset: 0,
+            page: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Load(offset) => {
+                self.offset = offset;
+                let url = format!(
+                    "/api/players/{}/history?offset={offset}&limit={PAGE}",
+                    self.id
+                );
+
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    let page = match Request::get(&url).send().await {
+                        Ok(res) => res.json().await.unwrap_or_default(),
+                        Err(_) => HistoryPage::default(),
+                    };
+                    link.send_message(Msg::Loaded(offset, page));
+                });
+                false
+            }
+            //a slow answer for a page that's no longer shown is dropped
+            Msg::Loaded(offset, _) if offset != self.offset => false,
+            Msg::Loaded(_, page) => {
+                self.page = Some(page);
+                true
+            }
+            Msg::Home => {
+                ctx.link().navigator().unwrap().push(&Route::Home);
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let home = ctx.link().callback(|_: MouseEvent| Msg::Home);
+
+        html! {
+        <div>
+           <header>
+           <button onclick={home} class="title-button">{"deathroll.gg "}{"\u{1F3E0}"}</button>
+           {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
+           </header>
+           {
+               match &self.page {
+                   Some(page) => self.history(ctx, page),
+                   None => html! { <div>{"loading..."}</div> },
+               }
+           }
+        </div>
+        }
+    }
+}
+
+impl Player {
+    fn history(&self, ctx: &Context<Self>, page: &HistoryPage) -> Html {
+        let stats = &page.stats;
+        let newer = self.offset.saturating_sub(PAGE);
+        let older = self.offset + PAGE;
+        let show_newer = ctx.link().callback(move |_: MouseEvent| Msg::Load(newer));
+        let show_older = ctx.link().callback(move |_: MouseEvent| Msg::Load(older));
+
+        html! {
+        <div>
+           <h3>{self.title(page)}</h3>
+           if stats.games == 0 {
+               <div>{"No finished games yet."}</div>
+           } else {
+               <div>{format!("\u{1F3C6} {}W \u{1F480} {}L", stats.wins, stats.games - stats.wins)}</div>
+               <div>{format!("\u{1F3B2} {:.1} rolls survived per game", stats.avg_rolls_survived)}</div>
+               if let Some(biggest_win) = stats.biggest_win {
+                   <div>{format!("\u{2694}\u{FE0F} biggest start roll won {biggest_win}")}</div>
+               }
+               <h3>{"Recent games"}</h3>
+           }
+           {
+               page.games.iter().map(|game| html! {
+                   <div key={game.id.clone()}>
+                       {self.game_line(&page.player, game)}{" "}
+                       <a href={format!("/replay/{}", game.id)}>{"\u{25B6}\u{FE0F} replay"}</a>
+                   </div>
+               }).collect::<Html>()
+           }
+           if self.offset > 0 {
+               <button onclick={show_newer}>{"\u{2B05}\u{FE0F} newer"}</button>
+           }
+           if older < page.total {
+               <button onclick={show_older}>{"older \u{27A1}\u{FE0F}"}</button>
+           }
+        </div>
+        }
+    }
+
+    //name and avatar from the player's latest game
+    fn title(&self, page: &HistoryPage) -> String {
+        let latest = page
+            .games
+            .first()
+            .and_then(|game| Some((game, game.seat_of(&page.player)?)));
+        match latest {
+            Some((game, seat)) => seat_name(&profiles(game), seat),
+            None if self.id == "me" => "You".to_string(),
+            None => format!("Player {}", short_id(&page.player)),
+        }
+    }
+
+    fn game_line(&self, player: &str, game: &MatchRecord) -> String {
+        let profiles = profiles(game);
+        let seat = game.seat_of(player);
+        let result = match seat {
+            Some(seat) if seat == game.winner => "\u{1F3C6}",
+            _ => "\u{1F480}",
+        };
+        let others = (0..game.players.len())
+            .filter(|other| Some(*other) != seat)
+            .map(|other| seat_name(&profiles, other))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let secs = game.finished_at.saturating_sub(game.started_at) / 1000;
+
+        format!(
+            "{result} \u{2694}\u{FE0F} {} vs {others} \u{00B7} {} rolls \u{00B7} {secs}s",
+            game.start_roll,
+            game.rolls.len()
+        )
+    }
+}
+
+fn short_id(player: &str) -> &str {
+    player.get(..8).unwrap_or(player)
+}
+
+fn profiles(game: &MatchRecord) -> Vec<Profile> {
+    game.players
+        .iter()
+        .map(|player| player.profile.clone())
+        .collect()
+}
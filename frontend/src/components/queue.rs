@@ -0,0 +1,187 @@
+use deathroll_protocol::{QueueMessage, QueueMsg, PROTOCOL_VERSION};
+use futures::channel::mpsc::Sender;
+use futures::future::{abortable, AbortHandle};
+use futures::{SinkExt, StreamExt};
+use gloo_net::websocket::{futures::WebSocket, Message};
+use yew::{platform::spawn_local, prelude::*};
+use yew_router::prelude::*;
+
+use crate::routes::Route;
+
+pub struct QueueComponent {
+    start_roll: u32,
+    queue_tx: Option<Sender<String>>,
+    //stops reading the queue socket once the page is left
+    subscription: Option<AbortHandle>,
+    searching: bool,
+    //(place in this bracket, players queued overall)
+    position: Option<(usize, usize)>,
+    status_msg: String,
+}
+
+pub enum Msg {
+    HandleMsg(QueueMessage),
+    Disconnected,
+    Search,
+    Cancel,
+}
+
+impl Component for QueueComponent {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let location = web_sys::window().unwrap().location();
+        let url = location.href().unwrap();
+        let url_split: Vec<&str> = url.split('/').collect();
+        let start_roll = url_split[4].trim().parse::<u32>().unwrap_or(1);
+
+        let host = location.host().unwrap();
+        let ws_protocol = match location.protocol().unwrap().as_str() {
+            "https:" => "wss:",
+            _ => "ws:",
+        };
+        let full_url = format!("{ws_protocol}//{host}/api/queue/ws?v={PROTOCOL_VERSION}");
+
+        let mut queue_tx = None;
+        let mut subscription = None;
+        if let Ok(ws) = WebSocket::open(&full_url) {
+            let (mut write, mut read) = ws.split();
+            let (tx, mut rx) = futures::channel::mpsc::channel::<String>(16);
+
+            spawn_local(async move {
+                while let Some(message) = rx.next().await {
+                    if write.send(Message::Text(message)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let link = ctx.link().clone();
+            let (read_queue, abort) = abortable(async move {
+                while let Some(Ok(msg)) = read.next().await {
+                    if let Message::Text(text) = msg {
+                        if let Ok(msg) = serde_json::from_str(&text) {
+                            link.send_message(Msg::HandleMsg(msg));
+                        }
+                    }
+                }
+                link.send_message(Msg::Disconnected);
+            });
+            spawn_local(async move {
+                let _ = read_queue.await;
+            });
+
+            queue_tx = Some(tx);
+            subscription = Some(abort);
+        }
+
+        ctx.link().send_message(Msg::Search);
+
+        Self {
+            start_roll,
+            queue_tx,
+            subscription,
+            searching: false,
+            position: None,
+            status_msg: "".to_string(),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Search => {
+                self.searching = true;
+                self.position = None;
+                self.status_msg = "".to_string();
+                self.send(QueueMsg::Join {
+                    start_roll: self.start_roll,
+                });
+                true
+            }
+            Msg::Cancel => {
+                self.send(QueueMsg::Cancel);
+                ctx.link().navigator().unwrap().push(&Route::Home);
+                false
+            }
+            Msg::Disconnected => {
+                self.searching = false;
+                self.status_msg = "\u{1f534} lost the connection to the queue".to_string();
+                true
+            }
+            Msg::HandleMsg(msg) => {
+                match msg {
+                    QueueMessage::Position { position, waiting } => {
+                        self.position = Some((position, waiting))
+                    }
+                    QueueMessage::Matched { game_id } => {
+                        ctx.link()
+                            .navigator()
+                            .unwrap()
+                            .push(&Route::PvP { id: game_id });
+                    }
+                    QueueMessage::TimedOut => {
+                        self.searching = false;
+                        self.status_msg = "\u{231B} nobody showed up, try again?".to_string();
+                    }
+                    QueueMessage::Cancelled => {
+                        self.searching = false;
+                        self.status_msg = "you are queueing in another tab".to_string();
+                    }
+                    QueueMessage::VersionMismatch { .. } => {
+                        self.searching = false;
+                        self.status_msg =
+                            "deathroll.gg has been updated, reload the page".to_string();
+                    }
+                    QueueMessage::Error { message, .. } => {
+                        self.searching = false;
+                        self.status_msg = message;
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let cancel = ctx.link().callback(|_: MouseEvent| Msg::Cancel);
+        let search = ctx.link().callback(|_: MouseEvent| Msg::Search);
+
+        html! {
+        <div>
+           <header>
+           <button onclick={cancel.clone()} class="title-button">{"deathroll.gg "}{"\u{1F3E0}"}</button>
+           </header>
+           <h3>{format!("\u{1F50D} Finding an opponent \u{2694}\u{FE0F} {}", self.start_roll)}</h3>
+           if self.searching {
+               <div>
+               {
+                   match self.position {
+                       Some((position, waiting)) => format!("#{position} in line, {waiting} queued"),
+                       None => "joining the queue...".to_string(),
+                   }
+               }
+               </div>
+               <button onclick={cancel} class="url-button">{"\u{274C} cancel"}</button>
+           } else {
+               <div>{&self.status_msg}</div>
+               <button onclick={search} class="url-button">{"\u{1F50D} search again"}</button>
+           }
+        </div>
+        }
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        if let Some(subscription) = self.subscription.take() {
+            subscription.abort();
+        }
+    }
+}
+
+impl QueueComponent {
+    fn send(&mut self, msg: QueueMsg) {
+        if let Some(queue_tx) = self.queue_tx.as_mut() {
+            let _ = queue_tx.try_send(serde_json::to_string(&msg).unwrap());
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use deathroll_protocol::{seat_name, FeedEvent, GameEvent, Profile, Replay, REPLAY_VERSION};
+use gloo_file::{futures::read_as_text, File};
+use gloo_net::http::Request;
+use std::time::Duration;
+use web_sys::HtmlInputElement;
+use yew::platform::time::sleep;
+use yew::{platform::spawn_local, prelude::*};
+use yew_router::prelude::*;
+
+use crate::components::multiplayer::feed_line;
+use crate::routes::Route;
+use crate::services::fairness::verify_feed;
+
+//(ms between rolls while playing, label)
+const SPEEDS: [(u64, &str); 4] = [(2000, "0.5x"), (1000, "1x"), (500, "2x"), (250, "4x")];
+
+pub struct ReplayViewer {
+    //None when the page was opened to look at a file
+    id: Option<String>,
+    replay: Option<Replay>,
+    //rolls shown so far
+    step: usize,
+    playing: bool,
+    speed_ms: u64,
+    //bumped on every play, pause and step so an old tick can't move the replay
+    play_gen: u32,
+    file_input: NodeRef,
+    status_msg: String,
+}
+
+pub enum Msg {
+    Loaded(Result<Replay, String>),
+    Upload,
+    Step(usize),
+    Play,
+    Pause,
+    Speed(u64),
+    Tick(u32),
+    Home,
+}
+
+impl Component for ReplayViewer {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let location = web_sys::window().unwrap().location();
+        let url = location.href().unwrap();
+        let url_split: Vec<&str> = url.split('/').collect();
+        let id = url_split
+            .get(4)
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty());
+
+        if let Some(id) = id.clone() {
+            let link = ctx.link().clone();
+            spawn_local(async move {
+                let replay = match Request::get(&format!("/api/replays/{id}")).send().await {
+                    Ok(res) if res.ok() => res.json().await.map_err(|err| err.to_string()),
+                    Ok(_) => Err("replay not found, you can still open a file".to_string()),
+                    Err(err) => Err(err.to_string()),
+                };
+                link.send_message(Msg::Loaded(replay));
+            });
+        }
+
+        Self {
+            id,
+            replay: None,
+            step: 0,
+            playing: false,
+            speed_ms: 1000,
+            play_gen: 0,
+            file_input: NodeRef::default(),
+            status_msg: "".to_string(),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Loaded(Ok(replay)) if replay.version != REPLAY_VERSION => {
+                self.status_msg = format!("replay version {} isn't supported", replay.version);
+                true
+            }
+            Msg::Loaded(Ok(mut replay)) => {
+                //replays saved before feed events had a seq, the lines are keyed on it
+                if replay.events.iter().any(|feed_event| feed_event.seq == 0) {
+                    for (index, feed_event) in replay.events.iter_mut().enumerate() {
+                        feed_event.seq = index as u64 + 1;
+                    }
+                }
+                self.replay = Some(replay);
+                self.status_msg = "".to_string();
+                self.step = 0;
+                self.pause();
+                true
+            }
+            Msg::Loaded(Err(message)) => {
+                self.status_msg = message;
+                true
+            }
+            Msg::Upload => {
+                let file = self
+                    .file_input
+                    .cast::<HtmlInputElement>()
+                    .and_then(|input| input.files())
+                    .and_then(|files| files.get(0));
+                if let Some(file) = file {
+                    //a file is only ever viewed, it never goes to the server
+                    self.id = None;
+                    let link = ctx.link().clone();
+                    spawn_local(async move {
+                        let replay = match read_as_text(&File::from(file)).await {
+                            Ok(text) => serde_json::from_str(&text)
+                                .map_err(|_| "that file isn't a deathroll replay".to_string()),
+                            Err(err) => Err(err.to_string()),
+                        };
+                        link.send_message(Msg::Loaded(replay));
+                    });
+                }
+                false
+            }
+            Msg::Step(step) => {
+                self.step = step.min(self.rolls().len());
+                self.pause();
+                true
+            }
+            Msg::Play => {
+                if self.step >= self.rolls().len() {
+                    self.step = 0;
+                }
+                self.playing = true;
+                self.play_gen += 1;
+                self.tick(ctx);
+                true
+            }
+            Msg::Pause => {
+                self.pause();
+                true
+            }
+            Msg::Speed(speed_ms) => {
+                self.speed_ms = speed_ms;
+                true
+            }
+            Msg::Tick(play_gen) => {
+                if play_gen != self.play_gen || !self.playing {
+                    return false;
+                }
+                self.step += 1;
+                if self.step >= self.rolls().len() {
+                    self.pause();
+                } else {
+                    self.tick(ctx);
+                }
+                true
+            }
+            Msg::Home => {
+                ctx.link().navigator().unwrap().push(&Route::Home);
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let home = ctx.link().callback(|_: MouseEvent| Msg::Home);
+        let upload = ctx.link().callback(|_: Event| Msg::Upload);
+
+        html! {
+        <div>
+           <header>
+           <button onclick={home} class="title-button">{"deathroll.gg "}{"\u{1F3E0}"}</button>
+           {" "}<a href="https://github.com/stum0/deathroll"><i class="fab fa-github-square" style="font-size:25px"></i></a>
+           </header>
+           <h3>{"Replay \u{1F3AC}"}</h3>
+           if let Some(replay) = &self.replay {
+               {self.player(ctx, replay)}
+           } else if self.id.is_some() && self.status_msg.is_empty() {
+               <div>{"loading..."}</div>
+           }
+           <div>{&self.status_msg}</div>
+           <div>
+               {"\u{1F4C2} open a replay file "}
+               <input ref={&self.file_input} type="file" accept=".json,application/json" onchange={upload}/>
+           </div>
+        </div>
+        }
+    }
+}
+
+impl ReplayViewer {
+    fn tick(&self, ctx: &Context<Self>) {
+        let play_gen = self.play_gen;
+        let speed_ms = self.speed_ms;
+        ctx.link().send_future(async move {
+            sleep(Duration::from_millis(speed_ms)).await;
+            Msg::Tick(play_gen)
+        });
+    }
+
+    fn pause(&mut self) {
+        self.playing = false;
+        self.play_gen += 1;
+    }
+
+    //positions of the roll events, one step each
+    fn rolls(&self) -> Vec<usize> {
+        let Some(replay) = &self.replay else {
+            return Vec::new();
+        };
+
+        replay
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, feed_event)| is_roll(feed_event))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn player(&self, ctx: &Context<Self>, replay: &Replay) -> Html {
+        let game = &replay.game;
+        let players: Vec<Profile> = game
+            .players
+            .iter()
+            .map(|player| player.profile.clone())
+            .collect();
+        let names = (0..players.len())
+            .map(|seat| seat_name(&players, seat))
+            .collect::<Vec<String>>()
+            .join(" vs ");
+
+        let rolls = self.rolls();
+        let total = rolls.len();
+        let finished = self.step >= total;
+        //everything up to the current roll, the seed reveal only once the game is over
+        let shown = match self.step {
+            _ if finished => replay.events.len(),
+            0 => rolls.first().copied().unwrap_or_default(),
+            step => rolls[step - 1] + 1,
+        };
+
+        let step = self.step;
+        let first = ctx.link().callback(|_: MouseEvent| Msg::Step(0));
+        let back = ctx
+            .link()
+            .callback(move |_: MouseEvent| Msg::Step(step.saturating_sub(1)));
+        let forward = ctx
+            .link()
+            .callback(move |_: MouseEvent| Msg::Step(step + 1));
+        let last = ctx.link().callback(move |_: MouseEvent| Msg::Step(total));
+        let play = ctx.link().callback(|_: MouseEvent| Msg::Play);
+        let pause = ctx.link().callback(|_: MouseEvent| Msg::Pause);
+
+        html! {
+        <div>
+           <div>{format!("{names} \u{2694}\u{FE0F} {}", game.start_roll)}</div>
+           <main class="msger-feed">
+             <div>
+             {
+                 replay.events[..shown].iter().map(|feed_event| feed_line(feed_event, &players)).collect::<Html>()
+             }
+             </div>
+           </main>
+           <div>
+               <button onclick={first}>{"\u{23EE}\u{FE0F}"}</button>
+               <button onclick={back}>{"\u{25C0}\u{FE0F}"}</button>
+               {format!(" {step}/{total} ")}
+               <button onclick={forward}>{"\u{25B6}\u{FE0F}"}</button>
+               <button onclick={last}>{"\u{23ED}\u{FE0F}"}</button>
+               if self.playing {
+                   <button onclick={pause}>{"\u{23F8}\u{FE0F} pause"}</button>
+               } else {
+                   <button onclick={play}>{"\u{1F3AC} play"}</button>
+               }
+           </div>
+           <div>
+               {"speed "}
+               {
+                   SPEEDS.iter().map(|(speed_ms, label)| {
+                       let speed_ms = *speed_ms;
+                       html! {
+                           <button onclick={ctx.link().callback(move |_: MouseEvent| Msg::Speed(speed_ms))}>
+                               {if self.speed_ms == speed_ms { "\u{2705} " } else { "" }}{*label}
+                           </button>
+                       }
+                   }).collect::<Html>()
+               }
+           </div>
+           if finished {
+               <div>{format!("\u{1F3C6} {} wins", seat_name(&players, game.winner))}</div>
+               <div>{fairness(replay)}</div>
+           }
+           if let Some(id) = &self.id {
+               <a href={format!("/api/replays/{id}")} download={format!("deathroll-{id}.json")}>
+                   {"\u{1F4BE} export"}
+               </a>
+           }
+        </div>
+        }
+    }
+}
+
+//games imported from a chat log were rolled by the WoW client, there are no seeds to check
+fn fairness(replay: &Replay) -> String {
+    let seeded = replay
+        .events
+        .iter()
+        .any(|feed_event| matches!(feed_event.event, GameEvent::SeedCommitted { .. }));
+    if seeded {
+        verify_feed(&replay.events, None).summary()
+    } else {
+        "\u{1F4DC} imported from a chat log, no seeds to verify".to_string()
+    }
+}
+
+fn is_roll(feed_event: &FeedEvent) -> bool {
+    matches!(
+        feed_event.event,
+        GameEvent::Rolled { .. }
+            | GameEvent::Eliminated { .. }
+            | GameEvent::Died { .. }
+            | GameEvent::TimedOut { .. }
+            | GameEvent::Forfeited { .. }
+    )
+}
@@ -1,7 +1,7 @@
 use yew::{html, Html};
 use yew_router::prelude::*;
 
-use crate::components::{homepage::Home, cpu::PvEComponent, multiplayer::PvPComponent, notfound::Notfound};
+use crate::components::{homepage::Home, cpu::PvEComponent, leaderboard::Leaderboard, lobby::Lobby, multiplayer::PvPComponent, notfound::Notfound, player::Player, replay::ReplayViewer, queue::QueueComponent};
 
 
 
@@ -11,6 +11,18 @@ pub enum Route {
     Home,
     #[at("/pve/:roll")]
     PvE { roll: u32},
+    #[at("/lobby")]
+    Lobby,
+    #[at("/leaderboard")]
+    Leaderboard,
+    #[at("/queue/:roll")]
+    Queue { roll: u32 },
+    #[at("/p/:id")]
+    Player { id: String },
+    #[at("/replay")]
+    ReplayFile,
+    #[at("/replay/:id")]
+    Replay { id: String },
     #[at("/:id")]
     PvP { id: String },
     #[not_found]
@@ -22,6 +34,12 @@ pub fn switch(routes: Route) -> Html {
     match routes {
         Route::Home => html! {<Home />},
         Route::PvE { roll: _} => html! {<PvEComponent />},
+        Route::Lobby => html! {<Lobby />},
+        Route::Leaderboard => html! {<Leaderboard />},
+        Route::Queue { roll: _ } => html! {<QueueComponent />},
+        Route::Player { id: _ } => html! {<Player />},
+        Route::ReplayFile => html! {<ReplayViewer />},
+        Route::Replay { id: _ } => html! {<ReplayViewer />},
         Route::PvP { id: _ } => html! {<PvPComponent />},
         Route::NotFound => html! {<Notfound />},
     }
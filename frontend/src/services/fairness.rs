@@ -0,0 +1,239 @@
+use deathroll_core::{fair, Seat};
+use deathroll_protocol::{FeedEvent, GameEvent};
+
+pub struct Verification {
+    pub verified: usize,
+    //rolls from the game in progress, their seed hasn't been revealed yet
+    pub pending: usize,
+    pub failed: Vec<String>,
+}
+
+impl Verification {
+    pub fn summary(&self) -> String {
+        if self.failed.is_empty() {
+            format!(
+                "\u{2705} {} rolls verified, {} waiting for the seed reveal",
+                self.verified, self.pending
+            )
+        } else {
+            format!("\u{274C} {}", self.failed.join(", "))
+        }
+    }
+}
+
+//recompute every roll in the feed from the revealed seeds, `own` is our seat and the seed
+//we sent when we're playing, it has to be the one the server rolled with
+pub fn verify_feed(feed: &[FeedEvent], own: Option<(Seat, &str)>) -> Verification {
+    let mut verification = Verification {
+        verified: 0,
+        pending: 0,
+        failed: Vec::new(),
+    };
+    let mut commitment: Option<&str> = None;
+    //(roll, roll_between, nonce) since the last commitment
+    let mut rolls: Vec<(u32, u32, u64)> = Vec::new();
+    //every roll under a commitment takes the next nonce, a gap is a dropped roll and a repeat a replayed one
+    let mut next_nonce = 0;
+
+    for feed_event in feed {
+        match &feed_event.event {
+            GameEvent::SeedCommitted { commitment: next } => {
+                commitment = Some(next.as_str());
+                rolls.clear();
+                next_nonce = 0;
+            }
+            GameEvent::Rolled {
+                roll,
+                roll_between,
+                nonce,
+                ..
+            } => {
+                check_nonce(&mut verification, &mut next_nonce, *nonce);
+                rolls.push((*roll, *roll_between, *nonce));
+            }
+            GameEvent::Eliminated {
+                roll_between,
+                nonce,
+                ..
+            }
+            | GameEvent::Died {
+                roll_between,
+                nonce,
+                ..
+            } => {
+                check_nonce(&mut verification, &mut next_nonce, *nonce);
+                rolls.push((1, *roll_between, *nonce));
+            }
+            GameEvent::SeedRevealed {
+                server_seed,
+                client_seeds,
+            } => {
+                if commitment != Some(fair::commit(server_seed).as_str()) {
+                    verification.failed.push(format!(
+                        "seed {} does not match its commitment",
+                        short(server_seed)
+                    ));
+                }
+                if !client_seeds
+                    .iter()
+                    .all(|seed| fair::valid_client_seed(seed))
+                {
+                    verification.failed.push(format!(
+                        "seed {} was rolled with a malformed client seed",
+                        short(server_seed)
+                    ));
+                }
+                if let Some((seat, seed)) = own {
+                    if client_seeds.get(seat).map(String::as_str) != Some(seed) {
+                        verification.failed.push(format!(
+                            "seed {} was rolled without your client seed",
+                            short(server_seed)
+                        ));
+                    }
+                }
+                for (roll, roll_between, nonce) in rolls.drain(..) {
+                    if fair::verify_roll(server_seed, client_seeds, nonce, roll_between, roll) {
+                        verification.verified += 1;
+                    } else {
+                        verification
+                            .failed
+                            .push(format!("roll {roll} (1-{roll_between}) #{nonce}"));
+                    }
+                }
+                commitment = None;
+            }
+            _ => {}
+        }
+    }
+    verification.pending = rolls.len();
+
+    verification
+}
+
+//the feed was paged in partway through a game, its commitment is on an older page
+pub fn starts_mid_game(feed: &[FeedEvent]) -> bool {
+    feed.iter()
+        .find_map(|feed_event| match feed_event.event {
+            GameEvent::SeedCommitted { .. } => Some(false),
+            GameEvent::Rolled { .. }
+            | GameEvent::Eliminated { .. }
+            | GameEvent::Died { .. }
+            | GameEvent::SeedRevealed { .. } => Some(true),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn check_nonce(verification: &mut Verification, next_nonce: &mut u64, nonce: u64) {
+    if nonce != *next_nonce {
+        verification
+            .failed
+            .push(format!("roll #{nonce} came where #{next_nonce} was due"));
+    }
+    *next_nonce = nonce + 1;
+}
+
+pub fn short(seed: &str) -> &str {
+    seed.get(..8).unwrap_or(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER_SEED: &str = "server-seed";
+
+    fn event(event: GameEvent) -> FeedEvent {
+        FeedEvent {
+            seq: 0,
+            at: 0,
+            event,
+        }
+    }
+
+    //a committed game with a roll for every nonce, revealed with `client_seeds`
+    fn game(nonces: &[u64], client_seeds: &[&str]) -> Vec<FeedEvent> {
+        let client_seeds: Vec<String> = client_seeds.iter().map(|seed| seed.to_string()).collect();
+        let mut feed = vec![event(GameEvent::SeedCommitted {
+            commitment: fair::commit(SERVER_SEED),
+        })];
+        for (turn, nonce) in nonces.iter().enumerate() {
+            feed.push(event(GameEvent::Rolled {
+                seat: turn % 2,
+                roll: fair::roll(SERVER_SEED, &client_seeds, *nonce, 1000),
+                roll_between: 1000,
+                nonce: *nonce,
+            }));
+        }
+        feed.push(event(GameEvent::SeedRevealed {
+            server_seed: SERVER_SEED.to_string(),
+            client_seeds,
+        }));
+        feed
+    }
+
+    #[test]
+    fn a_fair_game_verifies() {
+        let verification = verify_feed(&game(&[0, 1, 2], &["alice", "bob"]), Some((1, "bob")));
+        assert_eq!(verification.failed, Vec::<String>::new());
+        assert_eq!(verification.verified, 3);
+    }
+
+    #[test]
+    fn rolls_before_the_reveal_are_pending() {
+        let mut feed = game(&[0, 1], &["alice", "bob"]);
+        feed.pop();
+        let verification = verify_feed(&feed, None);
+        assert!(verification.failed.is_empty());
+        assert_eq!(verification.pending, 2);
+    }
+
+    #[test]
+    fn a_dropped_roll_fails() {
+        let verification = verify_feed(&game(&[0, 2, 3], &["alice", "bob"]), None);
+        assert_eq!(verification.failed, ["roll #2 came where #1 was due"]);
+    }
+
+    #[test]
+    fn a_replayed_nonce_fails() {
+        let verification = verify_feed(&game(&[0, 1, 1], &["alice", "bob"]), None);
+        assert_eq!(verification.failed, ["roll #1 came where #2 was due"]);
+    }
+
+    #[test]
+    fn a_swapped_client_seed_fails() {
+        let verification = verify_feed(&game(&[0, 1], &["alice", "server"]), Some((1, "bob")));
+        assert_eq!(verification.failed.len(), 1);
+        assert!(verification.failed[0].contains("without your client seed"));
+
+        //spectators have no seed of their own to look for
+        assert!(verify_feed(&game(&[0, 1], &["alice", "server"]), None)
+            .failed
+            .is_empty());
+    }
+
+    #[test]
+    fn a_client_seed_with_a_separator_fails() {
+        let verification = verify_feed(&game(&[0], &["a:b", "c"]), None);
+        assert!(verification.failed[0].contains("malformed client seed"));
+    }
+
+    #[test]
+    fn a_page_without_its_commitment_starts_mid_game() {
+        let feed = game(&[0, 1, 2], &["alice", "bob"]);
+        assert!(!starts_mid_game(&feed));
+        assert!(starts_mid_game(&feed[2..]));
+        assert!(starts_mid_game(&feed[4..]));
+        assert!(!starts_mid_game(&[]));
+    }
+
+    #[test]
+    fn a_seed_that_doesnt_match_its_commitment_fails() {
+        let mut feed = game(&[0], &["alice", "bob"]);
+        feed[0] = event(GameEvent::SeedCommitted {
+            commitment: fair::commit("other-seed"),
+        });
+        let verification = verify_feed(&feed, None);
+        assert!(verification.failed[0].contains("does not match its commitment"));
+    }
+}
@@ -1,2 +1,3 @@
+pub mod fairness;
 pub mod feed_bus;
 pub mod websockets;
\ No newline at end of file
@@ -1,69 +1,165 @@
 use futures::channel::mpsc::Sender;
 use futures::{SinkExt, StreamExt};
-use gloo_net::websocket::WebSocketError;
 use gloo_net::websocket::{futures::WebSocket, Message};
+use rand::Rng;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
 
 use yew::platform::spawn_local;
 
 use yew_agent::Dispatched;
 
-use crate::components::multiplayer::GameMessage;
 use crate::services::feed_bus::{FeedBus, Request};
+use deathroll_protocol::GameMessage;
 
-#[derive(serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub enum WsMsg {
-    Ping,
-    Close,
-    Roll,
-}
+//reconnect attempts before giving up until the player asks again
+pub const MAX_RETRIES: u32 = 8;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(15);
 
 #[derive(Clone, Debug)]
 pub struct WebsocketService {
     pub tx: Sender<String>,
+    //set once we've given up on the socket, it goes quietly instead of reporting a Disconnect
+    abandoned: Rc<Cell<bool>>,
 }
 impl WebsocketService {
-    pub fn ws_connect(full_url: &str) -> Self {
+    //messages sent before the socket opens wait for it, Disconnect goes to the bus once it closes
+    pub fn ws_connect(full_url: &str) -> Result<Self, String> {
         let mut event_bus = FeedBus::dispatcher();
-        let ws = WebSocket::open(full_url).unwrap();
+        let ws = WebSocket::open(full_url).map_err(|err| err.to_string())?;
 
         let (game_tx, mut game_rx) = futures::channel::mpsc::channel::<String>(1000);
 
         let (mut write, mut read) = ws.split();
+        let abandoned = Rc::new(Cell::new(false));
+        let read_abandoned = abandoned.clone();
 
         spawn_local(async move {
             while let Some(message) = game_rx.next().await {
                 log::debug!("{:?}", message);
-                write.send(Message::Text(message)).await.unwrap();
+                if write.send(Message::Text(message)).await.is_err() {
+                    break;
+                }
             }
         });
 
         spawn_local(async move {
             while let Some(result) = read.next().await {
-                log::debug!("{:?}", result);
+                if read_abandoned.get() {
+                    return;
+                }
                 match result {
                     Ok(Message::Text(msg)) => {
                         event_bus.send(Request::EventBusMsg(msg));
                     }
                     Ok(Message::Bytes(_)) => {}
-
-                    Err(e) => match e {
-                        WebSocketError::ConnectionError => {}
-                        WebSocketError::ConnectionClose(_) => {
-                            event_bus.send(Request::EventBusMsg(
-                                serde_json::to_string(&GameMessage::Disconnect).unwrap(),
-                            ));
-                        }
-                        WebSocketError::MessageSendError(_) => {}
-                        _ => {}
-                    },
+                    //a close or a failed connect, either way this socket is done
+                    Err(_) => break,
                 }
             }
+            if !read_abandoned.get() {
+                event_bus.send(Request::EventBusMsg(
+                    serde_json::to_string(&GameMessage::Disconnect).unwrap(),
+                ));
+            }
         });
 
-        Self { tx: game_tx }
+        Ok(Self {
+            tx: game_tx,
+            abandoned,
+        })
+    }
+    //a replacement is on its way, a late Disconnect from this one would knock it out
+    pub fn abandon(&mut self) {
+        self.abandoned.set(true);
+        self.tx.close_channel();
     }
     pub async fn close(&mut self) {
-        self.tx.close().await.unwrap();
+        let _ = self.tx.close().await;
+    }
+}
+
+//jittered exponential backoff, so a restarted server isn't hit by every client at once
+#[derive(Debug, Default)]
+pub struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    //None once the retries are used up
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.attempt >= MAX_RETRIES {
+            return None;
+        }
+        let ceiling = BASE_DELAY.saturating_mul(1 << self.attempt).min(MAX_DELAY);
+        self.attempt += 1;
+
+        //somewhere between half and all of the ceiling
+        let ceiling_ms = ceiling.as_millis() as u64;
+        let jitter = rand::thread_rng().gen_range(0..=ceiling_ms / 2);
+        Some(Duration::from_millis(ceiling_ms / 2 + jitter))
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the ceiling of every attempt, doubling from half a second up to the cap
+    const CEILINGS_MS: [u64; MAX_RETRIES as usize] =
+        [500, 1000, 2000, 4000, 8000, 15000, 15000, 15000];
+
+    #[test]
+    fn delays_double_up_to_the_cap_within_the_jitter() {
+        for _ in 0..100 {
+            let mut backoff = Backoff::default();
+            for ceiling_ms in CEILINGS_MS {
+                let delay = backoff.next_delay().unwrap().as_millis() as u64;
+                assert!(
+                    (ceiling_ms / 2..=ceiling_ms).contains(&delay),
+                    "{delay}ms outside {ceiling_ms}ms"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn delays_are_jittered() {
+        let delays: Vec<Duration> = (0..100)
+            .map(|_| Backoff::default().next_delay().unwrap())
+            .collect();
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+
+    #[test]
+    fn retries_run_out() {
+        let mut backoff = Backoff::default();
+        for _ in 0..MAX_RETRIES {
+            assert!(backoff.next_delay().is_some());
+        }
+        assert_eq!(backoff.attempt(), MAX_RETRIES);
+        assert_eq!(backoff.next_delay(), None);
+        assert_eq!(backoff.attempt(), MAX_RETRIES);
+    }
+
+    #[test]
+    fn reset_starts_over_from_the_base_delay() {
+        let mut backoff = Backoff::default();
+        while backoff.next_delay().is_some() {}
+        backoff.reset();
+
+        assert_eq!(backoff.attempt(), 0);
+        assert!(backoff.next_delay().unwrap() <= BASE_DELAY);
+        assert_eq!(backoff.attempt(), 1);
     }
 }
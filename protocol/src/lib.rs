@@ -0,0 +1,542 @@
+//! Wire types sent over the `/ws/:id` websocket, shared by server and frontend.
+
+use deathroll_core::{RuleError, Seat, MIN_SEATS};
+use serde::{Deserialize, Serialize};
+
+/// Bump this whenever `GameMessage` or `WsMsg` change shape, clients built
+/// against another version are asked to reload instead of failing to parse.
+pub const PROTOCOL_VERSION: u32 = 18;
+
+//one per seat, up to deathroll_core::MAX_SEATS
+pub const AVATARS: [&str; 10] = [
+    "\u{1F9D9}\u{200D}\u{2642}\u{FE0F}",
+    "\u{1F9DF}",
+    "\u{1F9DD}",
+    "\u{1F9DB}",
+    "\u{1F9DC}",
+    "\u{1F9DA}",
+    "\u{1F9B9}",
+    "\u{1F977}",
+    "\u{1F9DE}",
+    "\u{1F93A}",
+];
+
+pub fn avatar(seat: Seat) -> &'static str {
+    AVATARS.get(seat).copied().unwrap_or("\u{2753}")
+}
+
+//what a player can pick for their profile, the seat avatars and a few more
+pub const AVATAR_CHOICES: [&str; 20] = [
+    "\u{1F9D9}\u{200D}\u{2642}\u{FE0F}",
+    "\u{1F9DF}",
+    "\u{1F9DD}",
+    "\u{1F9DB}",
+    "\u{1F9DC}",
+    "\u{1F9DA}",
+    "\u{1F9B9}",
+    "\u{1F977}",
+    "\u{1F9DE}",
+    "\u{1F93A}",
+    "\u{1F480}",
+    "\u{1F47B}",
+    "\u{1F47D}",
+    "\u{1F916}",
+    "\u{1F409}",
+    "\u{1F43A}",
+    "\u{1F981}",
+    "\u{1F438}",
+    "\u{1F3B2}",
+    "\u{1F451}",
+];
+pub const MIN_NAME_CHARS: usize = 2;
+pub const MAX_NAME_CHARS: usize = 20;
+
+//display name and avatar, GET/PUT /api/profile for your own
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub avatar: String,
+}
+
+impl Profile {
+    //what a seat shows until its player sets a profile
+    pub fn for_seat(seat: Seat) -> Self {
+        Self {
+            name: String::new(),
+            avatar: avatar(seat).to_string(),
+        }
+    }
+}
+
+//how `seat` is shown in status lines and the feed
+pub fn seat_name(players: &[Profile], seat: Seat) -> String {
+    match players.get(seat) {
+        Some(player) if !player.name.is_empty() => format!("{} {}", player.avatar, player.name),
+        Some(player) => player.avatar.clone(),
+        None => avatar(seat).to_string(),
+    }
+}
+
+//query string sent with the websocket upgrade, e.g. /ws/:id?v=1&seq=42
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Handshake {
+    pub v: Option<u32>,
+    //last feed event the client has, set when it reconnects to pick up where it left off
+    pub seq: Option<u64>,
+}
+
+impl Handshake {
+    pub fn is_supported(&self) -> bool {
+        self.v == Some(PROTOCOL_VERSION)
+    }
+}
+
+//turn clock choices offered when creating a room
+pub const TURN_SECS: [u32; 3] = [15, 30, 60];
+pub const MIN_TURN_SECS: u32 = 5;
+pub const MAX_TURN_SECS: u32 = 300;
+//series lengths offered when creating a room
+pub const BEST_OF: [u32; 3] = [3, 5, 7];
+//gold stakes offered when creating a room, every seat puts this much in the pot
+pub const WAGERS: [u64; 3] = [10, 100, 500];
+//start rolls strangers can queue for, any other start roll is a custom bracket
+pub const BRACKETS: [u32; 3] = [100, 10_000, 1_000_000];
+//how long a player waits in the queue before giving up
+pub const QUEUE_TIMEOUT_SECS: u64 = 300;
+//leaderboard for every start roll outside of BRACKETS
+pub const CUSTOM_BRACKET: &str = "custom";
+
+//which bracket leaderboard a game with this start roll counts on
+pub fn bracket_key(start_roll: u32) -> String {
+    if BRACKETS.contains(&start_roll) {
+        start_roll.to_string()
+    } else {
+        CUSTOM_BRACKET.to_string()
+    }
+}
+
+//what happens to a player who lets their turn clock run out
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnTimeout {
+    #[default]
+    AutoRoll,
+    Forfeit,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct RoomSettings {
+    //the game starts once this many players have joined
+    pub seats: usize,
+    //seconds each player gets per turn, no clock when None
+    pub turn_secs: Option<u32>,
+    pub on_timeout: OnTimeout,
+    //play a best-of series instead of rematching forever
+    pub best_of: Option<u32>,
+    //gold each seat escrows per game, 0 plays for nothing
+    pub wager: u64,
+    //listed in the lobby while it has a free seat
+    pub public: bool,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        Self {
+            seats: MIN_SEATS,
+            turn_secs: None,
+            on_timeout: OnTimeout::default(),
+            best_of: None,
+            wager: 0,
+            public: false,
+        }
+    }
+}
+
+//body of POST /ws/:id, older clients post the bare start roll instead
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NewRoom {
+    pub start_roll: u32,
+    #[serde(default)]
+    pub settings: RoomSettings,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TurnClock {
+    pub seat: Seat,
+    //time left when the message was sent, clients count down from here
+    pub remaining_ms: u64,
+    pub on_timeout: OnTimeout,
+}
+
+//an open public room, GET /api/lobby lists them and /api/lobby/ws pushes the list
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LobbyRoom {
+    pub game_id: String,
+    pub start_roll: u32,
+    pub joined: usize,
+    pub seats: usize,
+    pub spectators: usize,
+    pub age_secs: u64,
+    pub best_of: Option<u32>,
+    pub wager: u64,
+}
+
+//GET /api/leaderboard?bracket=<100|10000|1000000|custom>, no bracket is the global board
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LeaderboardEntry {
+    //public id, never the player's cookie
+    pub player: String,
+    pub rating: u32,
+    pub games: u32,
+    pub wins: u32,
+}
+
+//one finished game, its id is also the id of its replay
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MatchRecord {
+    pub id: String,
+    pub game_id: String,
+    //in seat order
+    pub players: Vec<MatchPlayer>,
+    pub start_roll: u32,
+    //every roll of the game in order, the last 1 included
+    pub rolls: Vec<SeriesRoll>,
+    pub winner: Seat,
+    //unix time in milliseconds
+    pub started_at: u64,
+    pub finished_at: u64,
+}
+
+impl MatchRecord {
+    pub fn seat_of(&self, player: &str) -> Option<Seat> {
+        self.players
+            .iter()
+            .position(|match_player| match_player.id == player)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MatchPlayer {
+    //public id
+    pub id: String,
+    //as it was when the game ended
+    pub profile: Profile,
+}
+
+//over every game a player finished, not just the page that was asked for
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct PlayerStats {
+    pub games: u32,
+    pub wins: u32,
+    //rolls that weren't the player's last 1, averaged over their games
+    pub avg_rolls_survived: f64,
+    pub biggest_win: Option<u32>,
+}
+
+//GET /api/players/:id/history?offset=<n>&limit=<n>, newest game first
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct HistoryPage {
+    //public id, also when the page was asked for as `me`
+    pub player: String,
+    pub stats: PlayerStats,
+    pub games: Vec<MatchRecord>,
+    //games the player finished, for paging
+    pub total: usize,
+}
+
+//bumped whenever an older replay file would be read wrong
+pub const REPLAY_VERSION: u32 = 1;
+
+//GET /api/replays/:id, a finished game that can be watched and verified without the server
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub version: u32,
+    pub game: MatchRecord,
+    //the seed commitment, every roll in order, then the seed reveal
+    pub events: Vec<FeedEvent>,
+}
+
+//server -> client
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum GameMessage {
+    Spectate,
+    StartGame(String),
+    //never sent by the server, the frontend emits it when its socket closes
+    Disconnect,
+    Reconnect,
+    NoGameFound,
+    //you have a seat, wait for the room to fill up
+    P1Join,
+    //you were invited, show the join screen
+    P2Join,
+    Status(String),
+    //the latest part of the feed, replaces whatever the client had. `earlier` is set
+    //when older events were left out, ask for them with WsMsg::OlderFeed
+    FeedSnapshot { feed: Vec<FeedEvent>, earlier: bool },
+    //events that follow the last one the client saw, in seq order
+    FeedDelta(Vec<FeedEvent>),
+    //a page of events from before the oldest one the client has
+    OlderFeed { feed: Vec<FeedEvent>, earlier: bool },
+    StartRoll(String),
+    Pong,
+    GameOver(String),
+    VersionMismatch { server_version: u32 },
+    //the room was closed for being idle, nothing more will be sent for it
+    RoomClosed,
+    //how many seats are taken while the room fills up
+    Seats { joined: usize, seats: usize },
+    //sent whenever the turn changes, None when no clock is running
+    TurnClock(Option<TurnClock>),
+    //your rematch request went through, waiting on the other player
+    RematchRequested,
+    //the other player wants a rematch, reply with WsMsg::AcceptRematch
+    RematchOffered,
+    //the game server restarted, reconnect to pick the game back up
+    ServerRestarted,
+    //your virtual gold, sent on connect and whenever it changes
+    Balance(u64),
+    //everyone seated, in seat order
+    Players(Vec<Profile>),
+    //your seat, sent on connect and when you sit down, spectators never get it
+    YourSeat(Seat),
+    //a command was refused, nothing changed
+    Error { code: ErrorCode, message: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    RoomNotFound,
+    //spectators can't roll, forfeit or rematch
+    NotAPlayer,
+    AlreadySeated,
+    RoomFull,
+    InvalidStartRoll,
+    InvalidSeats,
+    NotStarted,
+    AlreadyStarted,
+    GameOver,
+    GameNotOver,
+    NotYourTurn,
+    Eliminated,
+    RematchAlreadyRequested,
+    NoRematchOffer,
+    InvalidSeries,
+    SeriesOver,
+    //not enough gold to cover the room's wager
+    InsufficientGold,
+    //only the stakes in WAGERS can be picked
+    InvalidWager,
+    //the gold couldn't be saved, nothing was moved
+    LedgerUnavailable,
+    InvalidName,
+    NameNotAllowed,
+    InvalidAvatar,
+    InvalidClientSeed,
+    //too many commands in a short time, wait a moment
+    RateLimited,
+    //the room hit a server bug and was closed
+    RoomCrashed,
+}
+
+impl ErrorCode {
+    pub fn message(self) -> &'static str {
+        match self {
+            ErrorCode::RoomNotFound => "this game doesn't exist",
+            ErrorCode::NotAPlayer => "you are spectating this game",
+            ErrorCode::AlreadySeated => "you are already in this game",
+            ErrorCode::RoomFull => "the arena is full",
+            ErrorCode::InvalidStartRoll => "the start roll must be at least 2",
+            ErrorCode::InvalidSeats => "a room needs between 2 and 10 seats",
+            ErrorCode::NotStarted => "the game hasn't started yet",
+            ErrorCode::AlreadyStarted => "the game has already started",
+            ErrorCode::GameOver => "the game is over",
+            ErrorCode::GameNotOver => "the game isn't over yet",
+            ErrorCode::NotYourTurn => "it's not your turn",
+            ErrorCode::Eliminated => "you are out of this game",
+            ErrorCode::RematchAlreadyRequested => "a rematch has already been requested",
+            ErrorCode::NoRematchOffer => "there is no rematch offer to accept",
+            ErrorCode::InvalidSeries => "a series has to be an odd number of games",
+            ErrorCode::SeriesOver => "the series is over",
+            ErrorCode::InsufficientGold => "you don't have enough gold for this wager",
+            ErrorCode::InvalidWager => "pick one of the wagers on offer",
+            ErrorCode::LedgerUnavailable => "couldn't move your gold, try again",
+            ErrorCode::InvalidName => {
+                "names are 2-20 letters from one alphabet, numbers, spaces, dots, dashes or underscores"
+            }
+            ErrorCode::NameNotAllowed => "pick a different name",
+            ErrorCode::InvalidAvatar => "pick one of the avatars on offer",
+            ErrorCode::InvalidClientSeed => "client seeds are up to 64 characters without a ':'",
+            ErrorCode::RateLimited => "slow down, you're clicking too fast",
+            ErrorCode::RoomCrashed => "something went wrong, this game has been closed",
+        }
+    }
+}
+
+impl From<RuleError> for ErrorCode {
+    fn from(err: RuleError) -> Self {
+        match err {
+            RuleError::InvalidStartRoll => ErrorCode::InvalidStartRoll,
+            RuleError::InvalidSeats => ErrorCode::InvalidSeats,
+            RuleError::NotStarted => ErrorCode::NotStarted,
+            RuleError::AlreadyStarted => ErrorCode::AlreadyStarted,
+            RuleError::GameOver => ErrorCode::GameOver,
+            RuleError::GameNotOver => ErrorCode::GameNotOver,
+            RuleError::NotYourTurn => ErrorCode::NotYourTurn,
+            RuleError::Eliminated => ErrorCode::Eliminated,
+            RuleError::InvalidSeries => ErrorCode::InvalidSeries,
+            RuleError::SeriesOver => ErrorCode::SeriesOver,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GameScore {
+    pub feed: Vec<FeedEvent>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FeedEvent {
+    //counts up from 1 in every room, 0 for events from before it was added
+    #[serde(default)]
+    pub seq: u64,
+    //unix time in milliseconds
+    pub at: u64,
+    pub event: GameEvent,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    Joined {
+        seat: Seat,
+    },
+    Left {
+        seat: Seat,
+    },
+    NewGame {
+        start_roll: u32,
+        start_seat: Seat,
+    },
+    Rolled {
+        seat: Seat,
+        roll: u32,
+        roll_between: u32,
+        nonce: u64,
+    },
+    //rolled a 1 with more than one player still standing
+    Eliminated {
+        seat: Seat,
+        roll_between: u32,
+        nonce: u64,
+    },
+    Died {
+        seat: Seat,
+        roll_between: u32,
+        winner: Seat,
+        //games won by each seat so far this session
+        score: Vec<u32>,
+        nonce: u64,
+    },
+    //the turn clock ran out on `seat`, followed by the auto roll or a forfeit
+    TimedOut {
+        seat: Seat,
+    },
+    //winner is only set when the forfeit ended the game
+    Forfeited {
+        seat: Seat,
+        winner: Option<Seat>,
+        score: Vec<u32>,
+    },
+    RematchRequested {
+        seat: Seat,
+    },
+    //`seat` put its stake in the pot
+    Escrowed {
+        seat: Seat,
+        amount: u64,
+    },
+    //the pot went to the winner
+    PaidOut {
+        seat: Seat,
+        amount: u64,
+    },
+    //closes a best-of series, no rematch after this
+    SeriesOver {
+        winner: Seat,
+        score: Vec<u32>,
+        games: Vec<SeriesGame>,
+        longest_streak: Option<Streak>,
+    },
+    //sha256 of the server seed used for the rolls that follow
+    SeedCommitted {
+        commitment: String,
+    },
+    //published at game over so every roll since the last commit can be checked
+    SeedRevealed {
+        server_seed: String,
+        client_seeds: Vec<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SeriesGame {
+    pub winner: Seat,
+    //every roll of the game in order, the last 1 included
+    pub rolls: Vec<SeriesRoll>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SeriesRoll {
+    pub seat: Seat,
+    pub roll: u32,
+}
+
+//most games in a row survived (won) by one seat
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Streak {
+    pub seat: Seat,
+    pub games: u32,
+}
+
+//client -> server
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum WsMsg {
+    Ping,
+    Close,
+    //take the empty seat of a room waiting for player 2
+    Join,
+    Roll,
+    //only at game over, the other player has to accept
+    RequestRematch,
+    AcceptRematch,
+    //give up the game in progress
+    Forfeit,
+    //mixed into every roll, locked in when a game starts
+    ClientSeed(String),
+    //the events before `before`, a seq
+    OlderFeed { before: u64 },
+}
+
+//client -> server on /api/queue/ws
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum QueueMsg {
+    //wait for someone who wants the same start roll, joining again moves you to that bracket
+    Join { start_roll: u32 },
+    Cancel,
+}
+
+//server -> client on /api/queue/ws
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum QueueMessage {
+    //your place in your bracket's line and how many players are queued overall
+    Position { position: usize, waiting: usize },
+    //go to /:game_id, your seat is reserved
+    Matched { game_id: String },
+    //nobody turned up in time
+    TimedOut,
+    //you left the queue, or joined it again somewhere else
+    Cancelled,
+    VersionMismatch { server_version: u32 },
+    Error { code: ErrorCode, message: String },
+}
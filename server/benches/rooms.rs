@@ -0,0 +1,119 @@
+//! Game server throughput: every room joins and plays a few turns at the same
+//! time. Runs the old one-loop game server (a single receiver, rooms found by
+//! a linear substring search), then the sharded one with one shard and with
+//! several, and prints commands per second for each.
+//!
+//!     cargo bench -p server --bench rooms --features single-loop
+
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use deathroll_protocol::RoomSettings;
+use server::{
+    game_server::{self, Action, GameServerHandle},
+    history::{History, SharedHistory},
+    ledger::{Ledger, SharedLedger},
+    lobby::{Lobby, SharedLobby},
+    profiles::{Profiles, SharedProfiles},
+    ratings::{Ratings, SharedRatings},
+    store::{MemoryStore, RoomStore},
+    PendingRoom, Services, SharedState, StartRoll,
+};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+const ROOMS: usize = 500;
+//rolls per room, kept under the per player rate limit
+const ROLLS: usize = 8;
+//connect x2, join, rolls, then the watcher's connect
+const COMMANDS_PER_ROOM: usize = ROLLS + 4;
+
+async fn play_room(server: GameServerHandle, game_id: String) {
+    let (p1_tx, _p1_rx) = mpsc::unbounded_channel();
+    let (p2_tx, _p2_rx) = mpsc::unbounded_channel();
+    let (p1, p2) = (Uuid::new_v4(), Uuid::new_v4());
+
+    server
+        .handle_connect(p1_tx, game_id.clone(), p1, None)
+        .await;
+    server
+        .handle_connect(p2_tx, game_id.clone(), p2, None)
+        .await;
+    server.handle_action(p2, game_id.clone(), Action::Join);
+    for roll in 0..ROLLS {
+        let player = if roll % 2 == 0 { p1 } else { p2 };
+        server.handle_action(player, game_id.clone(), Action::Roll);
+    }
+
+    //a room's commands are handled in order, once the watcher hears back the rolls are done
+    let (watcher_tx, mut watcher_rx) = mpsc::unbounded_channel();
+    server
+        .handle_connect(watcher_tx, game_id, Uuid::new_v4(), None)
+        .await;
+    watcher_rx.recv().await;
+}
+
+fn run(supervise: impl FnOnce(Services) -> GameServerHandle) -> Duration {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    runtime.block_on(async {
+        let store: Arc<dyn RoomStore> = Arc::new(MemoryStore::default());
+        let start_rolls: SharedState = Arc::new(RwLock::new(StartRoll::load(Arc::clone(&store))));
+        for room in 0..ROOMS {
+            let pending = PendingRoom::new("1000".to_string(), RoomSettings::default());
+            StartRoll::insert(&start_rolls, room_id(room), pending);
+        }
+        let ledger: SharedLedger = Arc::new(Mutex::new(Ledger::load(Arc::clone(&store))));
+        let lobby: SharedLobby = Arc::new(Lobby::default());
+        let ratings: SharedRatings = Arc::new(Mutex::new(Ratings::load(Arc::clone(&store))));
+        let profiles: SharedProfiles = Arc::new(RwLock::new(Profiles::load(Arc::clone(&store))));
+        let history: SharedHistory = Arc::new(RwLock::new(History::load(Arc::clone(&store))));
+        let services = Services {
+            store,
+            start_rolls,
+            ledger,
+            lobby,
+            ratings,
+            profiles,
+            history,
+        };
+        let server = supervise(services);
+
+        let started = Instant::now();
+        let rooms: Vec<_> = (0..ROOMS)
+            .map(|room| tokio::spawn(play_room(server.clone(), room_id(room))))
+            .collect();
+        for room in rooms {
+            room.await.unwrap();
+        }
+
+        started.elapsed()
+    })
+}
+
+//padded so no id is a substring of another, the single loop's search would find the wrong room
+fn room_id(room: usize) -> String {
+    format!("bench{room:04}")
+}
+
+fn main() {
+    let shards = game_server::shards_from_env().max(2);
+    let single_loop = run(game_server::supervise_single_loop);
+    let one_shard = run(|services| game_server::supervise(services, 1));
+    let sharded = run(|services| game_server::supervise(services, shards));
+
+    //the game server logs every command, print the results last so they're easy to find
+    for (name, elapsed) in [
+        ("single loop".to_string(), single_loop),
+        ("shards=1".to_string(), one_shard),
+        (format!("shards={shards}"), sharded),
+    ] {
+        let commands = ROOMS * COMMANDS_PER_ROOM;
+        println!(
+            "{name}: {commands} commands in {elapsed:?}, {:.0} commands/s",
+            commands as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
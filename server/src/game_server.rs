@@ -1,12 +1,40 @@
-use crate::SharedState;
-use rand::Rng;
+use crate::{
+    history::{History, SharedHistory},
+    ledger::{Ledger, SharedLedger},
+    lobby::SharedLobby,
+    profiles::SharedProfiles,
+    public_id,
+    ratings::{Ratings, SharedRatings},
+    room_writer::RoomWriter,
+    store::RoomStore,
+    Services, SharedState,
+};
+use deathroll_core::{
+    fair::{self, Fairness},
+    Deathroll, Outcome, Phase, RuleError, Seat,
+};
+use deathroll_protocol::{
+    seat_name, ErrorCode, FeedEvent, GameEvent, GameMessage, GameScore, LobbyRoom, MatchPlayer,
+    MatchRecord, OnTimeout, Profile, Replay, RoomSettings, SeriesGame, SeriesRoll, Streak,
+    TurnClock, REPLAY_VERSION,
+};
+use futures::FutureExt;
 use serde::{Deserialize, Serialize};
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    env,
+    hash::{Hash, Hasher},
     io,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, PoisonError},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{broadcast, mpsc, Mutex},
+    task, time,
 };
-use tokio::sync::mpsc;
 
 use uuid::Uuid;
 
@@ -14,23 +42,26 @@ pub type PlayerId = Uuid;
 pub type GameId = String;
 pub type Msg = String;
 
-const P1: &str = "\u{1F9D9}\u{200D}\u{2642}\u{FE0F}";
-const P2: &str = "\u{1F9DF}";
-
-#[derive(Serialize, Clone, Deserialize)]
-pub enum GameMessage {
-    Spectate,
-    StartGame(String),
-    Reconnect,
-    NoGameFound,
-    P1Join,
-    P2Join,
-    Status(String),
-    GameScore(GameScore),
-    StartRoll(String),
-    Pong,
-    GameOver(String),
-}
+const SHARDS_ENV: &str = "DEATHROLL_SHARDS";
+//how long the supervisor waits before starting a crashed game server again
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+//commands a player can send per window before being told to slow down
+const ACTION_LIMIT: u32 = 10;
+const ACTION_WINDOW: Duration = Duration::from_secs(1);
+//how often turn clocks are checked
+const CLOCK_TICK: Duration = Duration::from_secs(1);
+//how often the game server looks for rooms and invite links to clean up
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+//an invite link nobody opened in this long is dropped
+const START_ROLL_TTL: Duration = Duration::from_secs(60 * 60);
+//rooms nobody has rolled, joined or left in this long are closed
+const ROOM_IDLE_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+//a finished game that nobody rematches is archived sooner
+const FINISHED_ROOM_TTL: Duration = Duration::from_secs(15 * 60);
+//a reconnecting client further behind than this gets a snapshot instead of the gap
+const RESUME_WINDOW: usize = 64;
+//feed events sent on connect and per older page, long rematch sessions are paged in
+const SNAPSHOT_LIMIT: usize = 200;
 
 #[derive(Debug)]
 pub enum Command {
@@ -38,522 +69,2091 @@ pub enum Command {
         player_tx: mpsc::UnboundedSender<Msg>,
         game_id: GameId,
         player_id: PlayerId,
-        state: SharedState,
+        //last feed event the client saw, None on a fresh page load
+        last_seq: Option<u64>,
     },
 
     Disconnect {
         player_id: PlayerId,
         game_id: GameId,
+        player_tx: mpsc::UnboundedSender<Msg>,
+    },
+
+    Action {
+        player_id: PlayerId,
+        game_id: GameId,
+        action: Action,
+    },
+
+    ClientSeed {
+        player_id: PlayerId,
+        game_id: GameId,
+        seed: String,
     },
 
-    Turn {
+    //answered on `player_tx` only, the player's other tabs have their own feed
+    OlderFeed {
         player_id: PlayerId,
         game_id: GameId,
+        player_tx: mpsc::UnboundedSender<Msg>,
+        before: u64,
     },
 }
 
+impl Command {
+    fn game_id(&self) -> &GameId {
+        match self {
+            Command::Connect { game_id, .. }
+            | Command::Disconnect { game_id, .. }
+            | Command::Action { game_id, .. }
+            | Command::ClientSeed { game_id, .. }
+            | Command::OlderFeed { game_id, .. } => game_id,
+        }
+    }
+}
+
+//what a player asked for, checked against the room's phase before anything changes
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    Join,
+    Roll,
+    RequestRematch,
+    AcceptRematch,
+    Forfeit,
+}
+
+//routes every command to the shard that owns its room, sends never fail while
+//the supervisors are alive since the receivers outlive every restart
 #[derive(Debug, Clone)]
 pub struct GameServerHandle {
-    pub server_tx: mpsc::UnboundedSender<Command>,
+    shards: Arc<[Shard]>,
+}
+
+#[derive(Debug)]
+struct Shard {
+    server_tx: mpsc::UnboundedSender<Command>,
+    //fires after the shard restarted, sockets tell their client to reconnect
+    restarts: broadcast::Sender<()>,
 }
 
 impl GameServerHandle {
+    fn shard(&self, game_id: &str) -> &Shard {
+        &self.shards[shard_for(game_id, self.shards.len())]
+    }
+
+    fn send(&self, cmd: Command) {
+        let _ = self.shard(cmd.game_id()).server_tx.send(cmd);
+    }
+
+    pub fn restarts(&self, game_id: &str) -> broadcast::Receiver<()> {
+        self.shard(game_id).restarts.subscribe()
+    }
+
     pub async fn handle_connect(
         &self,
         player_tx: mpsc::UnboundedSender<String>,
         game_id: String,
         player_id: PlayerId,
-        state: SharedState,
+        last_seq: Option<u64>,
     ) {
-        self.server_tx
-            .send(Command::Connect {
-                player_tx,
-                game_id,
-                player_id,
-                state,
-            })
-            .unwrap();
+        self.send(Command::Connect {
+            player_tx,
+            game_id,
+            player_id,
+            last_seq,
+        });
     }
 
-    pub async fn handle_send(&self, player_id: PlayerId, game_id: GameId) {
-        self.server_tx
-            .send(Command::Turn { player_id, game_id })
-            .unwrap();
+    pub fn handle_action(&self, player_id: PlayerId, game_id: GameId, action: Action) {
+        self.send(Command::Action {
+            player_id,
+            game_id,
+            action,
+        });
     }
 
-    pub fn handle_disconnect(&self, player_id: PlayerId, game_id: GameId) {
-        self.server_tx
-            .send(Command::Disconnect { player_id, game_id })
-            .unwrap();
+    pub fn handle_disconnect(
+        &self,
+        player_id: PlayerId,
+        game_id: GameId,
+        player_tx: mpsc::UnboundedSender<Msg>,
+    ) {
+        self.send(Command::Disconnect {
+            player_id,
+            game_id,
+            player_tx,
+        });
     }
-}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct GameScore {
-    client_feed: Vec<String>,
+    pub fn handle_client_seed(&self, player_id: PlayerId, game_id: GameId, seed: String) {
+        self.send(Command::ClientSeed {
+            player_id,
+            game_id,
+            seed,
+        });
+    }
+
+    pub fn handle_older_feed(
+        &self,
+        player_id: PlayerId,
+        game_id: GameId,
+        player_tx: mpsc::UnboundedSender<Msg>,
+        before: u64,
+    ) {
+        self.send(Command::OlderFeed {
+            player_id,
+            game_id,
+            player_tx,
+            before,
+        });
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameState {
-    roll: u32,
-    player_1: Uuid,
-    player_2: Option<Uuid>,
-    player_turn: String,
-    game_start: bool,
-    start_roll: u32,
-    game_over: bool,
+    rules: Deathroll,
+    //players in seat order, the room's creator is seat 0
+    seats: Vec<PlayerId>,
     game_score: GameScore,
-    start_player: Uuid,
-    p1_overall: u32,
-    p2_overall: u32,
+    fairness: Fairness,
+    //latest seed sent by each player, copied into `fairness` when a game starts
+    client_seeds: HashMap<PlayerId, String>,
+    #[serde(default = "now")]
+    last_active: u64,
+    #[serde(default)]
+    settings: RoomSettings,
+    //unix ms when the current turn runs out, None while no clock is running
+    #[serde(default)]
+    turn_deadline: Option<u64>,
+    //seat waiting for the other player to accept a rematch
+    #[serde(default)]
+    rematch_requested: Option<Seat>,
+    //players whose wager is held for the current game, refunded if the room closes first
+    #[serde(default)]
+    escrow: Vec<PlayerId>,
+    #[serde(default = "now")]
+    created_at: u64,
+    //seats held for matched players, nobody else can sit down
+    #[serde(default)]
+    reserved: Vec<PlayerId>,
+    //when the current game's first turn began, for the match history
+    #[serde(default = "now")]
+    game_started_at: u64,
+    //last feed event sent out as a delta, sockets that connect later get a snapshot
+    #[serde(skip)]
+    broadcast_seq: u64,
+}
+
+impl GameState {
+    //a room with just its creator seated, committed to its first server seed
+    pub(crate) fn new(
+        rules: Deathroll,
+        creator: PlayerId,
+        settings: RoomSettings,
+        reserved: Vec<PlayerId>,
+    ) -> Self {
+        let mut game_state = Self {
+            rules,
+            seats: vec![creator],
+            game_score: GameScore::default(),
+            fairness: Fairness::new(&mut rand::thread_rng()),
+            client_seeds: HashMap::new(),
+            last_active: now(),
+            settings,
+            turn_deadline: None,
+            rematch_requested: None,
+            escrow: Vec::new(),
+            created_at: now(),
+            reserved,
+            game_started_at: now(),
+            broadcast_seq: 0,
+        };
+        //commit to the server seed before anyone can roll
+        let commitment = game_state.fairness.commitment();
+        game_state.push_event(GameEvent::SeedCommitted { commitment });
+
+        game_state
+    }
+
+    fn seat_of(&self, player_id: PlayerId) -> Option<Seat> {
+        self.seats.iter().position(|seated| *seated == player_id)
+    }
+
+    fn player(&self, seat: Seat) -> Option<PlayerId> {
+        self.seats.get(seat).copied()
+    }
+
+    fn push_event(&mut self, event: GameEvent) {
+        let seq = self.last_seq() + 1;
+        self.game_score.feed.push(FeedEvent {
+            seq,
+            at: now(),
+            event,
+        });
+    }
+
+    fn last_seq(&self) -> u64 {
+        self.game_score
+            .feed
+            .last()
+            .map(|feed_event| feed_event.seq)
+            .unwrap_or_default()
+    }
+
+    //rooms saved before events had a seq get one now, the feed is never trimmed so it's the position
+    fn number_feed(&mut self) {
+        if self
+            .game_score
+            .feed
+            .iter()
+            .any(|feed_event| feed_event.seq == 0)
+        {
+            for (index, feed_event) in self.game_score.feed.iter_mut().enumerate() {
+                feed_event.seq = index as u64 + 1;
+            }
+        }
+    }
+
+    //the newest events, the verifier asks for older pages if the first game is cut off
+    fn snapshot(&self) -> GameMessage {
+        let feed = &self.game_score.feed;
+        let start = page_start(feed.len());
+
+        GameMessage::FeedSnapshot {
+            feed: feed[start..].to_vec(),
+            earlier: start > 0,
+        }
+    }
+
+    fn older_feed(&self, before: u64) -> GameMessage {
+        let feed = &self.game_score.feed;
+        let end = feed.partition_point(|feed_event| feed_event.seq < before);
+        let start = page_start(end);
+
+        GameMessage::OlderFeed {
+            feed: feed[start..end].to_vec(),
+            earlier: start > 0,
+        }
+    }
+
+    //events pushed since the last broadcast
+    fn take_unsent(&mut self) -> Vec<FeedEvent> {
+        let feed = &self.game_score.feed;
+        let broadcast_seq = self.broadcast_seq;
+        let unsent =
+            feed[feed.partition_point(|feed_event| feed_event.seq <= broadcast_seq)..].to_vec();
+        self.broadcast_seq = self.last_seq();
+
+        unsent
+    }
+
+    //what a reconnecting client is missing, a snapshot when it's too far behind or
+    //has events this room doesn't (the room was restored from an older save)
+    fn resume(&self, last_seq: Option<u64>) -> GameMessage {
+        let feed = &self.game_score.feed;
+        let missed = last_seq
+            .filter(|last_seq| *last_seq > 0 && *last_seq <= self.last_seq())
+            .map(|last_seq| &feed[feed.partition_point(|feed_event| feed_event.seq <= last_seq)..])
+            .filter(|missed| missed.len() <= RESUME_WINDOW);
+
+        match missed {
+            Some(missed) => GameMessage::FeedDelta(missed.to_vec()),
+            None => self.snapshot(),
+        }
+    }
+
+    fn lock_client_seeds(&mut self) {
+        for (seat, player_id) in self.seats.iter().enumerate() {
+            let seed = self.client_seeds.get(player_id).cloned();
+            self.fairness
+                .set_client_seed(seat, seed.unwrap_or_default());
+        }
+    }
+
+    //roll for `seat` from the committed seeds, returns the outcome and its nonce
+    fn roll(&mut self, seat: Seat) -> Result<(Outcome, u64), RuleError> {
+        let fairness = &mut self.fairness;
+        let mut nonce = 0;
+        let outcome = self.rules.roll_with(seat, |roll_between| {
+            let (roll, roll_nonce) = fairness.next_roll(roll_between);
+            nonce = roll_nonce;
+            roll
+        })?;
+
+        Ok((outcome, nonce))
+    }
+
+    //reveal the seed behind the game that just finished and commit to the next one
+    fn reveal_seed(&mut self) {
+        let server_seed = self.fairness.reveal(&mut rand::thread_rng());
+        let client_seeds = self.fairness.client_seeds().to_vec();
+        self.push_event(GameEvent::SeedRevealed {
+            server_seed,
+            client_seeds,
+        });
+
+        let commitment = self.fairness.commitment();
+        self.push_event(GameEvent::SeedCommitted { commitment });
+    }
+
+    //give whoever's turn it is a full clock, stops the clock outside of a game
+    fn restart_clock(&mut self) {
+        self.turn_deadline = match (self.rules.phase(), self.settings.turn_secs) {
+            (Phase::Playing, Some(secs)) => Some(now() + u64::from(secs) * 1000),
+            _ => None,
+        };
+    }
+
+    fn turn_clock(&self) -> Option<TurnClock> {
+        let deadline = self.turn_deadline?;
+
+        Some(TurnClock {
+            seat: self.rules.turn(),
+            remaining_ms: deadline.saturating_sub(now()),
+            on_timeout: self.settings.on_timeout,
+        })
+    }
+
+    fn forfeit(&mut self, seat: Seat) -> Result<Outcome, RuleError> {
+        let outcome = self.rules.forfeit(seat)?;
+        let winner = match outcome {
+            Outcome::Died { winner, .. } => Some(winner),
+            _ => None,
+        };
+        let score = self.rules.scores().to_vec();
+        self.push_event(GameEvent::Forfeited {
+            seat,
+            winner,
+            score,
+        });
+        if winner.is_some() {
+            self.reveal_seed();
+            self.end_series();
+        }
+
+        Ok(outcome)
+    }
+
+    //once a game decides a best-of series, sum the whole series up in the feed
+    fn end_series(&mut self) {
+        let Some(winner) = self.rules.series_winner() else {
+            return;
+        };
+        let games = series_games(&self.game_score.feed);
+        let longest_streak = longest_streak(&games);
+        let score = self.rules.scores().to_vec();
+        self.push_event(GameEvent::SeriesOver {
+            winner,
+            score,
+            games,
+            longest_streak,
+        });
+    }
+
+    fn games_played(&self) -> u32 {
+        self.rules.scores().iter().sum()
+    }
+
+    fn expired(&self, now: u64) -> bool {
+        let idle = Duration::from_millis(now.saturating_sub(self.last_active));
+        match self.rules.phase() {
+            Phase::GameOver => idle > FINISHED_ROOM_TTL,
+            _ => idle > ROOM_IDLE_TTL,
+        }
+    }
+}
+
+//how connect finds a player's room
+#[derive(Debug, Clone, Copy)]
+enum RoomLookup {
+    Exact,
+    //the one-loop server's substring search, only there for the rooms bench to compare against
+    #[cfg(feature = "single-loop")]
+    Linear,
+}
+
+//one open socket, a player has one per tab
+#[derive(Debug)]
+struct Session {
+    game_id: GameId,
+    tx: mpsc::UnboundedSender<Msg>,
 }
 
+//owns every room whose id hashes to `shard`, rooms on other shards never wait on it
 #[derive(Debug)]
 pub struct GameServer {
-    sessions: HashMap<PlayerId, Vec<mpsc::UnboundedSender<Msg>>>,
+    shard: usize,
+    shards: usize,
+    sessions: HashMap<PlayerId, Vec<Session>>,
     players: HashMap<GameId, HashSet<PlayerId>>,
-    server_rx: mpsc::UnboundedReceiver<Command>,
+    server_rx: Arc<Mutex<mpsc::UnboundedReceiver<Command>>>,
     game_rooms: HashMap<GameId, GameState>,
+    room_lookup: RoomLookup,
+    store: Arc<dyn RoomStore>,
+    //saves, archives and removes rooms off the game loop
+    room_writer: RoomWriter,
+    start_rolls: SharedState,
+    ledger: SharedLedger,
+    lobby: SharedLobby,
+    ratings: SharedRatings,
+    profiles: SharedProfiles,
+    history: SharedHistory,
+    //(window start, commands sent in it) per player
+    action_counts: HashMap<PlayerId, (u64, u32)>,
+}
+
+//one game server per cpu unless DEATHROLL_SHARDS says otherwise
+pub fn shards_from_env() -> usize {
+    env::var(SHARDS_ENV)
+        .ok()
+        .and_then(|shards| shards.parse::<usize>().ok())
+        .filter(|shards| *shards > 0)
+        .unwrap_or_else(|| {
+            thread::available_parallelism()
+                .map(|cpus| cpus.get())
+                .unwrap_or(1)
+        })
+}
+
+//the shard that owns a room, the same id always lands on the same shard
+pub fn shard_for(game_id: &str, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    game_id.hash(&mut hasher);
+
+    (hasher.finish() % shards as u64) as usize
+}
+
+//run `shards` game servers, each restarted from the store whenever it dies
+pub fn supervise(services: Services, shards: usize) -> GameServerHandle {
+    supervise_with(services, shards, RoomLookup::Exact)
+}
+
+//the game server from before sharding: every room on one loop, found by a linear search
+#[cfg(feature = "single-loop")]
+pub fn supervise_single_loop(services: Services) -> GameServerHandle {
+    supervise_with(services, 1, RoomLookup::Linear)
+}
+
+fn supervise_with(services: Services, shards: usize, room_lookup: RoomLookup) -> GameServerHandle {
+    let shards = shards.max(1);
+    let handles = (0..shards)
+        .map(|shard| {
+            let (server_tx, server_rx) = mpsc::unbounded_channel();
+            let (restarts, _) = broadcast::channel(1);
+            let server_rx = Arc::new(Mutex::new(server_rx));
+
+            let services = services.clone();
+            let shard_restarts = restarts.clone();
+            //outlives the restarts, so a new game server can wait for the old one's writes
+            let room_writer = RoomWriter::spawn(Arc::clone(&services.store));
+            tokio::spawn(async move {
+                loop {
+                    let mut game_server = GameServer::new(
+                        (shard, shards),
+                        services.clone(),
+                        room_writer.clone(),
+                        Arc::clone(&server_rx),
+                    );
+                    game_server.room_lookup = room_lookup;
+                    match tokio::spawn(game_server.run()).await {
+                        Ok(Ok(())) => break,
+                        Ok(Err(err)) => println!("game server {shard} stopped: {err}"),
+                        Err(err) => println!("game server {shard} crashed: {err}"),
+                    }
+
+                    //the new game server has no sessions, its clients have to connect again
+                    let _ = shard_restarts.send(());
+                    time::sleep(RESTART_DELAY).await;
+                    println!("restarting game server {shard}");
+                }
+            });
+
+            Shard {
+                server_tx,
+                restarts,
+            }
+        })
+        .collect();
+
+    GameServerHandle { shards: handles }
 }
+
 impl GameServer {
-    pub fn new() -> (Self, GameServerHandle) {
-        let (server_tx, server_rx) = mpsc::unbounded_channel();
+    pub fn new(
+        (shard, shards): (usize, usize),
+        services: Services,
+        room_writer: RoomWriter,
+        server_rx: Arc<Mutex<mpsc::UnboundedReceiver<Command>>>,
+    ) -> Self {
+        let Services {
+            store,
+            start_rolls,
+            ledger,
+            lobby,
+            ratings,
+            profiles,
+            history,
+        } = services;
 
-        (
-            Self {
-                sessions: HashMap::new(),
-                players: HashMap::new(),
-                server_rx,
-                game_rooms: HashMap::new(),
-            },
-            GameServerHandle { server_tx },
-        )
+        Self {
+            shard,
+            shards,
+            sessions: HashMap::new(),
+            players: HashMap::new(),
+            server_rx,
+            game_rooms: HashMap::new(),
+            room_lookup: RoomLookup::Exact,
+            store,
+            room_writer,
+            start_rolls,
+            ledger,
+            lobby,
+            ratings,
+            profiles,
+            history,
+            action_counts: HashMap::new(),
+        }
     }
 
     pub async fn run(mut self) -> io::Result<()> {
-        while let Some(cmd) = self.server_rx.recv().await {
-            match cmd {
-                Command::Connect {
-                    player_tx,
-                    game_id,
-                    player_id,
-                    state,
-                } => {
-                    self.connect(player_tx, game_id, player_id, state).await;
-                }
+        //pick up the games that were running before a restart, players rejoin with their cookie
+        let (shard, shards) = (self.shard, self.shards);
+        self.room_writer.flush().await;
+        self.game_rooms = self.store.load_rooms()?;
+        self.game_rooms
+            .retain(|game_id, _| shard_for(game_id, shards) == shard);
+        println!(
+            "game server {shard} restored {} rooms",
+            self.game_rooms.len()
+        );
+        //clocks don't run while the server is down, everyone gets a fresh turn
+        for game_state in self.game_rooms.values_mut() {
+            game_state.restart_clock();
+            game_state.number_feed();
+            game_state.broadcast_seq = game_state.last_seq();
+        }
+        //the last run's listings are stale, list the restored rooms again
+        self.lobby
+            .retain(|game_id| shard_for(game_id, shards) != shard);
+        let game_ids: Vec<GameId> = self.game_rooms.keys().cloned().collect();
+        for game_id in game_ids {
+            self.update_lobby(&game_id);
+        }
 
-                Command::Disconnect { player_id, game_id } => {
-                    self.disconnect(player_id, game_id).await;
-                }
+        let mut clock = time::interval(CLOCK_TICK);
+        let mut sweeper = time::interval(SWEEP_INTERVAL);
+        //released when this game server returns or unwinds, the next one takes it over
+        let server_rx = Arc::clone(&self.server_rx);
+        let mut server_rx = server_rx.lock().await;
 
-                Command::Turn { player_id, game_id } => {
-                    self.new_turn(player_id, game_id).await;
+        loop {
+            tokio::select! {
+                cmd = server_rx.recv() => {
+                    let Some(cmd) = cmd else {
+                        break;
+                    };
+                    self.handle_command(cmd).await;
                 }
+                _ = clock.tick() => self.check_clocks().await,
+                _ = sweeper.tick() => self.sweep(),
             }
         }
 
         Ok(())
     }
 
-    async fn update_game_feed(&self, game_id: &str) {
-        let game = self.game_rooms.get(game_id).unwrap();
+    async fn handle_command(&mut self, cmd: Command) {
+        let game_id = cmd.game_id().clone();
 
-        let msg = GameMessage::GameScore(game.game_score.clone());
+        //a panic while handling one room only takes that room down
+        if AssertUnwindSafe(self.dispatch(cmd))
+            .catch_unwind()
+            .await
+            .is_err()
+        {
+            self.fail_room(&game_id);
+            return;
+        }
 
-        if let Some(game) = self.players.get(game_id) {
-            for player_ids in game {
-                if let Some(cmd_tx) = self.sessions.get(player_ids) {
-                    for tx in cmd_tx {
-                        let _ = tx.send(serde_json::to_string(&msg).unwrap());
-                    }
-                }
-            }
+        if let Some(game_state) = self.game_rooms.get_mut(&game_id) {
+            game_state.last_active = now();
         }
+        self.save_room(&game_id);
+        self.update_lobby(&game_id);
     }
 
-    async fn send_to_other(&self, game_id: &str, msg: GameMessage, player_id: Uuid) {
-        if let Some(game) = self.players.get(game_id) {
-            for player_ids in game {
-                if *player_ids != player_id {
-                    if let Some(cmd_tx) = self.sessions.get(player_ids) {
-                        for tx in cmd_tx {
-                            let _ = tx.send(serde_json::to_string(&msg).unwrap());
-                        }
-                    }
-                }
+    async fn dispatch(&mut self, cmd: Command) {
+        match cmd {
+            Command::Connect {
+                player_tx,
+                game_id,
+                player_id,
+                last_seq,
+            } => {
+                self.connect(player_tx, game_id, player_id, last_seq).await;
             }
-        }
-    }
 
-    async fn send_status_message(&self, player_id: PlayerId, msg: GameMessage) {
-        if let Some(cmd_tx) = self.sessions.get(&player_id) {
-            for tx in cmd_tx {
-                let _ = tx.send(serde_json::to_string(&msg).unwrap());
+            Command::Disconnect {
+                player_id,
+                game_id,
+                player_tx,
+            } => {
+                self.disconnect(player_id, game_id, player_tx).await;
             }
-        }
-    }
 
-    async fn new_turn(&mut self, player_id: PlayerId, game_id: GameId) {
-        if self.game_rooms.get(&game_id).is_some() {
-            if let Some(game_state) = self
-                .game_rooms
-                .iter()
-                .find_map(|(game, game_state)| game.contains(&game_id).then_some(game_state))
-            {
-                println!("GAME DETAILS {:?}", game_state);
-                if game_state.player_turn == player_id.to_string()
-                    && !game_state.game_over
-                    && game_state.game_start
-                {
-                    let roll_between = game_state.roll;
-                    let roll = roll_die(game_state.roll).await;
-                    if roll != 1 {
-                        //handle player 1 turn
-                        if player_id == game_state.player_1 {
-                            let msg = format!("{P1} {roll} \u{1F3B2} (1-{roll_between})");
-                            self.game_rooms
-                                .entry(game_id.clone())
-                                .and_modify(|game_state| {
-                                    game_state.roll = roll;
-                                    game_state.game_score.client_feed.push(msg);
-
-                                    if let Some(player_2) = game_state.player_2 {
-                                        game_state.player_turn = player_2.to_string();
-                                    } else {
-                                        game_state.player_turn = "0".to_string();
-                                    }
-                                });
-                            let status_msg = GameMessage::Status(format!("{P1} \u{1F3B2} {roll}"));
-
-                            self.send_status_message(player_id, status_msg).await;
-                            let status_msg =
-                                GameMessage::Status(format!("{P2} \u{1F3B2} It's your roll!"));
-                            self.send_to_other(&game_id, status_msg, player_id).await;
-                            //handle player 2 turn
-                        } else if Some(player_id) == game_state.player_2 {
-                            let msg = format!("{P2} {roll} \u{1F3B2} (1-{roll_between})");
-                            self.game_rooms
-                                .entry(game_id.clone())
-                                .and_modify(|game_state| {
-                                    game_state.roll = roll;
-                                    game_state.game_score.client_feed.push(msg);
-                                    game_state.player_turn = game_state.player_1.to_string()
-                                });
-                            let status_msg = GameMessage::Status(format!("{P2} \u{1F3B2} {roll}"));
-                            //send player roll as status update
-                            self.send_status_message(player_id, status_msg).await;
-                            let status_msg =
-                                GameMessage::Status(format!("{P1} \u{1F3B2} It's your roll!"));
-                            self.send_to_other(&game_id, status_msg, player_id).await;
-                        }
-                        self.update_game_feed(&game_id).await;
-                    } else {
-                        let defeat1 = GameMessage::GameOver(format!("{P1} \u{1F480}"));
-                        let victory1 = GameMessage::GameOver(format!("{P1} \u{1F3C6}"));
-                        let defeat2 = GameMessage::GameOver(format!("{P2} \u{1F480}"));
-                        let victory2 = GameMessage::GameOver(format!("{P2} \u{1F3C6}"));
-                        //handle player 1 death
-                        if player_id == game_state.player_1 {
-                            //send victory status message to player 2
-                            let player2 = Some(game_state.player_2).unwrap();
-                            self.send_status_message(player2.unwrap(), victory2).await;
-                            //send defeat status message to player 1
-                            let player1 = game_state.player_1;
-
-                            self.send_status_message(player1, defeat1).await;
-                            //deathroll feed update
-
-                            self.game_rooms
-                                    .entry(game_id.clone())
-                                    .and_modify(|game_state| {
-                                        game_state.p2_overall += 1;
-                                        let p1_score = game_state.p1_overall;
-                                        let p2_score = game_state.p2_overall;
-                                        let msg = format!("{P1} 1 \u{1F480} (1-{roll_between}) {P1} \u{1F3C6} {p1_score} {P2} \u{1F3C6} {p2_score}");
-                                        game_state.roll = roll;
-                                        game_state.game_score.client_feed.push(msg);
-                                        game_state.game_over = true;
-                                    });
-                            //handle player 1 death
-                        } else if Some(player_id) == game_state.player_2 {
-                            //send victory message to player 1
-                            let player1 = game_state.player_1;
-                            self.send_status_message(player1, victory1).await;
-                            //send defeat status message to player 2
-                            let player2 = Some(game_state.player_2).unwrap();
-                            self.send_status_message(player2.unwrap(), defeat2).await;
-                            //deathroll feed update
-
-                            self.game_rooms
-                                    .entry(game_id.clone())
-                                    .and_modify(|game_state| {
-                                        game_state.p1_overall += 1;
-                                        let p1_score = game_state.p1_overall;
-                                        let p2_score = game_state.p2_overall;
-                                        let msg = format!("{P2} 1 \u{1F480} (1-{roll_between}) {P1} \u{1F3C6} {p1_score} {P2} \u{1F3C6} {p2_score}");
-                                        game_state.roll = roll;
-                                        game_state.game_score.client_feed.push(msg);
-                                        game_state.game_over = true;
-                                    });
-                        }
-
-                        self.update_game_feed(&game_id).await;
-                    }
-                } else if !game_state.game_start {
-                    let msg =
-                        GameMessage::StartGame(format!("{P2} \u{1F3B2} waiting for {P1} to roll"));
-                    self.send_status_message(player_id, msg).await;
-
-                    let msg = GameMessage::StartGame(format!("{P1} \u{1F3B2} roll to start"));
-                    let player_1 = game_state.player_1;
-                    self.send_status_message(player_1, msg).await;
-
-                    if player_id != game_state.player_1 {
-                        self.game_rooms
-                            .entry(game_id.clone())
-                            .and_modify(|game_state| {
-                                game_state.game_start = true;
-                                game_state.player_2 = Some(player_id);
-                            });
-                    }
-                } else if game_state.game_over {
-                    if game_state.start_player != game_state.player_1 {
-                        let mut new_game = GameState {
-                            roll: game_state.start_roll,
-                            player_1: game_state.player_1,
-                            player_2: game_state.player_2,
-                            player_turn: game_state.player_1.to_string(),
-                            start_player: game_state.player_1,
-                            game_start: true,
-                            start_roll: game_state.start_roll,
-                            game_over: false,
-                            game_score: game_state.game_score.clone(),
-                            p1_overall: game_state.p1_overall,
-                            p2_overall: game_state.p2_overall,
-                        };
-
-                        let start_roll = new_game.start_roll;
-
-                        new_game
-                            .game_score
-                            .client_feed
-                            .push(format!("New Game \u{2694}\u{FE0F} {start_roll}"));
-
-                        let sendp2 = game_state.player_1;
-                        let sendp1 = game_state.player_2.unwrap();
-
-                        if let Some(x) = self.game_rooms.get_mut(&game_id) {
-                            *x = new_game
-                        }
-
-                        self.update_game_feed(&game_id).await;
-                        let msg = GameMessage::Status(format!("{P1} \u{1F3B2} roll to start"));
-                        //send start roll message to p2 on reset
-                        self.send_to_other(&game_id, msg, sendp1).await;
-                        let msg =
-                            GameMessage::Status(format!("{P2} \u{1F3B2} waiting for {P1} to roll"));
-                        self.send_to_other(&game_id, msg, sendp2).await;
-                    } else if game_state.start_player != game_state.player_2.unwrap() {
-                        let mut new_game = GameState {
-                            roll: game_state.start_roll,
-                            player_1: game_state.player_1,
-                            player_2: game_state.player_2,
-                            player_turn: game_state.player_2.unwrap().to_string(),
-                            start_player: game_state.player_2.unwrap(),
-                            game_start: true,
-                            start_roll: game_state.start_roll,
-                            game_over: false,
-                            game_score: game_state.game_score.clone(),
-                            p1_overall: game_state.p1_overall,
-                            p2_overall: game_state.p2_overall,
-                        };
-
-                        let start_roll = new_game.start_roll;
-
-                        new_game
-                            .game_score
-                            .client_feed
-                            .push(format!("New Game \u{2694}\u{FE0F} {start_roll}"));
-
-                        let sendp2 = game_state.player_1;
-                        let sendp1 = game_state.player_2.unwrap();
-                        if let Some(gamestate) = self.game_rooms.get_mut(&game_id) {
-                            *gamestate = new_game
-                        }
-                        self.update_game_feed(&game_id).await;
-                        let msg = GameMessage::Status(format!("{P2} \u{1F3B2} roll to start"));
-                        //send start roll message to p2 on reset
-                        self.send_to_other(&game_id, msg, sendp2).await;
-                        let msg =
-                            GameMessage::Status(format!("{P1} \u{1F3B2} waiting for {P2} to roll"));
-                        self.send_to_other(&game_id, msg, sendp1).await;
-                    }
-                } else if player_id != game_state.player_1
-                    && player_id != game_state.player_2.unwrap()
-                {
-                    self.send_status_message(player_id, GameMessage::Spectate)
-                        .await;
+            Command::Action {
+                player_id,
+                game_id,
+                action,
+            } => {
+                self.player_action(player_id, game_id, action).await;
+            }
+
+            Command::ClientSeed {
+                player_id,
+                game_id,
+                seed,
+            } => {
+                if self.allow_command(player_id, &game_id).await {
+                    self.client_seed(player_id, game_id, seed).await;
                 }
             }
-        }
-    }
 
-    async fn connect(
-        &mut self,
-        tx: mpsc::UnboundedSender<Msg>,
-        game_id: String,
-        player_id: Uuid,
-        state: SharedState,
-    ) -> PlayerId {
-        if let Some(value) = self.sessions.get_mut(&player_id) {
-            // If session exists then push new tx to vec (fix opening multiple tabs of the same game)
-            value.push(tx);
-        } else {
-            let tx_vec = vec![tx];
-            self.sessions.insert(player_id, tx_vec);
+            //every page is up to SNAPSHOT_LIMIT events, it's limited like any other action
+            Command::OlderFeed {
+                player_id,
+                game_id,
+                player_tx,
+                before,
+            } => {
+                if !self.allow_command(player_id, &game_id).await {
+                    return;
+                }
+                if let Some(game_state) = self.game_rooms.get(&game_id) {
+                    let msg = game_state.older_feed(before);
+                    let _ = player_tx.send(serde_json::to_string(&msg).unwrap());
+                }
+            }
         }
+    }
 
-        let game_id_clone = game_id.clone();
-        let game_id_clone_2 = game_id.clone();
-        let game_id_clone_3 = game_id.clone();
-
-        self.players
-            .entry(game_id)
-            .or_insert_with(HashSet::new)
-            .insert(player_id);
-
-        if let Some(game_state) = self
+    //act for players whose turn clock ran out
+    async fn check_clocks(&mut self) {
+        let now = now();
+        let timed_out: Vec<GameId> = self
             .game_rooms
             .iter()
-            .find_map(|(game, game_state)| game.contains(&game_id_clone).then_some(game_state))
-        {
-            if !game_state.game_start && game_state.player_1 != player_id {
-                //send p2 join message to show join screen
-                self.send_status_message(player_id, GameMessage::P2Join)
-                    .await;
-                //display start roll
-                let start_roll = game_state.start_roll;
-                self.send_status_message(player_id, GameMessage::StartRoll(start_roll.to_string()))
-                    .await;
-            } else if !game_state.game_start && game_state.player_1 == player_id {
-                self.send_status_message(player_id, GameMessage::P1Join)
-                    .await;
-                let start_roll = game_state.start_roll;
-                self.send_status_message(player_id, GameMessage::StartRoll(start_roll.to_string()))
-                    .await;
-            } else if game_state.player_1 == player_id && game_state.game_start {
-                self.send_status_message(player_id, GameMessage::Reconnect)
-                    .await;
-                let msg = GameMessage::Status(format!("{P1} \u{1F3B2}"));
-                self.send_status_message(player_id, msg).await;
-            } else if game_state.player_2.unwrap() == player_id && game_state.game_start {
-                self.send_status_message(player_id, GameMessage::Reconnect)
-                    .await;
+            .filter(|(_, game_state)| game_state.turn_deadline.is_some_and(|at| at <= now))
+            .map(|(game_id, _)| game_id.clone())
+            .collect();
 
-                let msg = GameMessage::Status(format!("{P2} \u{1F3B2}"));
-                self.send_status_message(player_id, msg).await;
-            } else {
-                self.send_status_message(player_id, GameMessage::Reconnect)
-                    .await;
-                self.send_status_message(player_id, GameMessage::Spectate)
-                    .await;
+        for game_id in timed_out {
+            if AssertUnwindSafe(self.time_out(game_id.clone()))
+                .catch_unwind()
+                .await
+                .is_err()
+            {
+                self.fail_room(&game_id);
+                continue;
             }
+            self.save_room(&game_id);
+        }
+    }
 
-            println!("game_state {:?}", game_state);
+    async fn time_out(&mut self, game_id: GameId) {
+        let Some(game_state) = self.game_rooms.get_mut(&game_id) else {
+            return;
+        };
+        game_state.turn_deadline = None;
+        let seat = game_state.rules.turn();
+        let Some(player_id) = game_state.player(seat) else {
+            return;
+        };
+        println!("turn clock ran out for {player_id} in {game_id}");
+        game_state.push_event(GameEvent::TimedOut { seat });
 
-            let start_roll = game_state.start_roll;
-            self.send_status_message(player_id, GameMessage::StartRoll(start_roll.to_string()))
-                .await;
-        } else {
-            let start_roll = state
-                .read()
-                .unwrap()
-                .start_roll
-                .get(&game_id_clone)
-                .map(|s| s.trim().parse::<u32>().unwrap_or_default())
-                .unwrap_or_default();
+        //act exactly as if the player had pressed the button
+        let action = match game_state.settings.on_timeout {
+            OnTimeout::AutoRoll => Action::Roll,
+            OnTimeout::Forfeit => Action::Forfeit,
+        };
+        self.action(player_id, game_id, action).await;
+    }
 
-            //if start roll contains the game_id then make a new game, if not redirect to 404
-            if start_roll != 0 {
-                let game_score = GameScore {
-                    client_feed: Vec::new(),
-                };
+    //drop stale invite links and close rooms that have gone idle
+    fn sweep(&mut self) {
+        let now = now();
 
-                let game_state_new = GameState {
-                    roll: start_roll,
-                    player_1: player_id,
-                    player_2: None,
-                    player_turn: player_id.to_string(),
-                    game_start: false,
-                    start_roll,
-                    start_player: player_id,
-                    game_over: false,
-                    game_score,
-                    p1_overall: 0,
-                    p2_overall: 0,
-                };
-                println!("NEW GAME ADDED {:?}", game_state_new);
+        let expired_start_rolls: Vec<GameId> = {
+            let mut start_rolls = self
+                .start_rolls
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            let expired: Vec<GameId> = start_rolls
+                .start_roll
+                .iter()
+                .filter(|(game_id, _)| shard_for(game_id, self.shards) == self.shard)
+                .filter(|(_, pending)| {
+                    Duration::from_millis(now.saturating_sub(pending.created_at)) > START_ROLL_TTL
+                })
+                .map(|(game_id, _)| game_id.clone())
+                .collect();
+            for game_id in &expired {
+                start_rolls.start_roll.remove(game_id);
+            }
+            expired
+        };
+        for game_id in expired_start_rolls {
+            self.room_writer.remove_start_roll(&game_id);
+        }
 
-                self.game_rooms.insert(game_id_clone, game_state_new);
+        let expired_rooms: Vec<GameId> = self
+            .game_rooms
+            .iter()
+            .filter(|(_, game_state)| game_state.expired(now))
+            .map(|(game_id, _)| game_id.clone())
+            .collect();
+        for game_id in expired_rooms {
+            self.close_room(&game_id);
+        }
+    }
 
-                self.send_status_message(player_id, GameMessage::P1Join)
-                    .await;
-                //display start roll
+    //something panicked inside this room, its state can't be trusted anymore
+    fn fail_room(&mut self, game_id: &str) {
+        println!("room {game_id} crashed, closing it");
 
-                self.send_status_message(player_id, GameMessage::StartRoll(start_roll.to_string()))
-                    .await;
-            } else {
-                self.send_status_message(player_id, GameMessage::NoGameFound)
-                    .await;
+        let code = ErrorCode::RoomCrashed;
+        let msg = serde_json::to_string(&GameMessage::Error {
+            code,
+            message: code.message().to_string(),
+        })
+        .unwrap();
+        if let Some(players) = self.players.get(game_id) {
+            for player_id in players {
+                for tx in self.room_sessions(game_id, *player_id) {
+                    let _ = tx.send(msg.clone());
+                }
             }
         }
 
-        self.game_rooms
-            .entry(game_id_clone_2)
-            .and_modify(|game_state| {
-                if game_state.game_start {
-                    if player_id == game_state.player_1 {
-                        let msg = format!("{P1} has joined the game");
+        self.close_room(game_id);
+    }
 
-                        game_state.game_score.client_feed.push(msg);
-                    } else if player_id == game_state.player_2.unwrap() {
-                        let msg = format!("{P2} has joined the game");
+    fn close_room(&mut self, game_id: &str) {
+        let Some(game_state) = self.game_rooms.remove(game_id) else {
+            return;
+        };
+        println!("closing room {game_id}");
+        self.lobby.remove(game_id);
 
-                        game_state.game_score.client_feed.push(msg);
-                    }
+        //the game never finished, everyone gets their stake back. nobody is left to tell,
+        //so the loop doesn't wait for it
+        if !game_state.escrow.is_empty() {
+            let ledger = Arc::clone(&self.ledger);
+            let game_id = game_id.to_string();
+            let (escrow, wager) = (game_state.escrow.clone(), game_state.settings.wager);
+            task::spawn_blocking(move || {
+                let refund = ledger
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .refund(&game_id, &escrow, wager);
+                if refund.is_err() {
+                    println!("stakes in {game_id} were not refunded: {escrow:?}");
                 }
             });
-        self.update_game_feed(&game_id_clone_3).await;
-
-        player_id
-    }
+        }
 
-    async fn disconnect(&mut self, player_id: PlayerId, game_id: GameId) {
-        println!("session closed");
-        if self.sessions.remove(&player_id).is_some() {
-            self.game_rooms
-                .entry(game_id.clone())
-                .and_modify(|game_state| {
-                    if player_id == game_state.player_1 {
-                        let msg = format!("{P1} has left the game");
-
-                        game_state.game_score.client_feed.push(msg);
-                    } else if player_id == game_state.player_2.unwrap() {
-                        let msg = format!("{P2} has left the game");
-
-                        game_state.game_score.client_feed.push(msg);
-                    }
-                });
-            for sessions in self.players.values_mut() {
-                sessions.remove(&player_id);
+        //let anyone still watching know the room is gone
+        let msg = serde_json::to_string(&GameMessage::RoomClosed).unwrap();
+        if let Some(players) = self.players.remove(game_id) {
+            for player_id in players {
+                for tx in self.room_sessions(game_id, player_id) {
+                    let _ = tx.send(msg.clone());
+                }
             }
-            self.update_game_feed(&game_id).await;
         }
-    }
-}
-
-async fn roll_die(num: u32) -> u32 {
-    let mut rng = rand::thread_rng();
 
-    rng.gen_range(1..=num)
+        //keep finished series around on disk, rooms where nobody played are just dropped
+        if game_state.games_played() > 0 {
+            self.room_writer.archive(game_id, game_state);
+        } else {
+            self.room_writer.remove(game_id);
+        }
+    }
+
+    //a public room is listed until its last seat is taken
+    fn update_lobby(&self, game_id: &str) {
+        let Some(game_state) = self.game_rooms.get(game_id) else {
+            self.lobby.remove(game_id);
+            return;
+        };
+        let joined = game_state.seats.len();
+        let seats = game_state.rules.seats();
+        if !game_state.settings.public
+            || game_state.rules.phase() != Phase::Waiting
+            || joined >= seats
+        {
+            self.lobby.remove(game_id);
+            return;
+        }
+
+        let spectators = self.players.get(game_id).map_or(0, |players| {
+            players
+                .iter()
+                .filter(|player_id| game_state.seat_of(**player_id).is_none())
+                .count()
+        });
+        let room = LobbyRoom {
+            game_id: game_id.to_string(),
+            start_roll: game_state.rules.start_roll(),
+            joined,
+            seats,
+            spectators,
+            age_secs: 0,
+            best_of: game_state.settings.best_of,
+            wager: game_state.settings.wager,
+        };
+        self.lobby.upsert(game_state.created_at, room);
+    }
+
+    fn save_room(&self, game_id: &str) {
+        if let Some(game_state) = self.game_rooms.get(game_id) {
+            self.room_writer.save(game_id, game_state);
+        }
+    }
+
+    async fn update_game_feed(&mut self, game_id: &str) {
+        self.send_feed(game_id, None).await;
+    }
+
+    //only the new events go out, `skip` is a socket that was just caught up with `GameState::resume`
+    async fn send_feed(&mut self, game_id: &str, skip: Option<&mpsc::UnboundedSender<Msg>>) {
+        let Some(game) = self.game_rooms.get_mut(game_id) else {
+            return;
+        };
+        let unsent = game.take_unsent();
+        if unsent.is_empty() {
+            return;
+        }
+
+        let msg = GameMessage::FeedDelta(unsent);
+
+        if let Some(game) = self.players.get(game_id) {
+            for player_ids in game {
+                for tx in self.room_sessions(game_id, *player_ids) {
+                    if skip.is_some_and(|skip| skip.same_channel(tx)) {
+                        continue;
+                    }
+                    let _ = tx.send(serde_json::to_string(&msg).unwrap());
+                }
+            }
+        }
+    }
+
+    async fn broadcast_clock(&self, game_id: &str) {
+        let Some(game_state) = self.game_rooms.get(game_id) else {
+            return;
+        };
+        let msg = GameMessage::TurnClock(game_state.turn_clock());
+
+        self.send_to_room(game_id, msg).await;
+    }
+
+    //tell every seated player how the game ended
+    async fn game_over(&self, game_id: &str, winner: Seat) {
+        let Some(game_state) = self.game_rooms.get(game_id) else {
+            return;
+        };
+
+        for (seat, player_id) in game_state.seats.iter().enumerate() {
+            let msg = if seat == winner {
+                format!("{} \u{1F3C6}", self.seat_name(game_id, winner))
+            } else {
+                format!("{} \u{1F480}", self.seat_name(game_id, seat))
+            };
+            self.send_status_message(game_id, *player_id, GameMessage::GameOver(msg))
+                .await;
+        }
+    }
+
+    //status lines after a roll or a forfeit, the next player is told it's their turn
+    async fn announce(&self, game_id: &str, outcome: Outcome) {
+        let (msg, next) = match outcome {
+            Outcome::Rolled {
+                seat, roll, next, ..
+            } => (
+                format!("{} \u{1F3B2} {roll}", self.seat_name(game_id, seat)),
+                next,
+            ),
+            Outcome::Eliminated { seat, next, .. } => (
+                format!("{} \u{1F480} is out", self.seat_name(game_id, seat)),
+                next,
+            ),
+            Outcome::Died { winner, .. } => {
+                self.game_over(game_id, winner).await;
+                return;
+            }
+        };
+        let Some(next_player) = self
+            .game_rooms
+            .get(game_id)
+            .and_then(|game_state| game_state.player(next))
+        else {
+            return;
+        };
+
+        self.send_to_other(game_id, GameMessage::Status(msg), next_player)
+            .await;
+        let msg = GameMessage::Status(format!(
+            "{} \u{1F3B2} It's your roll!",
+            self.seat_name(game_id, next)
+        ));
+        self.send_status_message(game_id, next_player, msg).await;
+    }
+
+    //who starts the game that just began, `kind` wraps the line for the client
+    async fn announce_start(&self, game_id: &str, kind: fn(String) -> GameMessage) {
+        let Some(game_state) = self.game_rooms.get(game_id) else {
+            return;
+        };
+        let first = game_state.rules.turn();
+        let players = self.seat_profiles(game_id);
+
+        for (seat, player_id) in game_state.seats.iter().enumerate() {
+            let msg = if seat == first {
+                format!("{} \u{1F3B2} roll to start", seat_name(&players, first))
+            } else {
+                format!(
+                    "{} \u{1F3B2} waiting for {} to roll",
+                    seat_name(&players, seat),
+                    seat_name(&players, first)
+                )
+            };
+            self.send_status_message(game_id, *player_id, kind(msg))
+                .await;
+        }
+    }
+
+    //who sits where, players without a profile keep their seat's emoji
+    fn seat_profiles(&self, game_id: &str) -> Vec<Profile> {
+        let Some(game_state) = self.game_rooms.get(game_id) else {
+            return Vec::new();
+        };
+        let profiles = self.profiles.read().unwrap_or_else(PoisonError::into_inner);
+
+        game_state
+            .seats
+            .iter()
+            .enumerate()
+            .map(|(seat, player_id)| {
+                profiles
+                    .get(*player_id)
+                    .cloned()
+                    .unwrap_or_else(|| Profile::for_seat(seat))
+            })
+            .collect()
+    }
+
+    fn seat_name(&self, game_id: &str, seat: Seat) -> String {
+        seat_name(&self.seat_profiles(game_id), seat)
+    }
+
+    async fn send_to_room(&self, game_id: &str, msg: GameMessage) {
+        if let Some(players) = self.players.get(game_id) {
+            for player_id in players {
+                self.send_status_message(game_id, *player_id, msg.clone())
+                    .await;
+            }
+        }
+    }
+
+    async fn send_to_other(&self, game_id: &str, msg: GameMessage, player_id: Uuid) {
+        if let Some(game) = self.players.get(game_id) {
+            for player_ids in game {
+                if *player_ids != player_id {
+                    for tx in self.room_sessions(game_id, *player_ids) {
+                        let _ = tx.send(serde_json::to_string(&msg).unwrap());
+                    }
+                }
+            }
+        }
+    }
+
+    //the sockets `player_id` has open on `game_id`, their tabs on other rooms of this shard are left out
+    fn room_sessions<'a>(
+        &'a self,
+        game_id: &'a str,
+        player_id: PlayerId,
+    ) -> impl Iterator<Item = &'a mpsc::UnboundedSender<Msg>> {
+        self.sessions
+            .get(&player_id)
+            .into_iter()
+            .flatten()
+            .filter(move |session| session.game_id == game_id)
+            .map(|session| &session.tx)
+    }
+
+    async fn send_status_message(&self, game_id: &str, player_id: PlayerId, msg: GameMessage) {
+        for tx in self.room_sessions(game_id, player_id) {
+            let _ = tx.send(serde_json::to_string(&msg).unwrap());
+        }
+    }
+
+    async fn send_error(&self, game_id: &str, player_id: PlayerId, code: ErrorCode) {
+        let msg = GameMessage::Error {
+            code,
+            message: code.message().to_string(),
+        };
+        self.send_status_message(game_id, player_id, msg).await;
+    }
+
+    //gold is the same in every room, every tab the player has open hears about it
+    async fn send_balance(&self, player_id: PlayerId) {
+        let balance = self
+            .with_ledger(move |ledger| ledger.balance(player_id))
+            .await;
+        let msg = serde_json::to_string(&GameMessage::Balance(balance)).unwrap();
+        if let Some(cmd_tx) = self.sessions.get(&player_id) {
+            for Session { tx, .. } in cmd_tx {
+                let _ = tx.send(msg.clone());
+            }
+        }
+    }
+
+    //put the room's wager for each of `seats` in the pot, all of them or none
+    async fn escrow(&mut self, game_id: &str, seats: &[(Seat, PlayerId)]) -> Result<(), ErrorCode> {
+        let amount = self
+            .game_rooms
+            .get(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?
+            .settings
+            .wager;
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let players: Vec<PlayerId> = seats.iter().map(|(_, player_id)| *player_id).collect();
+        let (game, stakes) = (game_id.to_string(), players.clone());
+        self.with_ledger(move |ledger| ledger.escrow(&game, &stakes, amount))
+            .await?;
+        let game_state = self
+            .game_rooms
+            .get_mut(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?;
+        for (seat, player_id) in seats {
+            game_state.escrow.push(*player_id);
+            game_state.push_event(GameEvent::Escrowed {
+                seat: *seat,
+                amount,
+            });
+        }
+
+        for player_id in players {
+            self.send_balance(player_id).await;
+        }
+
+        Ok(())
+    }
+
+    //a game just ended, settle the wager and the ratings
+    async fn finish_game(&mut self, game_id: &str, winner: Seat) {
+        self.pay_out(game_id, winner).await;
+        self.rate_game(game_id, winner);
+        self.record_game(game_id, winner);
+    }
+
+    //the game that just ended goes into the match history of everyone who played it,
+    //with a replay of its part of the feed
+    fn record_game(&self, game_id: &str, winner: Seat) {
+        let Some(game_state) = self.game_rooms.get(game_id) else {
+            return;
+        };
+        let Some(game) = series_games(&game_state.game_score.feed).pop() else {
+            return;
+        };
+        let players = game_state
+            .seats
+            .iter()
+            .zip(self.seat_profiles(game_id))
+            .map(|(player_id, profile)| MatchPlayer {
+                id: public_id(*player_id).to_string(),
+                profile,
+            })
+            .collect();
+
+        let record = MatchRecord {
+            id: Uuid::new_v4().simple().to_string(),
+            game_id: game_id.to_string(),
+            players,
+            start_roll: game_state.rules.start_roll(),
+            rolls: game.rolls,
+            winner,
+            started_at: game_state.game_started_at,
+            finished_at: now(),
+        };
+        let replay = Replay {
+            version: REPLAY_VERSION,
+            game: record,
+            events: replay_events(&game_state.game_score.feed),
+        };
+        //the replay and the history line are written off the loop
+        let history = Arc::clone(&self.history);
+        task::spawn_blocking(move || History::record(&history, replay));
+    }
+
+    fn rate_game(&self, game_id: &str, winner: Seat) {
+        let Some(game_state) = self.game_rooms.get(game_id) else {
+            return;
+        };
+        let Some(winner_id) = game_state.player(winner) else {
+            return;
+        };
+        let losers: Vec<PlayerId> = game_state
+            .seats
+            .iter()
+            .copied()
+            .filter(|player_id| *player_id != winner_id)
+            .collect();
+
+        self.ratings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(game_state.rules.start_roll(), winner_id, &losers);
+        let ratings = Arc::clone(&self.ratings);
+        task::spawn_blocking(move || Ratings::save(&ratings));
+    }
+
+    //the whole pot goes to the winner in one ledger entry
+    async fn pay_out(&mut self, game_id: &str, winner: Seat) {
+        let Some(game_state) = self.game_rooms.get(game_id) else {
+            return;
+        };
+        let Some(winner_id) = game_state.player(winner) else {
+            return;
+        };
+        if game_state.escrow.is_empty() {
+            return;
+        }
+
+        let amount = game_state.settings.wager * game_state.escrow.len() as u64;
+        let game = game_id.to_string();
+        let paid = self
+            .with_ledger(move |ledger| ledger.pay_out(&game, winner_id, amount))
+            .await;
+        //the stakes stay held, they're refunded if the room closes before a payout goes through
+        if paid.is_err() {
+            return;
+        }
+        let Some(game_state) = self.game_rooms.get_mut(game_id) else {
+            return;
+        };
+        game_state.escrow.clear();
+        game_state.push_event(GameEvent::PaidOut {
+            seat: winner,
+            amount,
+        });
+
+        self.send_balance(winner_id).await;
+    }
+
+    //ledger changes are appended to the store before they count, that write happens on a
+    //blocking thread so the shard's loop waits for it without holding up the other shards
+    async fn with_ledger<T: Send + 'static>(
+        &self,
+        change: impl FnOnce(&mut Ledger) -> T + Send + 'static,
+    ) -> T {
+        let ledger = Arc::clone(&self.ledger);
+        let change = task::spawn_blocking(move || {
+            change(&mut ledger.lock().unwrap_or_else(PoisonError::into_inner))
+        });
+        match change.await {
+            Ok(result) => result,
+            //a panic in there is this room's panic, handle_command closes the room
+            Err(err) => panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    //count a command against the player's budget, false once they're over it
+    fn allow_action(&mut self, player_id: PlayerId) -> bool {
+        let now = now();
+        let window = self.action_counts.entry(player_id).or_insert((now, 0));
+        if Duration::from_millis(now.saturating_sub(window.0)) > ACTION_WINDOW {
+            *window = (now, 0);
+        }
+        window.1 += 1;
+
+        window.1 <= ACTION_LIMIT
+    }
+
+    //the player is told when they're over the limit
+    async fn allow_command(&mut self, player_id: PlayerId, game_id: &str) -> bool {
+        if self.allow_action(player_id) {
+            return true;
+        }
+        self.send_error(game_id, player_id, ErrorCode::RateLimited)
+            .await;
+
+        false
+    }
+
+    async fn player_action(&mut self, player_id: PlayerId, game_id: GameId, action: Action) {
+        if self.allow_command(player_id, &game_id).await {
+            self.action(player_id, game_id, action).await;
+        }
+    }
+
+    async fn action(&mut self, player_id: PlayerId, game_id: GameId, action: Action) {
+        let result = match action {
+            Action::Join => self.join(player_id, &game_id).await,
+            Action::Roll => self.roll(player_id, &game_id).await,
+            Action::RequestRematch => self.request_rematch(player_id, &game_id).await,
+            Action::AcceptRematch => self.accept_rematch(player_id, &game_id).await,
+            Action::Forfeit => self.forfeit(player_id, &game_id).await,
+        };
+
+        match result {
+            Ok(()) => self.broadcast_clock(&game_id).await,
+            Err(code) => {
+                println!("{action:?} from {player_id} in {game_id} refused: {code:?}");
+                self.send_error(&game_id, player_id, code).await;
+            }
+        }
+    }
+
+    async fn join(&mut self, player_id: PlayerId, game_id: &str) -> Result<(), ErrorCode> {
+        let game_state = self
+            .game_rooms
+            .get_mut(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?;
+        if game_state.seat_of(player_id).is_some() {
+            return Err(ErrorCode::AlreadySeated);
+        }
+        let seats = game_state.rules.seats();
+        if game_state.rules.phase() != Phase::Waiting || game_state.seats.len() >= seats {
+            return Err(ErrorCode::RoomFull);
+        }
+        if !admits(&game_state.reserved, player_id) {
+            return Err(ErrorCode::RoomFull);
+        }
+
+        //no seat without the stake, everyone in the room sees it go in the pot
+        let seat = game_state.seats.len();
+        self.escrow(game_id, &[(seat, player_id)]).await?;
+        self.update_game_feed(game_id).await;
+        let game_state = self
+            .game_rooms
+            .get_mut(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?;
+        game_state.seats.push(player_id);
+        let joined = game_state.seats.len();
+        let players = GameMessage::Players(self.seat_profiles(game_id));
+        self.send_to_room(game_id, players).await;
+        self.send_status_message(game_id, player_id, GameMessage::YourSeat(seat))
+            .await;
+        let Some(game_state) = self.game_rooms.get_mut(game_id) else {
+            return Err(ErrorCode::RoomNotFound);
+        };
+        if joined < seats {
+            //still filling up, the new player waits with everyone else
+            self.send_status_message(game_id, player_id, GameMessage::P1Join)
+                .await;
+            self.send_to_room(game_id, GameMessage::Seats { joined, seats })
+                .await;
+            return Ok(());
+        }
+
+        game_state.lock_client_seeds();
+        game_state.rules.start()?;
+        game_state.game_started_at = now();
+        game_state.restart_clock();
+
+        self.announce_start(game_id, GameMessage::StartGame).await;
+
+        Ok(())
+    }
+
+    async fn roll(&mut self, player_id: PlayerId, game_id: &str) -> Result<(), ErrorCode> {
+        let game_state = self
+            .game_rooms
+            .get_mut(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?;
+        let seat = game_state.seat_of(player_id).ok_or(ErrorCode::NotAPlayer)?;
+        let (outcome, nonce) = game_state.roll(seat)?;
+
+        match outcome {
+            Outcome::Rolled {
+                seat,
+                roll,
+                roll_between,
+                ..
+            } => {
+                game_state.push_event(GameEvent::Rolled {
+                    seat,
+                    roll,
+                    roll_between,
+                    nonce,
+                });
+            }
+            Outcome::Eliminated {
+                seat, roll_between, ..
+            } => {
+                game_state.push_event(GameEvent::Eliminated {
+                    seat,
+                    roll_between,
+                    nonce,
+                });
+            }
+            Outcome::Died {
+                seat,
+                roll_between,
+                winner,
+            } => {
+                //deathroll feed update
+                let score = game_state.rules.scores().to_vec();
+                game_state.push_event(GameEvent::Died {
+                    seat,
+                    roll_between,
+                    winner,
+                    score,
+                    nonce,
+                });
+                game_state.reveal_seed();
+                game_state.end_series();
+            }
+        }
+        game_state.restart_clock();
+
+        if let Outcome::Died { winner, .. } = outcome {
+            self.finish_game(game_id, winner).await;
+        }
+
+        self.announce(game_id, outcome).await;
+        self.update_game_feed(game_id).await;
+
+        Ok(())
+    }
+
+    async fn request_rematch(
+        &mut self,
+        player_id: PlayerId,
+        game_id: &str,
+    ) -> Result<(), ErrorCode> {
+        let game_state = self
+            .game_rooms
+            .get_mut(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?;
+        let seat = game_state.seat_of(player_id).ok_or(ErrorCode::NotAPlayer)?;
+        if game_state.rules.phase() != Phase::GameOver {
+            return Err(ErrorCode::GameNotOver);
+        }
+        if game_state.rules.series_winner().is_some() {
+            return Err(ErrorCode::SeriesOver);
+        }
+        //a second click (from anyone) must not start anything
+        if game_state.rematch_requested.is_some() {
+            return Err(ErrorCode::RematchAlreadyRequested);
+        }
+
+        game_state.rematch_requested = Some(seat);
+        game_state.push_event(GameEvent::RematchRequested { seat });
+        let others: Vec<PlayerId> = game_state
+            .seats
+            .iter()
+            .copied()
+            .filter(|other| *other != player_id)
+            .collect();
+
+        self.send_status_message(game_id, player_id, GameMessage::RematchRequested)
+            .await;
+        for other in others {
+            self.send_status_message(game_id, other, GameMessage::RematchOffered)
+                .await;
+        }
+        self.update_game_feed(game_id).await;
+
+        Ok(())
+    }
+
+    async fn accept_rematch(
+        &mut self,
+        player_id: PlayerId,
+        game_id: &str,
+    ) -> Result<(), ErrorCode> {
+        let game_state = self
+            .game_rooms
+            .get_mut(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?;
+        let seat = game_state.seat_of(player_id).ok_or(ErrorCode::NotAPlayer)?;
+        if game_state.rules.phase() != Phase::GameOver {
+            return Err(ErrorCode::GameNotOver);
+        }
+        //anyone but the player who asked can accept
+        match game_state.rematch_requested {
+            Some(requested) if requested != seat => {}
+            _ => return Err(ErrorCode::NoRematchOffer),
+        }
+
+        //every seat stakes the wager again, nobody plays if someone can't cover it
+        let seats: Vec<(Seat, PlayerId)> = game_state.seats.iter().copied().enumerate().collect();
+        match self.escrow(game_id, &seats).await {
+            Err(ErrorCode::InsufficientGold) => {
+                return self.short_of_the_wager(player_id, game_id, &seats).await;
+            }
+            result => result?,
+        }
+        let game_state = self
+            .game_rooms
+            .get_mut(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?;
+        game_state.lock_client_seeds();
+        let start_seat = game_state.rules.rematch()?;
+        game_state.rematch_requested = None;
+        game_state.game_started_at = now();
+
+        let start_roll = game_state.rules.start_roll();
+        game_state.push_event(GameEvent::NewGame {
+            start_roll,
+            start_seat,
+        });
+        game_state.restart_clock();
+
+        self.update_game_feed(game_id).await;
+        self.announce_start(game_id, GameMessage::Status).await;
+
+        Ok(())
+    }
+
+    //the error goes to whoever can't cover the rematch, not to the player who accepted it,
+    //and the room is told who is short
+    async fn short_of_the_wager(
+        &mut self,
+        player_id: PlayerId,
+        game_id: &str,
+        seats: &[(Seat, PlayerId)],
+    ) -> Result<(), ErrorCode> {
+        let amount = self
+            .game_rooms
+            .get(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?
+            .settings
+            .wager;
+        let seats = seats.to_vec();
+        let short: Vec<(Seat, PlayerId)> = self
+            .with_ledger(move |ledger| {
+                seats
+                    .into_iter()
+                    .filter(|(_, player_id)| ledger.balance(*player_id) < amount)
+                    .collect()
+            })
+            .await;
+
+        for (seat, short_id) in &short {
+            let name = self.seat_name(game_id, *seat);
+            let msg = GameMessage::Status(format!("{name} can't cover the wager"));
+            self.send_to_room(game_id, msg).await;
+            if *short_id != player_id {
+                self.send_error(game_id, *short_id, ErrorCode::InsufficientGold)
+                    .await;
+            }
+        }
+
+        if short.iter().any(|(_, short_id)| *short_id == player_id) {
+            return Err(ErrorCode::InsufficientGold);
+        }
+
+        Ok(())
+    }
+
+    async fn forfeit(&mut self, player_id: PlayerId, game_id: &str) -> Result<(), ErrorCode> {
+        let game_state = self
+            .game_rooms
+            .get_mut(game_id)
+            .ok_or(ErrorCode::RoomNotFound)?;
+        let seat = game_state.seat_of(player_id).ok_or(ErrorCode::NotAPlayer)?;
+        let outcome = game_state.forfeit(seat)?;
+        game_state.restart_clock();
+
+        if let Outcome::Died { winner, .. } = outcome {
+            self.finish_game(game_id, winner).await;
+        }
+
+        self.announce(game_id, outcome).await;
+        self.update_game_feed(game_id).await;
+
+        Ok(())
+    }
+
+    fn find_room(&self, game_id: &str) -> Option<&GameState> {
+        match self.room_lookup {
+            RoomLookup::Exact => self.game_rooms.get(game_id),
+            #[cfg(feature = "single-loop")]
+            RoomLookup::Linear => self
+                .game_rooms
+                .iter()
+                .find_map(|(game, game_state)| game.contains(game_id).then_some(game_state)),
+        }
+    }
+
+    async fn connect(
+        &mut self,
+        tx: mpsc::UnboundedSender<Msg>,
+        game_id: String,
+        player_id: Uuid,
+        last_seq: Option<u64>,
+    ) -> PlayerId {
+        let resume_tx = tx.clone();
+        let session = Session {
+            game_id: game_id.clone(),
+            tx,
+        };
+        if let Some(value) = self.sessions.get_mut(&player_id) {
+            // If session exists then push new tx to vec (fix opening multiple tabs of the same game)
+            value.push(session);
+        } else {
+            let tx_vec = vec![session];
+            self.sessions.insert(player_id, tx_vec);
+        }
+
+        let game_id_clone = game_id.clone();
+        let game_id_clone_2 = game_id.clone();
+        let game_id_clone_3 = game_id.clone();
+
+        self.players.entry(game_id).or_default().insert(player_id);
+        //a matched player sits down as soon as they arrive
+        let mut auto_join = false;
+
+        if let Some(game_state) = self.find_room(&game_id_clone) {
+            let game_start = game_state.rules.phase() != Phase::Waiting;
+            match (game_start, game_state.seat_of(player_id)) {
+                (false, None) if game_state.reserved.contains(&player_id) => auto_join = true,
+                //anyone without a seat gets the join screen while the room fills up
+                (false, None) => {
+                    self.send_status_message(&game_id_clone_3, player_id, GameMessage::P2Join)
+                        .await;
+                }
+                (false, Some(_)) => {
+                    self.send_status_message(&game_id_clone_3, player_id, GameMessage::P1Join)
+                        .await;
+                }
+                (true, Some(seat)) => {
+                    self.send_status_message(&game_id_clone_3, player_id, GameMessage::Reconnect)
+                        .await;
+                    let name = self.seat_name(&game_id_clone, seat);
+                    let msg = GameMessage::Status(format!("{name} \u{1F3B2}"));
+                    self.send_status_message(&game_id_clone_3, player_id, msg)
+                        .await;
+                }
+                (true, None) => {
+                    self.send_status_message(&game_id_clone_3, player_id, GameMessage::Reconnect)
+                        .await;
+                    self.send_status_message(&game_id_clone_3, player_id, GameMessage::Spectate)
+                        .await;
+                }
+            }
+            if !game_start {
+                let msg = GameMessage::Seats {
+                    joined: game_state.seats.len(),
+                    seats: game_state.rules.seats(),
+                };
+                self.send_status_message(&game_id_clone_3, player_id, msg)
+                    .await;
+            }
+
+            let start_roll = game_state.rules.start_roll();
+            self.send_status_message(
+                &game_id_clone_3,
+                player_id,
+                GameMessage::StartRoll(start_roll.to_string()),
+            )
+            .await;
+        } else {
+            let pending = self
+                .start_rolls
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .start_roll
+                .get(&game_id_clone)
+                .cloned();
+            let start_roll = pending
+                .as_ref()
+                .map(|pending| pending.start_roll())
+                .unwrap_or_default();
+            let (settings, reserved) = pending
+                .map(|pending| (pending.settings, pending.reserved))
+                .unwrap_or_default();
+            let settings_seats = settings.seats;
+
+            //if start roll contains the game_id then make a new game, if not redirect to 404
+            //a matched room can only be opened by one of its players
+            let rules = Deathroll::with_seats(start_roll, settings.seats)
+                .and_then(|rules| rules.with_best_of(settings.best_of))
+                .ok()
+                .filter(|_| admits(&reserved, player_id));
+            if let Some(rules) = rules {
+                let mut game_state_new = GameState::new(rules, player_id, settings, reserved);
+
+                //the creator stakes first, the invite stays unclaimed if they can't
+                let wager = game_state_new.settings.wager;
+                if wager > 0 {
+                    let game_id = game_id_clone.clone();
+                    let escrow = self
+                        .with_ledger(move |ledger| ledger.escrow(&game_id, &[player_id], wager))
+                        .await;
+                    if let Err(code) = escrow {
+                        self.send_error(&game_id_clone_3, player_id, code).await;
+                        self.players.remove(&game_id_clone);
+                        return player_id;
+                    }
+                    game_state_new.escrow.push(player_id);
+                    game_state_new.push_event(GameEvent::Escrowed {
+                        seat: 0,
+                        amount: wager,
+                    });
+                }
+
+                //the invite link has been claimed, the room takes over from here
+                self.start_rolls
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .start_roll
+                    .remove(&game_id_clone);
+                self.room_writer.remove_start_roll(&game_id_clone);
+
+                self.game_rooms.insert(game_id_clone, game_state_new);
+
+                self.send_status_message(&game_id_clone_3, player_id, GameMessage::P1Join)
+                    .await;
+                let msg = GameMessage::Seats {
+                    joined: 1,
+                    seats: settings_seats,
+                };
+                self.send_status_message(&game_id_clone_3, player_id, msg)
+                    .await;
+                //display start roll
+
+                self.send_status_message(
+                    &game_id_clone_3,
+                    player_id,
+                    GameMessage::StartRoll(start_roll.to_string()),
+                )
+                .await;
+            } else {
+                self.send_status_message(&game_id_clone_3, player_id, GameMessage::NoGameFound)
+                    .await;
+                self.players.remove(&game_id_clone);
+                return player_id;
+            }
+        }
+
+        self.game_rooms
+            .entry(game_id_clone_2)
+            .and_modify(|game_state| {
+                if game_state.rules.phase() != Phase::Waiting {
+                    if let Some(seat) = game_state.seat_of(player_id) {
+                        game_state.push_event(GameEvent::Joined { seat });
+                    }
+                }
+            });
+        //the new socket only gets what it missed, everyone else sees the join
+        if let Some(game_state) = self.game_rooms.get(&game_id_clone_3) {
+            let msg = game_state.resume(last_seq);
+            let _ = resume_tx.send(serde_json::to_string(&msg).unwrap());
+        }
+        self.send_feed(&game_id_clone_3, Some(&resume_tx)).await;
+        let players = GameMessage::Players(self.seat_profiles(&game_id_clone_3));
+        self.send_status_message(&game_id_clone_3, player_id, players)
+            .await;
+        if let Some(game_state) = self.game_rooms.get(&game_id_clone_3) {
+            if let Some(seat) = game_state.seat_of(player_id) {
+                self.send_status_message(&game_id_clone_3, player_id, GameMessage::YourSeat(seat))
+                    .await;
+            }
+            let msg = GameMessage::TurnClock(game_state.turn_clock());
+            self.send_status_message(&game_id_clone_3, player_id, msg)
+                .await;
+
+            //pick an open rematch offer back up after a reload
+            if let (Some(requested), Some(seat)) =
+                (game_state.rematch_requested, game_state.seat_of(player_id))
+            {
+                let msg = if requested == seat {
+                    GameMessage::RematchRequested
+                } else {
+                    GameMessage::RematchOffered
+                };
+                self.send_status_message(&game_id_clone_3, player_id, msg)
+                    .await;
+            }
+        }
+        self.send_balance(player_id).await;
+
+        if auto_join {
+            match self.join(player_id, &game_id_clone_3).await {
+                Ok(()) => self.broadcast_clock(&game_id_clone_3).await,
+                Err(code) => self.send_error(&game_id_clone_3, player_id, code).await,
+            }
+        }
+
+        player_id
+    }
+
+    async fn client_seed(&mut self, player_id: PlayerId, game_id: GameId, seed: String) {
+        if !fair::valid_client_seed(&seed) {
+            self.send_error(&game_id, player_id, ErrorCode::InvalidClientSeed)
+                .await;
+            return;
+        }
+        if let Some(game_state) = self.game_rooms.get_mut(&game_id) {
+            game_state.client_seeds.insert(player_id, seed);
+        }
+    }
+
+    //only `player_tx`'s socket closed, the player hasn't left while another tab has the game open
+    async fn disconnect(
+        &mut self,
+        player_id: PlayerId,
+        game_id: GameId,
+        player_tx: mpsc::UnboundedSender<Msg>,
+    ) {
+        let Some(sessions) = self.sessions.get_mut(&player_id) else {
+            return;
+        };
+        let open = sessions.len();
+        sessions.retain(|session| !session.tx.same_channel(&player_tx));
+        //WsMsg::Close and the socket ending both get here
+        if sessions.len() == open {
+            return;
+        }
+        println!("session closed");
+        let still_here = sessions.iter().any(|session| session.game_id == game_id);
+        if sessions.is_empty() {
+            self.sessions.remove(&player_id);
+            self.action_counts.remove(&player_id);
+        }
+        if still_here {
+            return;
+        }
+
+        self.game_rooms
+            .entry(game_id.clone())
+            .and_modify(|game_state| {
+                if let Some(seat) = game_state.seat_of(player_id) {
+                    game_state.push_event(GameEvent::Left { seat });
+                }
+            });
+        if let Some(players) = self.players.get_mut(&game_id) {
+            players.remove(&player_id);
+        }
+        self.update_game_feed(&game_id).await;
+    }
+}
+
+//unix time in milliseconds, used to timestamp feed events
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+//every game of a room in order, a room only ever plays one series
+fn series_games(feed: &[FeedEvent]) -> Vec<SeriesGame> {
+    let mut games = Vec::new();
+    let mut rolls = Vec::new();
+    for feed_event in feed {
+        match feed_event.event {
+            GameEvent::Rolled { seat, roll, .. } => rolls.push(SeriesRoll { seat, roll }),
+            GameEvent::Eliminated { seat, .. } => rolls.push(SeriesRoll { seat, roll: 1 }),
+            GameEvent::Died { seat, winner, .. } => {
+                rolls.push(SeriesRoll { seat, roll: 1 });
+                games.push(SeriesGame {
+                    winner,
+                    rolls: std::mem::take(&mut rolls),
+                });
+            }
+            GameEvent::Forfeited {
+                winner: Some(winner),
+                ..
+            } => games.push(SeriesGame {
+                winner,
+                rolls: std::mem::take(&mut rolls),
+            }),
+            _ => {}
+        }
+    }
+
+    games
+}
+
+fn game_ended(event: &GameEvent) -> bool {
+    matches!(
+        event,
+        GameEvent::Died { .. }
+            | GameEvent::Forfeited {
+                winner: Some(_),
+                ..
+            }
+    )
+}
+
+//a matchmade room only seats the players it was made for
+fn admits(reserved: &[PlayerId], player_id: PlayerId) -> bool {
+    reserved.is_empty() || reserved.contains(&player_id)
+}
+
+//where the page of events ending at `end` starts, never more than SNAPSHOT_LIMIT long. a page
+//can start inside a game, the verifier pages back to that game's commitment when it needs it
+fn page_start(end: usize) -> usize {
+    end.saturating_sub(SNAPSHOT_LIMIT)
+}
+
+//the last finished game's commitment, rolls and reveal, the reveal is the first after it ended
+fn replay_events(feed: &[FeedEvent]) -> Vec<FeedEvent> {
+    let Some(end) = feed
+        .iter()
+        .rposition(|feed_event| game_ended(&feed_event.event))
+    else {
+        return Vec::new();
+    };
+    let start = feed[..end]
+        .iter()
+        .rposition(|feed_event| game_ended(&feed_event.event))
+        .map_or(0, |previous| previous + 1);
+
+    let mut committed = false;
+    let mut revealed = false;
+    feed.iter()
+        .enumerate()
+        .skip(start)
+        .filter(|(index, feed_event)| match feed_event.event {
+            GameEvent::SeedCommitted { .. } => !std::mem::replace(&mut committed, true),
+            GameEvent::SeedRevealed { .. } => {
+                *index > end && !std::mem::replace(&mut revealed, true)
+            }
+            GameEvent::NewGame { .. }
+            | GameEvent::Rolled { .. }
+            | GameEvent::Eliminated { .. }
+            | GameEvent::Died { .. }
+            | GameEvent::TimedOut { .. }
+            | GameEvent::Forfeited { .. } => true,
+            _ => false,
+        })
+        .map(|(_, feed_event)| feed_event.clone())
+        .collect()
+}
+
+//most games in a row won by the same seat, the earliest streak wins a tie
+fn longest_streak(games: &[SeriesGame]) -> Option<Streak> {
+    let mut longest: Option<Streak> = None;
+    let mut current: Option<Streak> = None;
+    for game in games {
+        current = match current {
+            Some(streak) if streak.seat == game.winner => Some(Streak {
+                seat: streak.seat,
+                games: streak.games + 1,
+            }),
+            _ => Some(Streak {
+                seat: game.winner,
+                games: 1,
+            }),
+        };
+        if longest.as_ref().map_or(0, |streak| streak.games)
+            < current.as_ref().map_or(0, |streak| streak.games)
+        {
+            longest = current.clone();
+        }
+    }
+
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(events: Vec<GameEvent>) -> Vec<FeedEvent> {
+        events
+            .into_iter()
+            .enumerate()
+            .map(|(index, event)| FeedEvent {
+                seq: index as u64 + 1,
+                at: 0,
+                event,
+            })
+            .collect()
+    }
+
+    fn commitment() -> GameEvent {
+        GameEvent::SeedCommitted {
+            commitment: "c".to_string(),
+        }
+    }
+
+    fn rolls(count: usize) -> impl Iterator<Item = GameEvent> {
+        (0..count).map(|nonce| GameEvent::Rolled {
+            seat: nonce % 2,
+            roll: 2,
+            roll_between: 3,
+            nonce: nonce as u64,
+        })
+    }
+
+    //the seed commitment is seq 1, the rolls follow
+    fn room_with_rolls(count: usize) -> GameState {
+        let rules = Deathroll::new(100).unwrap();
+        let mut game_state =
+            GameState::new(rules, Uuid::new_v4(), RoomSettings::default(), Vec::new());
+        for event in rolls(count) {
+            game_state.push_event(event);
+        }
+        game_state
+    }
+
+    fn seqs(feed: &[FeedEvent]) -> Vec<u64> {
+        feed.iter().map(|feed_event| feed_event.seq).collect()
+    }
+
+    //the seqs of a delta, None when the client would get a snapshot instead
+    fn delta(game_state: &GameState, last_seq: Option<u64>) -> Option<Vec<u64>> {
+        match game_state.resume(last_seq) {
+            GameMessage::FeedDelta(missed) => Some(seqs(&missed)),
+            GameMessage::FeedSnapshot { feed, .. } => {
+                assert_eq!(
+                    feed.last().map(|feed_event| feed_event.seq),
+                    Some(game_state.last_seq())
+                );
+                None
+            }
+            msg => panic!("resume answered with {msg:?}"),
+        }
+    }
+
+    #[test]
+    fn an_up_to_date_client_gets_an_empty_delta() {
+        let game_state = room_with_rolls(10);
+        assert_eq!(delta(&game_state, Some(11)), Some(Vec::new()));
+    }
+
+    #[test]
+    fn a_client_behind_gets_only_what_it_missed() {
+        let game_state = room_with_rolls(10);
+        assert_eq!(delta(&game_state, Some(8)), Some(vec![9, 10, 11]));
+        assert_eq!(delta(&game_state, Some(1)), Some((2..=11).collect()));
+    }
+
+    #[test]
+    fn a_client_behind_the_resume_window_gets_a_snapshot() {
+        let game_state = room_with_rolls(100);
+        let last_seq = game_state.last_seq();
+        let window = RESUME_WINDOW as u64;
+        assert_eq!(
+            delta(&game_state, Some(last_seq - window)),
+            Some((last_seq - window + 1..=last_seq).collect())
+        );
+        assert_eq!(delta(&game_state, Some(last_seq - window - 1)), None);
+    }
+
+    #[test]
+    fn a_seq_the_room_never_reached_gets_a_snapshot() {
+        //the room was restored from a save older than what the client saw
+        let game_state = room_with_rolls(10);
+        assert_eq!(delta(&game_state, Some(12)), None);
+        assert_eq!(delta(&game_state, Some(u64::MAX)), None);
+        //a fresh tab
+        assert_eq!(delta(&game_state, None), None);
+        assert_eq!(delta(&game_state, Some(0)), None);
+    }
+
+    #[test]
+    fn unsent_events_are_only_taken_once() {
+        let mut game_state = room_with_rolls(0);
+        assert_eq!(seqs(&game_state.take_unsent()), [1]);
+        for event in rolls(2) {
+            game_state.push_event(event);
+        }
+        assert_eq!(seqs(&game_state.take_unsent()), [2, 3]);
+        assert!(game_state.take_unsent().is_empty());
+        //a client that saw the last broadcast is up to date
+        assert_eq!(
+            delta(&game_state, Some(game_state.broadcast_seq)),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn short_feeds_are_one_page() {
+        let feed = feed(rolls(SNAPSHOT_LIMIT).collect());
+        assert_eq!(page_start(feed.len()), 0);
+        assert_eq!(page_start(0), 0);
+    }
+
+    #[test]
+    fn a_game_longer_than_a_page_is_cut_at_the_limit() {
+        let mut events = vec![commitment()];
+        events.extend(rolls(SNAPSHOT_LIMIT * 2));
+        let feed = feed(events);
+
+        //the verifier pages back for the commitment, the page doesn't stretch to it
+        assert_eq!(page_start(feed.len()), feed.len() - SNAPSHOT_LIMIT);
+        assert_eq!(page_start(SNAPSHOT_LIMIT + 1), 1);
+    }
+
+    #[test]
+    fn pages_follow_on_from_each_other() {
+        let mut events = Vec::new();
+        for _ in 0..5 {
+            events.push(commitment());
+            events.extend(rolls(98));
+        }
+        let feed = feed(events);
+
+        let mut end = feed.len();
+        let mut pages = Vec::new();
+        while end > 0 {
+            let start = page_start(end);
+            pages.push(end - start);
+            end = start;
+        }
+        assert_eq!(pages, [SNAPSHOT_LIMIT, SNAPSHOT_LIMIT, 95]);
+    }
+
+    //matchmade rooms ask for 30s turns, the clock only runs once the game has started
+    #[test]
+    fn the_turn_clock_runs_while_playing() {
+        let settings = RoomSettings {
+            turn_secs: Some(30),
+            on_timeout: OnTimeout::AutoRoll,
+            ..RoomSettings::default()
+        };
+        let rules = Deathroll::new(100).unwrap();
+        let mut game_state = GameState::new(rules, Uuid::new_v4(), settings, Vec::new());
+        game_state.restart_clock();
+        assert!(game_state.turn_clock().is_none());
+
+        game_state.rules.start().unwrap();
+        game_state.restart_clock();
+        let clock = game_state.turn_clock().unwrap();
+        assert!(clock.remaining_ms > 29_000 && clock.remaining_ms <= 30_000);
+        assert_eq!((clock.seat, clock.on_timeout), (0, OnTimeout::AutoRoll));
+    }
+
+    #[test]
+    fn reserved_rooms_only_seat_their_players() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        assert!(admits(&[a, b], a));
+        assert!(admits(&[a, b], b));
+        assert!(!admits(&[a, b], Uuid::new_v4()));
+        //invite links seat anyone
+        assert!(admits(&[], Uuid::new_v4()));
+    }
+
+    #[test]
+    fn invite_rooms_have_no_turn_clock() {
+        let rules = Deathroll::new(100).unwrap();
+        let mut game_state =
+            GameState::new(rules, Uuid::new_v4(), RoomSettings::default(), Vec::new());
+        game_state.rules.start().unwrap();
+        game_state.restart_clock();
+        assert!(game_state.turn_clock().is_none());
+    }
 }
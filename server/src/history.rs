@@ -0,0 +1,327 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use deathroll_protocol::{HistoryPage, MatchRecord, PlayerStats, Replay};
+use serde::Deserialize;
+
+use crate::store::RoomStore;
+
+pub type SharedHistory = Arc<RwLock<History>>;
+
+pub const HISTORY_PAGE_LIMIT: usize = 50;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+//every finished game, indexed by the public ids of the players in it
+#[derive(Debug)]
+pub struct History {
+    records: VecDeque<MatchRecord>,
+    //position of the oldest record still kept, the store may only keep the latest games
+    first: usize,
+    //positions counted from the first game ever indexed, oldest first
+    by_player: HashMap<String, VecDeque<usize>>,
+    ids: HashSet<String>,
+    store: Arc<dyn RoomStore>,
+}
+
+impl History {
+    pub fn load(store: Arc<dyn RoomStore>) -> Self {
+        let records = store.load_history().unwrap_or_else(|err| {
+            println!("failed to load match history: {err}");
+            Vec::new()
+        });
+
+        let mut history = Self {
+            records: VecDeque::new(),
+            first: 0,
+            by_player: HashMap::new(),
+            ids: HashSet::new(),
+            store,
+        };
+        for record in records {
+            history.index(record);
+        }
+
+        history
+    }
+
+    pub fn record(history: &SharedHistory, replay: Replay) {
+        Self::import(history, vec![replay]);
+    }
+
+    //games from elsewhere, the ones already in the history are skipped.
+    //the replays are kept on their own and only the records stay in memory,
+    //the files are written with no lock held so pages can still be read meanwhile
+    pub fn import(history: &SharedHistory, replays: Vec<Replay>) -> Vec<MatchRecord> {
+        let (store, replays) = {
+            let history = history.read().unwrap_or_else(PoisonError::into_inner);
+            let mut ids = HashSet::new();
+            let replays: Vec<Replay> = replays
+                .into_iter()
+                .filter(|replay| {
+                    !history.ids.contains(&replay.game.id) && ids.insert(replay.game.id.clone())
+                })
+                .collect();
+            (Arc::clone(&history.store), replays)
+        };
+        if replays.is_empty() {
+            return Vec::new();
+        }
+
+        for replay in &replays {
+            if let Err(err) = store.save_replay(replay) {
+                println!("failed to save replay {}: {err}", replay.game.id);
+            }
+        }
+        let records: Vec<MatchRecord> = replays.into_iter().map(|replay| replay.game).collect();
+        if let Err(err) = store.append_history(&records) {
+            println!("failed to save {} matches: {err}", records.len());
+        }
+
+        let mut history = history.write().unwrap_or_else(PoisonError::into_inner);
+        for record in &records {
+            history.index(record.clone());
+        }
+
+        records
+    }
+
+    pub fn replay(&self, id: &str) -> Option<Replay> {
+        self.store.load_replay(id).unwrap_or_else(|err| {
+            println!("failed to load replay {id}: {err}");
+            None
+        })
+    }
+
+    //`player` is a public id, newest games first
+    pub fn page(&self, player: &str, query: &HistoryQuery) -> HistoryPage {
+        let no_games = VecDeque::new();
+        let positions = self.by_player.get(player).unwrap_or(&no_games);
+        let limit = query
+            .limit
+            .unwrap_or(HISTORY_PAGE_LIMIT)
+            .min(HISTORY_PAGE_LIMIT);
+
+        let games = positions
+            .iter()
+            .rev()
+            .skip(query.offset.unwrap_or_default())
+            .take(limit)
+            .map(|position| self.records[position - self.first].clone())
+            .collect();
+        let stats = stats(
+            player,
+            positions
+                .iter()
+                .map(|position| &self.records[position - self.first]),
+        );
+
+        HistoryPage {
+            player: player.to_string(),
+            stats,
+            games,
+            total: positions.len(),
+        }
+    }
+
+    //a game imported twice at once is only indexed the first time
+    fn index(&mut self, record: MatchRecord) {
+        if !self.ids.insert(record.id.clone()) {
+            return;
+        }
+        let position = self.first + self.records.len();
+        for player in &record.players {
+            self.by_player
+                .entry(player.id.clone())
+                .or_default()
+                .push_back(position);
+        }
+        self.records.push_back(record);
+
+        if let Some(limit) = self.store.history_limit() {
+            while self.records.len() > limit {
+                self.evict();
+            }
+        }
+    }
+
+    //drops the oldest record, it is the first position of every player in it
+    fn evict(&mut self) {
+        let Some(record) = self.records.pop_front() else {
+            return;
+        };
+        self.first += 1;
+        self.ids.remove(&record.id);
+        for player in &record.players {
+            if let Some(positions) = self.by_player.get_mut(&player.id) {
+                positions.pop_front();
+                if positions.is_empty() {
+                    self.by_player.remove(&player.id);
+                }
+            }
+        }
+    }
+}
+
+fn stats<'a>(player: &str, records: impl Iterator<Item = &'a MatchRecord>) -> PlayerStats {
+    let mut stats = PlayerStats::default();
+    let mut survived = 0;
+    for record in records {
+        let Some(seat) = record.seat_of(player) else {
+            continue;
+        };
+        stats.games += 1;
+        //a 1 is always the roll that took the player out
+        survived += record
+            .rolls
+            .iter()
+            .filter(|roll| roll.seat == seat && roll.roll != 1)
+            .count();
+        if record.winner == seat {
+            stats.wins += 1;
+            stats.biggest_win = stats.biggest_win.max(Some(record.start_roll));
+        }
+    }
+    if stats.games > 0 {
+        stats.avg_rolls_survived = survived as f64 / f64::from(stats.games);
+    }
+
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use deathroll_core::Seat;
+    use deathroll_protocol::{MatchPlayer, Profile, SeriesRoll, REPLAY_VERSION};
+
+    fn history() -> SharedHistory {
+        Arc::new(RwLock::new(History::load(Arc::new(MemoryStore::default()))))
+    }
+
+    fn replay(id: &str, players: &[&str], start_roll: u32, rolls: &[(Seat, u32)]) -> Replay {
+        let rolls: Vec<SeriesRoll> = rolls
+            .iter()
+            .map(|&(seat, roll)| SeriesRoll { seat, roll })
+            .collect();
+        //two seats, whoever didn't roll the 1 won
+        let winner = rolls.last().map_or(0, |roll| 1 - roll.seat);
+        Replay {
+            version: REPLAY_VERSION,
+            game: MatchRecord {
+                id: id.to_string(),
+                game_id: "room".to_string(),
+                players: players
+                    .iter()
+                    .enumerate()
+                    .map(|(seat, player)| MatchPlayer {
+                        id: player.to_string(),
+                        profile: Profile::for_seat(seat),
+                    })
+                    .collect(),
+                start_roll,
+                rolls,
+                winner,
+                started_at: 1,
+                finished_at: 2,
+            },
+            events: Vec::new(),
+        }
+    }
+
+    fn ids(page: &HistoryPage) -> Vec<&str> {
+        page.games.iter().map(|game| game.id.as_str()).collect()
+    }
+
+    #[test]
+    fn pages_stay_in_range_past_the_history_limit() {
+        let history = history();
+        let limit = history.read().unwrap().store.history_limit().unwrap();
+        //the first games are the only ones "old" played, they get evicted
+        let replays = (0..limit + 10)
+            .map(|game| {
+                let opponent = if game < 10 { "old" } else { "new" };
+                replay(&game.to_string(), &["regular", opponent], 100, &[(1, 1)])
+            })
+            .collect();
+        History::import(&history, replays);
+        History::record(
+            &history,
+            replay("last", &["regular", "new"], 100, &[(1, 1)]),
+        );
+
+        let history = history.read().unwrap();
+        let query = HistoryQuery::default();
+        let page = history.page("regular", &query);
+        assert_eq!(page.total, limit);
+        assert_eq!(page.stats.games as usize, limit);
+        assert_eq!(ids(&page)[..2], ["last", &(limit + 9).to_string()]);
+
+        let oldest = HistoryQuery {
+            offset: Some(limit - 1),
+            limit: None,
+        };
+        assert_eq!(ids(&history.page("regular", &oldest)), ["11"]);
+        assert_eq!(history.page("new", &oldest).games.len(), 1);
+        assert_eq!(history.page("old", &query).total, 0);
+        assert!(!history.ids.contains("10"));
+    }
+
+    #[test]
+    fn importing_twice_skips_known_games() {
+        let history = history();
+        let first = replay("first", &["a", "b"], 100, &[(0, 1)]);
+        let imported = History::import(&history, vec![first.clone(), first.clone()]);
+        assert_eq!(imported, std::slice::from_ref(&first.game));
+
+        let second = replay("second", &["a", "b"], 100, &[(0, 1)]);
+        let imported = History::import(&history, vec![first, second.clone()]);
+        assert_eq!(imported, [second.game]);
+
+        let history = history.read().unwrap();
+        assert_eq!(history.page("a", &HistoryQuery::default()).total, 2);
+        assert_eq!(history.store.load_history().unwrap().len(), 2);
+        assert!(history.replay("second").is_some());
+    }
+
+    #[test]
+    fn stats_count_wins_survived_rolls_and_the_biggest_win() {
+        let history = history();
+        let replays = vec![
+            replay("small", &["a", "b"], 100, &[(0, 50), (1, 20), (0, 1)]),
+            replay("big", &["a", "b"], 500, &[(0, 300), (1, 1)]),
+        ];
+        History::import(&history, replays);
+
+        let history = history.read().unwrap();
+        let query = HistoryQuery::default();
+        let a = history.page("a", &query).stats;
+        assert_eq!(
+            a,
+            PlayerStats {
+                games: 2,
+                wins: 1,
+                avg_rolls_survived: 1.0,
+                biggest_win: Some(500),
+            }
+        );
+        let b = history.page("b", &query).stats;
+        assert_eq!(
+            b,
+            PlayerStats {
+                games: 2,
+                wins: 1,
+                avg_rolls_survived: 0.5,
+                biggest_win: Some(100),
+            }
+        );
+        assert_eq!(history.page("nobody", &query).stats, PlayerStats::default());
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use deathroll_protocol::ErrorCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game_server::{now, GameId, PlayerId},
+    store::RoomStore,
+};
+
+pub type SharedLedger = Arc<Mutex<Ledger>>;
+
+//gold every player identity gets once a day, there is no cap
+pub const DAILY_ALLOWANCE: u64 = 1000;
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+//one change to one balance, entries are only ever appended
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub id: u64,
+    //unix time in milliseconds
+    pub at: u64,
+    pub player_id: PlayerId,
+    pub amount: i64,
+    //balance right after this entry
+    pub balance: u64,
+    pub reason: LedgerReason,
+    pub game_id: Option<GameId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LedgerReason {
+    Allowance,
+    //stake held by a room until the game is decided
+    Escrow,
+    //stake given back by a room that closed before its game ended
+    Refund,
+    //the whole pot to the winner
+    Payout,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LedgerQuery {
+    pub player: Option<PlayerId>,
+    pub game: Option<GameId>,
+    //only entries with a bigger id
+    pub after: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Account {
+    balance: u64,
+    //last day the allowance was paid, in days since the epoch
+    allowance_day: u64,
+}
+
+//balances are rebuilt from the entries on boot, the entries are the source of truth.
+//a change only counts once the store has it, a failed write leaves the balances as they were
+#[derive(Debug)]
+pub struct Ledger {
+    accounts: HashMap<PlayerId, Account>,
+    //every entry so far, oldest first, queries don't go back to the store
+    entries: Vec<LedgerEntry>,
+    store: Arc<dyn RoomStore>,
+}
+
+impl Ledger {
+    pub fn load(store: Arc<dyn RoomStore>) -> Self {
+        let entries = store.load_ledger().unwrap_or_else(|err| {
+            println!("failed to load ledger: {err}");
+            Vec::new()
+        });
+
+        let mut ledger = Self {
+            accounts: HashMap::new(),
+            entries: Vec::new(),
+            store,
+        };
+        for entry in entries {
+            ledger.apply(entry);
+        }
+        println!(
+            "loaded {} ledger entries for {} players",
+            ledger.entries.len(),
+            ledger.accounts.len()
+        );
+
+        ledger
+    }
+
+    pub fn balance(&mut self, player_id: PlayerId) -> u64 {
+        let mut entries = Vec::new();
+        self.allowance(player_id, &mut entries);
+        //without the allowance if it couldn't be saved, it's paid on the next try
+        let _ = self.append(entries);
+
+        self.account(player_id).balance
+    }
+
+    //take `amount` from every player or from none of them
+    pub fn escrow(
+        &mut self,
+        game_id: &str,
+        players: &[PlayerId],
+        amount: u64,
+    ) -> Result<(), ErrorCode> {
+        let mut entries = Vec::new();
+        for player_id in players {
+            self.allowance(*player_id, &mut entries);
+        }
+        let covered = players.iter().all(|player_id| {
+            staged_balance(&entries, self.account(*player_id), *player_id) >= amount
+        });
+        if !covered {
+            //the allowances still count, the stake doesn't
+            let _ = self.append(entries);
+            return Err(ErrorCode::InsufficientGold);
+        }
+        for player_id in players {
+            self.change(
+                &mut entries,
+                *player_id,
+                -(amount as i64),
+                LedgerReason::Escrow,
+                game_id,
+            );
+        }
+
+        self.append(entries)
+    }
+
+    pub fn pay_out(&mut self, game_id: &str, winner: PlayerId, pot: u64) -> Result<(), ErrorCode> {
+        let mut entries = Vec::new();
+        self.change(
+            &mut entries,
+            winner,
+            pot as i64,
+            LedgerReason::Payout,
+            game_id,
+        );
+        self.append(entries)
+    }
+
+    pub fn refund(
+        &mut self,
+        game_id: &str,
+        players: &[PlayerId],
+        amount: u64,
+    ) -> Result<(), ErrorCode> {
+        let mut entries = Vec::new();
+        for player_id in players {
+            self.change(
+                &mut entries,
+                *player_id,
+                amount as i64,
+                LedgerReason::Refund,
+                game_id,
+            );
+        }
+        self.append(entries)
+    }
+
+    pub fn entries(&self, query: &LedgerQuery) -> Vec<LedgerEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| query.player.is_none_or(|player| entry.player_id == player))
+            .filter(|entry| {
+                query
+                    .game
+                    .as_ref()
+                    .is_none_or(|game| entry.game_id.as_ref() == Some(game))
+            })
+            .filter(|entry| query.after.is_none_or(|after| entry.id > after))
+            .take(query.limit.unwrap_or(100).min(1000))
+            .cloned()
+            .collect()
+    }
+
+    fn account(&self, player_id: PlayerId) -> Account {
+        self.accounts.get(&player_id).copied().unwrap_or_default()
+    }
+
+    //first touch of the day pays the allowance
+    fn allowance(&self, player_id: PlayerId, entries: &mut Vec<LedgerEntry>) {
+        let today = now() / DAY_MS;
+        if self.account(player_id).allowance_day >= today {
+            return;
+        }
+
+        self.entry(
+            entries,
+            player_id,
+            DAILY_ALLOWANCE as i64,
+            LedgerReason::Allowance,
+            None,
+        );
+    }
+
+    fn change(
+        &self,
+        entries: &mut Vec<LedgerEntry>,
+        player_id: PlayerId,
+        amount: i64,
+        reason: LedgerReason,
+        game_id: &str,
+    ) {
+        self.entry(
+            entries,
+            player_id,
+            amount,
+            reason,
+            Some(game_id.to_string()),
+        );
+    }
+
+    //stages an entry on top of the ones before it in `entries`, nothing changes until `append`
+    fn entry(
+        &self,
+        entries: &mut Vec<LedgerEntry>,
+        player_id: PlayerId,
+        amount: i64,
+        reason: LedgerReason,
+        game_id: Option<GameId>,
+    ) {
+        let balance = staged_balance(entries, self.account(player_id), player_id);
+        let id = entries
+            .last()
+            .or(self.entries.last())
+            .map_or(0, |entry| entry.id + 1);
+        entries.push(LedgerEntry {
+            id,
+            at: now(),
+            player_id,
+            amount,
+            balance: balance.saturating_add_signed(amount),
+            reason,
+            game_id,
+        });
+    }
+
+    //a transfer's entries go to the store in one write, the balances move once it's there
+    fn append(&mut self, entries: Vec<LedgerEntry>) -> Result<(), ErrorCode> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        if let Err(err) = self.store.append_ledger(&entries) {
+            println!("failed to append {} ledger entries: {err}", entries.len());
+            return Err(ErrorCode::LedgerUnavailable);
+        }
+        for entry in entries {
+            self.apply(entry);
+        }
+
+        Ok(())
+    }
+
+    fn apply(&mut self, entry: LedgerEntry) {
+        let account = self.accounts.entry(entry.player_id).or_default();
+        account.balance = entry.balance;
+        if entry.reason == LedgerReason::Allowance {
+            account.allowance_day = entry.at / DAY_MS;
+        }
+        self.entries.push(entry);
+    }
+}
+
+//the balance after the entries staged so far
+fn staged_balance(entries: &[LedgerEntry], account: Account, player_id: PlayerId) -> u64 {
+    entries
+        .iter()
+        .rev()
+        .find(|entry| entry.player_id == player_id)
+        .map_or(account.balance, |entry| entry.balance)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use deathroll_protocol::{MatchRecord, Profile, Replay};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{game_server::GameState, ratings::RatingTable, store::MemoryStore, PendingRoom};
+
+    //a memory store whose ledger appends fail while it's down
+    #[derive(Debug, Default)]
+    struct FlakyStore {
+        store: MemoryStore,
+        down: AtomicBool,
+    }
+
+    impl RoomStore for FlakyStore {
+        fn load_rooms(&self) -> io::Result<HashMap<GameId, GameState>> {
+            self.store.load_rooms()
+        }
+
+        fn save_room(&self, game_id: &str, game_state: &GameState) -> io::Result<()> {
+            self.store.save_room(game_id, game_state)
+        }
+
+        fn remove_room(&self, game_id: &str) -> io::Result<()> {
+            self.store.remove_room(game_id)
+        }
+
+        fn archive_room(&self, game_id: &str, game_state: &GameState) -> io::Result<()> {
+            self.store.archive_room(game_id, game_state)
+        }
+
+        fn load_start_rolls(&self) -> io::Result<HashMap<GameId, PendingRoom>> {
+            self.store.load_start_rolls()
+        }
+
+        fn save_start_roll(&self, game_id: &str, pending: &PendingRoom) -> io::Result<()> {
+            self.store.save_start_roll(game_id, pending)
+        }
+
+        fn remove_start_roll(&self, game_id: &str) -> io::Result<()> {
+            self.store.remove_start_roll(game_id)
+        }
+
+        fn load_ledger(&self) -> io::Result<Vec<LedgerEntry>> {
+            self.store.load_ledger()
+        }
+
+        fn append_ledger(&self, entries: &[LedgerEntry]) -> io::Result<()> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(io::Error::other("disk full"));
+            }
+            self.store.append_ledger(entries)
+        }
+
+        fn load_ratings(&self) -> io::Result<RatingTable> {
+            self.store.load_ratings()
+        }
+
+        fn save_ratings(&self, ratings: &RatingTable) -> io::Result<()> {
+            self.store.save_ratings(ratings)
+        }
+
+        fn load_profiles(&self) -> io::Result<HashMap<PlayerId, Profile>> {
+            self.store.load_profiles()
+        }
+
+        fn save_profiles(&self, profiles: &HashMap<PlayerId, Profile>) -> io::Result<()> {
+            self.store.save_profiles(profiles)
+        }
+
+        fn load_history(&self) -> io::Result<Vec<MatchRecord>> {
+            self.store.load_history()
+        }
+
+        fn append_history(&self, records: &[MatchRecord]) -> io::Result<()> {
+            self.store.append_history(records)
+        }
+
+        fn load_replay(&self, id: &str) -> io::Result<Option<Replay>> {
+            self.store.load_replay(id)
+        }
+
+        fn save_replay(&self, replay: &Replay) -> io::Result<()> {
+            self.store.save_replay(replay)
+        }
+    }
+
+    fn reasons(ledger: &Ledger, player_id: PlayerId) -> Vec<LedgerReason> {
+        let query = LedgerQuery {
+            player: Some(player_id),
+            ..LedgerQuery::default()
+        };
+        ledger
+            .entries(&query)
+            .iter()
+            .map(|entry| entry.reason)
+            .collect()
+    }
+
+    #[test]
+    fn the_allowance_is_paid_once_a_day() {
+        let mut ledger = Ledger::load(Arc::new(MemoryStore::default()));
+        let player_id = Uuid::new_v4();
+
+        assert_eq!(ledger.balance(player_id), DAILY_ALLOWANCE);
+        assert_eq!(ledger.balance(player_id), DAILY_ALLOWANCE);
+        assert_eq!(reasons(&ledger, player_id), [LedgerReason::Allowance]);
+    }
+
+    #[test]
+    fn escrow_takes_from_everyone_or_nobody() {
+        let mut ledger = Ledger::load(Arc::new(MemoryStore::default()));
+        let (rich, poor) = (Uuid::new_v4(), Uuid::new_v4());
+        ledger.escrow("room", &[poor], 800).unwrap();
+        assert_eq!(ledger.balance(poor), 200);
+
+        assert_eq!(
+            ledger.escrow("room", &[rich, poor], 500),
+            Err(ErrorCode::InsufficientGold)
+        );
+        //the rich player still got their allowance, but nothing was held
+        assert_eq!(ledger.balance(rich), DAILY_ALLOWANCE);
+        assert_eq!(ledger.balance(poor), 200);
+        assert_eq!(reasons(&ledger, rich), [LedgerReason::Allowance]);
+
+        ledger.escrow("room", &[rich, poor], 200).unwrap();
+        assert_eq!(ledger.balance(rich), 800);
+        assert_eq!(ledger.balance(poor), 0);
+    }
+
+    #[test]
+    fn pay_out_and_refund_credit_the_pot() {
+        let mut ledger = Ledger::load(Arc::new(MemoryStore::default()));
+        let (winner, loser) = (Uuid::new_v4(), Uuid::new_v4());
+        ledger.escrow("won", &[winner, loser], 100).unwrap();
+        ledger.pay_out("won", winner, 200).unwrap();
+        assert_eq!(ledger.balance(winner), 1100);
+        assert_eq!(ledger.balance(loser), 900);
+
+        ledger.escrow("closed", &[winner, loser], 300).unwrap();
+        ledger.refund("closed", &[winner, loser], 300).unwrap();
+        assert_eq!(ledger.balance(winner), 1100);
+        assert_eq!(ledger.balance(loser), 900);
+
+        let query = LedgerQuery {
+            game: Some("closed".to_string()),
+            ..LedgerQuery::default()
+        };
+        let amounts: Vec<i64> = ledger
+            .entries(&query)
+            .iter()
+            .map(|entry| entry.amount)
+            .collect();
+        assert_eq!(amounts, [-300, -300, 300, 300]);
+    }
+
+    #[test]
+    fn balances_are_rebuilt_from_the_store() {
+        let store: Arc<dyn RoomStore> = Arc::new(MemoryStore::default());
+        let mut ledger = Ledger::load(Arc::clone(&store));
+        let (winner, loser) = (Uuid::new_v4(), Uuid::new_v4());
+        ledger.escrow("room", &[winner, loser], 250).unwrap();
+        ledger.pay_out("room", winner, 500).unwrap();
+
+        let mut reloaded = Ledger::load(store);
+        assert_eq!(reloaded.balance(winner), 1250);
+        assert_eq!(reloaded.balance(loser), 750);
+        assert_eq!(reloaded.entries(&LedgerQuery::default()).len(), 5);
+    }
+
+    #[test]
+    fn balances_only_move_once_the_append_lands() {
+        let store = Arc::new(FlakyStore::default());
+        let mut ledger = Ledger::load(store.clone());
+        let (winner, loser) = (Uuid::new_v4(), Uuid::new_v4());
+        ledger.escrow("room", &[winner, loser], 100).unwrap();
+        let entries = ledger.entries(&LedgerQuery::default()).len();
+
+        store.down.store(true, Ordering::SeqCst);
+        assert_eq!(
+            ledger.pay_out("room", winner, 200),
+            Err(ErrorCode::LedgerUnavailable)
+        );
+        assert_eq!(
+            ledger.escrow("next", &[winner, loser], 100),
+            Err(ErrorCode::LedgerUnavailable)
+        );
+        assert_eq!(
+            ledger.refund("room", &[winner, loser], 100),
+            Err(ErrorCode::LedgerUnavailable)
+        );
+        assert_eq!(ledger.balance(winner), 900);
+        assert_eq!(ledger.balance(loser), 900);
+        assert_eq!(ledger.entries(&LedgerQuery::default()).len(), entries);
+        //a player seen for the first time gets nothing until the allowance is saved
+        let newcomer = Uuid::new_v4();
+        assert_eq!(ledger.balance(newcomer), 0);
+
+        store.down.store(false, Ordering::SeqCst);
+        ledger.pay_out("room", winner, 200).unwrap();
+        assert_eq!(ledger.balance(winner), 1100);
+        assert_eq!(ledger.balance(newcomer), DAILY_ALLOWANCE);
+    }
+}
@@ -0,0 +1,132 @@
+//! deathroll game server, kept apart from the binary so benches can drive the
+//! game server directly without going through a socket.
+
+use deathroll_protocol::RoomSettings;
+use game_server::{now, PlayerId};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use history::SharedHistory;
+use ledger::SharedLedger;
+use lobby::SharedLobby;
+use profiles::SharedProfiles;
+use ratings::SharedRatings;
+use store::RoomStore;
+use uuid::Uuid;
+
+pub mod game_server;
+pub mod history;
+pub mod ledger;
+pub mod lobby;
+pub mod matchmaking;
+pub mod profiles;
+pub mod ratings;
+pub mod room_writer;
+pub mod store;
+pub mod websockets;
+pub mod wowlog;
+
+pub type SharedState = Arc<RwLock<StartRoll>>;
+
+//everything the game servers share with each other and with the http handlers
+#[derive(Debug, Clone)]
+pub struct Services {
+    pub store: Arc<dyn RoomStore>,
+    pub start_rolls: SharedState,
+    pub ledger: SharedLedger,
+    pub lobby: SharedLobby,
+    pub ratings: SharedRatings,
+    pub profiles: SharedProfiles,
+    pub history: SharedHistory,
+}
+
+const PUBLIC_ID_NAMESPACE: Uuid = Uuid::from_u128(0x6d1f_27c4_93a0_4b8e_a5f2_0c3e_7b19_d846);
+
+//the cookie id is a player's secret, anything shown to other players uses this instead
+pub fn public_id(player_id: PlayerId) -> Uuid {
+    Uuid::new_v5(&PUBLIC_ID_NAMESPACE, player_id.as_bytes())
+}
+
+#[derive(Debug)]
+pub struct StartRoll {
+    start_roll: HashMap<String, PendingRoom>,
+    store: Arc<dyn RoomStore>,
+}
+
+//an invite link that has been created but not opened yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingRoom {
+    start_roll: String,
+    #[serde(default)]
+    settings: RoomSettings,
+    created_at: u64,
+    //matchmade rooms only seat these players, empty for invite links
+    #[serde(default)]
+    reserved: Vec<PlayerId>,
+}
+
+impl PendingRoom {
+    pub fn new(start_roll: String, settings: RoomSettings) -> Self {
+        Self {
+            start_roll,
+            settings,
+            created_at: now(),
+            reserved: Vec::new(),
+        }
+    }
+
+    pub fn reserved_for(mut self, players: impl IntoIterator<Item = PlayerId>) -> Self {
+        self.reserved = players.into_iter().collect();
+        self
+    }
+
+    //0 when it isn't a number, which no game accepts
+    pub fn start_roll(&self) -> u32 {
+        self.start_roll.trim().parse::<u32>().unwrap_or_default()
+    }
+
+    pub fn seats(&self) -> usize {
+        self.settings.seats
+    }
+
+    pub fn best_of(&self) -> Option<u32> {
+        self.settings.best_of
+    }
+
+    pub fn wager(&self) -> u64 {
+        self.settings.wager
+    }
+}
+
+impl StartRoll {
+    pub fn load(store: Arc<dyn RoomStore>) -> Self {
+        let start_roll = store.load_start_rolls().unwrap_or_else(|err| {
+            println!("failed to load start rolls: {err}");
+            HashMap::new()
+        });
+
+        Self { start_roll, store }
+    }
+
+    //saved first and listed after, the table isn't locked while the file is written
+    pub fn insert(start_rolls: &SharedState, game_id: String, pending: PendingRoom) {
+        let store = Arc::clone(
+            &start_rolls
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .store,
+        );
+        if let Err(err) = store.save_start_roll(&game_id, &pending) {
+            println!("failed to save start roll {game_id}: {err}");
+        }
+        println!("start_roll {game_id} - {:?}", pending);
+        start_rolls
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .start_roll
+            .insert(game_id, pending);
+    }
+}
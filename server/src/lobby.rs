@@ -0,0 +1,90 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use deathroll_protocol::LobbyRoom;
+use tokio::sync::broadcast;
+
+use crate::game_server::{now, GameId};
+
+pub type SharedLobby = Arc<Lobby>;
+
+//public rooms that still have a free seat, written by every shard
+#[derive(Debug)]
+pub struct Lobby {
+    //(created at, room), the age is filled in when the list is read
+    rooms: RwLock<HashMap<GameId, (u64, LobbyRoom)>>,
+    //fires whenever the list changes, subscribers fetch it again
+    changes: broadcast::Sender<()>,
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        let (changes, _) = broadcast::channel(16);
+        Self {
+            rooms: RwLock::default(),
+            changes,
+        }
+    }
+}
+
+impl Lobby {
+    //newest rooms first
+    pub fn rooms(&self) -> Vec<LobbyRoom> {
+        let now = now();
+        let mut rooms: Vec<(u64, LobbyRoom)> = self
+            .rooms
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .cloned()
+            .collect();
+        rooms.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        rooms
+            .into_iter()
+            .map(|(created_at, mut room)| {
+                room.age_secs = now.saturating_sub(created_at) / 1000;
+                room
+            })
+            .collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    //only tells subscribers when something they can see changed
+    pub fn upsert(&self, created_at: u64, room: LobbyRoom) {
+        let mut rooms = self.rooms.write().unwrap_or_else(PoisonError::into_inner);
+        if rooms.get(&room.game_id).map(|(_, listed)| listed) == Some(&room) {
+            return;
+        }
+        rooms.insert(room.game_id.clone(), (created_at, room));
+        drop(rooms);
+
+        let _ = self.changes.send(());
+    }
+
+    pub fn remove(&self, game_id: &str) {
+        if self
+            .rooms
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(game_id)
+            .is_some()
+        {
+            let _ = self.changes.send(());
+        }
+    }
+
+    //drop everything a restarted shard listed, it lists its rooms again on boot
+    pub fn retain(&self, keep: impl Fn(&str) -> bool) {
+        self.rooms
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|game_id, _| keep(game_id));
+        let _ = self.changes.send(());
+    }
+}
@@ -1,46 +1,99 @@
 use axum::{
-    extract::{ws::WebSocketUpgrade, Path, State},
+    extract::{ws::WebSocketUpgrade, Path, Query, State},
+    headers::{authorization::Bearer, Authorization},
+    http::StatusCode,
     response::IntoResponse,
-    routing::get,
-    Extension, Router,
+    routing::{get, post, put},
+    Extension, Json, Router, TypedHeader,
 };
 use axum_extra::routing::SpaRouter;
-use game_server::{GameServer, GameServerHandle};
+use deathroll_core::Deathroll;
+use deathroll_protocol::{
+    ErrorCode, GameMessage, Handshake, HistoryPage, LeaderboardEntry, LobbyRoom, MatchRecord,
+    NewRoom, Profile, Replay, RoomSettings, MAX_TURN_SECS, MIN_TURN_SECS, WAGERS,
+};
+use serde::Deserialize;
+use server::{
+    game_server::{self, GameServerHandle},
+    history::{History, HistoryQuery, SharedHistory},
+    ledger::{Ledger, LedgerEntry, LedgerQuery, SharedLedger, DAILY_ALLOWANCE},
+    lobby::{Lobby, SharedLobby},
+    matchmaking::{self, MatchmakerHandle},
+    profiles::{Profiles, SharedProfiles},
+    public_id,
+    ratings::{Ratings, SharedRatings, LEADERBOARD_LIMIT},
+    store,
+    websockets::{handle_socket, lobby_socket, queue_socket, reject_version},
+    wowlog, PendingRoom, Services, SharedState, StartRoll,
+};
 use std::{
-    collections::HashMap,
+    env, fs,
     net::SocketAddr,
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, PoisonError, RwLock},
 };
 use tower_cookies::{Cookie, CookieManagerLayer, Cookies};
 
 use uuid::Uuid;
 
-use websockets::handle_socket;
-mod game_server;
-mod websockets;
-
 const COOKIE_NAME: &str = "deathroll";
-
-type SharedState = Arc<RwLock<StartRoll>>;
-
-#[derive(Default, Debug)]
-pub struct StartRoll {
-    start_roll: HashMap<String, String>,
-}
+//the admin endpoints only exist when this is set
+const ADMIN_TOKEN_ENV: &str = "DEATHROLL_ADMIN_TOKEN";
+const IMPORT_WOWLOG: &str = "import-wowlog";
 
 #[tokio::main]
 async fn main() {
-    let (game_server, server_tx) = GameServer::new();
-    let run_game = tokio::spawn(game_server.run());
+    let store = store::from_env();
+
+    //server import-wowlog <WoWChatLog.txt> [year], then exit
+    if env::args().nth(1).as_deref() == Some(IMPORT_WOWLOG) {
+        let args: Vec<String> = env::args().skip(2).collect();
+        import_wowlog(Arc::new(RwLock::new(History::load(store))), &args);
+        return;
+    }
+
+    let shared_state: SharedState = Arc::new(RwLock::new(StartRoll::load(Arc::clone(&store))));
+
+    let ledger: SharedLedger = Arc::new(Mutex::new(Ledger::load(Arc::clone(&store))));
+    let lobby: SharedLobby = Arc::new(Lobby::default());
+    let ratings: SharedRatings = Arc::new(Mutex::new(Ratings::load(Arc::clone(&store))));
+    let profiles: SharedProfiles = Arc::new(RwLock::new(Profiles::load(Arc::clone(&store))));
+    let history: SharedHistory = Arc::new(RwLock::new(History::load(Arc::clone(&store))));
+
+    let services = Services {
+        store,
+        start_rolls: Arc::clone(&shared_state),
+        ledger: Arc::clone(&ledger),
+        lobby: Arc::clone(&lobby),
+        ratings: Arc::clone(&ratings),
+        profiles: Arc::clone(&profiles),
+        history: Arc::clone(&history),
+    };
+    let server_tx = game_server::supervise(services, game_server::shards_from_env());
 
-    let shared_state = SharedState::default();
+    let matchmaker = matchmaking::spawn(Arc::clone(&shared_state));
 
     let spa = SpaRouter::new("/assets", "../dist");
 
     let app = Router::new()
         .merge(spa)
         .route("/ws/:id", get(ws_handler).post(start_roll))
+        .route("/api/admin/ledger", get(admin_ledger))
+        .route("/api/admin/wowlog", post(admin_wowlog))
+        .route("/api/lobby", get(lobby_rooms))
+        .route("/api/lobby/ws", get(lobby_ws))
+        .route("/api/queue/ws", get(queue_ws))
+        .route("/api/leaderboard", get(leaderboard))
+        .route("/api/profile", get(get_profile))
+        .route("/api/profile", put(set_profile))
+        .route("/api/players/:id/history", get(player_history))
+        .route("/api/replays/:id", get(replay))
         .layer(Extension(server_tx))
+        .layer(Extension(ledger))
+        .layer(Extension(lobby))
+        .layer(Extension(matchmaker))
+        .layer(Extension(ratings))
+        .layer(Extension(profiles))
+        .layer(Extension(history))
         .layer(CookieManagerLayer::new())
         .with_state(Arc::clone(&shared_state));
 
@@ -51,33 +104,293 @@ async fn main() {
         .serve(app.into_make_service())
         .await
         .unwrap();
-
-    if (run_game.await).is_ok() {}
 }
 
 async fn ws_handler(
     ws: WebSocketUpgrade,
     Path(id): Path<String>,
+    Query(handshake): Query<Handshake>,
     server_tx: Extension<GameServerHandle>,
     cookies: Cookies,
-    State(state): State<SharedState>,
 ) -> impl IntoResponse {
-    let visited = cookies.get(COOKIE_NAME);
-    match visited {
-        Some(player_id) => {
-            let uuid = Uuid::parse_str(player_id.value()).unwrap();
-            ws.on_upgrade(move |socket| handle_socket(socket, server_tx, id, uuid, state))
-        }
-        None => {
-            let player_id = Uuid::new_v4();
-            let player_clone = player_id;
-            cookies.add(Cookie::new(COOKIE_NAME, player_clone.to_string()));
-            ws.on_upgrade(move |socket| handle_socket(socket, server_tx, id, player_id, state))
+    //stale cached wasm bundles get told to reload before they touch the game server
+    if !handshake.is_supported() {
+        println!("protocol version mismatch {:?}", handshake.v);
+        return ws.on_upgrade(reject_version);
+    }
+
+    let player_id = player_id(&cookies);
+    ws.on_upgrade(move |socket| handle_socket(socket, server_tx, id, player_id, handshake.seq))
+}
+
+//the deathroll cookie is the player's identity, the first visit gets a new one
+fn player_id(cookies: &Cookies) -> Uuid {
+    let visited = cookies
+        .get(COOKIE_NAME)
+        .and_then(|cookie| Uuid::parse_str(cookie.value()).ok());
+    if let Some(player_id) = visited {
+        return player_id;
+    }
+
+    let player_id = Uuid::new_v4();
+    cookies.add(Cookie::new(COOKIE_NAME, player_id.to_string()));
+    player_id
+}
+
+async fn queue_ws(
+    ws: WebSocketUpgrade,
+    Query(handshake): Query<Handshake>,
+    Extension(matchmaker): Extension<MatchmakerHandle>,
+    cookies: Cookies,
+) -> impl IntoResponse {
+    if !handshake.is_supported() {
+        return ws.on_upgrade(reject_version);
+    }
+
+    let player_id = player_id(&cookies);
+    ws.on_upgrade(move |socket| queue_socket(socket, matchmaker, player_id))
+}
+
+async fn start_roll(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    Extension(ledger): Extension<SharedLedger>,
+    cookies: Cookies,
+    body: String,
+) -> Result<(), (StatusCode, Json<GameMessage>)> {
+    let pending = match serde_json::from_str::<NewRoom>(&body) {
+        Ok(mut new_room) => {
+            new_room.settings.turn_secs = new_room
+                .settings
+                .turn_secs
+                .map(|secs| secs.clamp(MIN_TURN_SECS, MAX_TURN_SECS));
+
+            PendingRoom::new(new_room.start_roll.to_string(), new_room.settings)
         }
+        //bare start roll, no turn clock
+        Err(_) => PendingRoom::new(body, RoomSettings::default()),
+    };
+
+    //refuse rooms the game could never start with instead of 404ing the invite later
+    let rules = Deathroll::with_seats(pending.start_roll(), pending.seats())
+        .and_then(|rules| rules.with_best_of(pending.best_of()));
+    if let Err(err) = rules {
+        return Err(bad_request(ErrorCode::from(err)));
+    }
+    //no wager, or one of the stakes the homepage offers
+    if pending.wager() > 0 && !WAGERS.contains(&pending.wager()) {
+        return Err(bad_request(ErrorCode::InvalidWager));
     }
+
+    //the creator stakes when they open the invite, catch an empty purse before that
+    let player_id = cookies
+        .get(COOKIE_NAME)
+        .and_then(|cookie| Uuid::parse_str(cookie.value()).ok());
+    let balance = match player_id {
+        Some(player_id) => ledger
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .balance(player_id),
+        //a new player starts with today's allowance
+        None => DAILY_ALLOWANCE,
+    };
+    if pending.wager() > balance {
+        return Err(bad_request(ErrorCode::InsufficientGold));
+    }
+
+    StartRoll::insert(&state, id, pending);
+
+    Ok(())
+}
+
+//public rooms with a free seat, newest first
+async fn lobby_rooms(Extension(lobby): Extension<SharedLobby>) -> Json<Vec<LobbyRoom>> {
+    Json(lobby.rooms())
 }
 
-async fn start_roll(Path(id): Path<String>, State(state): State<SharedState>, start_roll: String) {
-    state.write().unwrap().start_roll.insert(id, start_roll);
-    println!("start_rolls - {:?}", state.read().unwrap().start_roll)
+async fn lobby_ws(
+    ws: WebSocketUpgrade,
+    Extension(lobby): Extension<SharedLobby>,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| lobby_socket(socket, lobby))
+}
+
+#[derive(Deserialize)]
+struct LeaderboardQuery {
+    bracket: Option<String>,
+    limit: Option<usize>,
+}
+
+async fn leaderboard(
+    Query(query): Query<LeaderboardQuery>,
+    Extension(ratings): Extension<SharedRatings>,
+) -> Json<Vec<LeaderboardEntry>> {
+    let limit = query
+        .limit
+        .unwrap_or(LEADERBOARD_LIMIT)
+        .min(LEADERBOARD_LIMIT);
+    let entries = ratings
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .leaderboard(query.bracket.as_deref(), limit);
+
+    Json(entries)
+}
+
+//players who never saved a profile get an empty name and the first avatar
+async fn get_profile(
+    Extension(profiles): Extension<SharedProfiles>,
+    cookies: Cookies,
+) -> Json<Profile> {
+    let player_id = player_id(&cookies);
+    let profile = profiles
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(player_id)
+        .cloned()
+        .unwrap_or_else(|| Profile::for_seat(0));
+
+    Json(profile)
+}
+
+async fn set_profile(
+    Extension(profiles): Extension<SharedProfiles>,
+    cookies: Cookies,
+    Json(profile): Json<Profile>,
+) -> Result<Json<Profile>, (StatusCode, Json<GameMessage>)> {
+    let player_id = player_id(&cookies);
+    let profile = profiles
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .set(player_id, profile)
+        .map_err(bad_request)?;
+
+    Ok(Json(profile))
+}
+
+//`me` is whoever's cookie asks, the frontend doesn't know its own public id
+async fn player_history(
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+    Extension(history): Extension<SharedHistory>,
+    cookies: Cookies,
+) -> Json<HistoryPage> {
+    let player = match id.as_str() {
+        "me" => public_id(player_id(&cookies)).to_string(),
+        _ => id,
+    };
+    let page = history
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .page(&player, &query);
+
+    Json(page)
+}
+
+async fn replay(
+    Path(id): Path<String>,
+    Extension(history): Extension<SharedHistory>,
+) -> Result<Json<Replay>, StatusCode> {
+    let replay = history
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .replay(&id);
+
+    replay.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+fn bad_request(code: ErrorCode) -> (StatusCode, Json<GameMessage>) {
+    let msg = GameMessage::Error {
+        code,
+        message: code.message().to_string(),
+    };
+    (StatusCode::BAD_REQUEST, Json(msg))
+}
+
+//GET /api/admin/ledger?player=<uuid>&game=<id>&after=<entry id>&limit=<n>
+async fn admin_ledger(
+    Query(query): Query<LedgerQuery>,
+    Extension(ledger): Extension<SharedLedger>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<Json<Vec<LedgerEntry>>, StatusCode> {
+    admin(auth)?;
+
+    let entries = ledger
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entries(&query);
+
+    Ok(Json(entries))
+}
+
+#[derive(Deserialize)]
+struct WowlogQuery {
+    year: Option<i32>,
+}
+
+//POST /api/admin/wowlog?year=<year> with the chat log as the body, answers with the new games
+async fn admin_wowlog(
+    Query(query): Query<WowlogQuery>,
+    Extension(history): Extension<SharedHistory>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    log: String,
+) -> Result<Json<Vec<MatchRecord>>, StatusCode> {
+    admin(auth)?;
+
+    let year = query.year.unwrap_or_else(wowlog::this_year);
+    let replays = wowlog::parse(&log, year);
+    let imported = History::import(&history, replays);
+    println!("imported {} games from a chat log", imported.len());
+
+    Ok(Json(imported))
+}
+
+fn admin(auth: Option<TypedHeader<Authorization<Bearer>>>) -> Result<(), StatusCode> {
+    let Ok(token) = env::var(ADMIN_TOKEN_ENV) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    match auth {
+        Some(TypedHeader(Authorization(bearer))) if bearer.token() == token => Ok(()),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+fn import_wowlog(history: SharedHistory, args: &[String]) {
+    let Some(path) = args.first() else {
+        println!("usage: server {IMPORT_WOWLOG} <WoWChatLog.txt> [year]");
+        return;
+    };
+    let year = args
+        .get(1)
+        .and_then(|year| year.parse().ok())
+        .unwrap_or_else(wowlog::this_year);
+    //old logs aren't always utf-8, character names survive the lossy read
+    let log = match fs::read(path) {
+        Ok(log) => String::from_utf8_lossy(&log).into_owned(),
+        Err(err) => {
+            println!("can't read {path}: {err}");
+            return;
+        }
+    };
+
+    let replays = wowlog::parse(&log, year);
+    let found = replays.len();
+    let imported = History::import(&history, replays);
+    println!(
+        "found {found} deathrolls in {path}, imported {}",
+        imported.len()
+    );
+    for record in imported {
+        let names: Vec<&str> = record
+            .players
+            .iter()
+            .map(|player| player.profile.name.as_str())
+            .collect();
+        println!(
+            "  {} {} /replay/{}",
+            record.start_roll,
+            names.join(" vs "),
+            record.id
+        );
+    }
 }
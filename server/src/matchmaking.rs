@@ -0,0 +1,407 @@
+use std::{collections::VecDeque, time::Duration};
+
+use deathroll_core::Deathroll;
+use deathroll_protocol::{ErrorCode, OnTimeout, QueueMessage, RoomSettings, QUEUE_TIMEOUT_SECS};
+use tokio::{sync::mpsc, time};
+use uuid::Uuid;
+
+use crate::{
+    game_server::{now, PlayerId},
+    PendingRoom, SharedState, StartRoll,
+};
+
+//how often the queue looks for players who waited too long
+const TIMEOUT_TICK: Duration = Duration::from_secs(1);
+//strangers get a turn clock so nobody can stall a matched game
+const MATCH_TURN_SECS: u32 = 30;
+
+#[derive(Debug)]
+enum QueueCommand {
+    Join {
+        player_id: PlayerId,
+        start_roll: u32,
+        player_tx: mpsc::UnboundedSender<String>,
+    },
+    //the socket that queued, a player's other tabs keep their place
+    Cancel {
+        player_tx: mpsc::UnboundedSender<String>,
+    },
+}
+
+#[derive(Debug)]
+struct Waiting {
+    player_id: PlayerId,
+    start_roll: u32,
+    player_tx: mpsc::UnboundedSender<String>,
+    joined_at: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchmakerHandle {
+    queue_tx: mpsc::UnboundedSender<QueueCommand>,
+}
+
+impl MatchmakerHandle {
+    pub fn join(
+        &self,
+        player_id: PlayerId,
+        start_roll: u32,
+        player_tx: mpsc::UnboundedSender<String>,
+    ) {
+        let _ = self.queue_tx.send(QueueCommand::Join {
+            player_id,
+            start_roll,
+            player_tx,
+        });
+    }
+
+    pub fn cancel(&self, player_tx: mpsc::UnboundedSender<String>) {
+        let _ = self.queue_tx.send(QueueCommand::Cancel { player_tx });
+    }
+}
+
+//pairs players queued for the same start roll, the room is made like an invite link
+#[derive(Debug)]
+struct Matchmaker {
+    //first come first served across every bracket
+    queue: VecDeque<Waiting>,
+    start_rolls: SharedState,
+}
+
+pub fn spawn(start_rolls: SharedState) -> MatchmakerHandle {
+    let (queue_tx, queue_rx) = mpsc::unbounded_channel();
+    let matchmaker = Matchmaker {
+        queue: VecDeque::new(),
+        start_rolls,
+    };
+    tokio::spawn(matchmaker.run(queue_rx));
+
+    MatchmakerHandle { queue_tx }
+}
+
+impl Matchmaker {
+    async fn run(mut self, mut queue_rx: mpsc::UnboundedReceiver<QueueCommand>) {
+        let mut timeouts = time::interval(TIMEOUT_TICK);
+
+        loop {
+            tokio::select! {
+                cmd = queue_rx.recv() => match cmd {
+                    Some(QueueCommand::Join { player_id, start_roll, player_tx }) => {
+                        self.join(player_id, start_roll, player_tx)
+                    }
+                    Some(QueueCommand::Cancel { player_tx }) => self.cancel(&player_tx),
+                    None => break,
+                },
+                _ = timeouts.tick() => self.time_out(),
+            }
+        }
+    }
+
+    fn join(
+        &mut self,
+        player_id: PlayerId,
+        start_roll: u32,
+        player_tx: mpsc::UnboundedSender<String>,
+    ) {
+        if let Err(err) = Deathroll::new(start_roll) {
+            let code = ErrorCode::from(err);
+            send(
+                &player_tx,
+                &QueueMessage::Error {
+                    code,
+                    message: code.message().to_string(),
+                },
+            );
+            return;
+        }
+        //one place in line per player, the last tab to queue keeps it
+        if let Some(index) = self
+            .queue
+            .iter()
+            .position(|waiting| waiting.player_id == player_id)
+        {
+            if let Some(waiting) = self.queue.remove(index) {
+                if !waiting.player_tx.same_channel(&player_tx) {
+                    send(&waiting.player_tx, &QueueMessage::Cancelled);
+                }
+            }
+        }
+
+        let opponent = self
+            .queue
+            .iter()
+            .position(|waiting| waiting.start_roll == start_roll);
+        match opponent.and_then(|index| self.queue.remove(index)) {
+            Some(opponent) => {
+                let game_id = self.create_room(start_roll, [opponent.player_id, player_id]);
+                let matched = QueueMessage::Matched { game_id };
+                send(&opponent.player_tx, &matched);
+                send(&player_tx, &matched);
+            }
+            None => self.queue.push_back(Waiting {
+                player_id,
+                start_roll,
+                player_tx,
+                joined_at: now(),
+            }),
+        }
+
+        self.send_positions();
+    }
+
+    fn cancel(&mut self, player_tx: &mpsc::UnboundedSender<String>) {
+        let Some(index) = self
+            .queue
+            .iter()
+            .position(|waiting| waiting.player_tx.same_channel(player_tx))
+        else {
+            return;
+        };
+        if let Some(waiting) = self.queue.remove(index) {
+            send(&waiting.player_tx, &QueueMessage::Cancelled);
+        }
+
+        self.send_positions();
+    }
+
+    fn time_out(&mut self) {
+        let deadline = now().saturating_sub(QUEUE_TIMEOUT_SECS * 1000);
+        let before = self.queue.len();
+        self.queue.retain(|waiting| {
+            let expired = waiting.joined_at < deadline;
+            if expired {
+                send(&waiting.player_tx, &QueueMessage::TimedOut);
+            }
+            !expired
+        });
+
+        if self.queue.len() != before {
+            self.send_positions();
+        }
+    }
+
+    //goes through the same start roll table as POST /ws/:id, only these two can sit down
+    fn create_room(&self, start_roll: u32, players: [PlayerId; 2]) -> String {
+        let game_id = Uuid::new_v4().simple().to_string();
+        let settings = RoomSettings {
+            turn_secs: Some(MATCH_TURN_SECS),
+            on_timeout: OnTimeout::AutoRoll,
+            ..RoomSettings::default()
+        };
+        let pending = PendingRoom::new(start_roll.to_string(), settings).reserved_for(players);
+        StartRoll::insert(&self.start_rolls, game_id.clone(), pending);
+        println!("matched {} and {} in {game_id}", players[0], players[1]);
+
+        game_id
+    }
+
+    fn send_positions(&self) {
+        let waiting = self.queue.len();
+        for waiting_player in &self.queue {
+            let position = self
+                .queue
+                .iter()
+                .filter(|other| other.start_roll == waiting_player.start_roll)
+                .take_while(|other| other.player_id != waiting_player.player_id)
+                .count()
+                + 1;
+            send(
+                &waiting_player.player_tx,
+                &QueueMessage::Position { position, waiting },
+            );
+        }
+    }
+}
+
+fn send(player_tx: &mpsc::UnboundedSender<String>, msg: &QueueMessage) {
+    let _ = player_tx.send(serde_json::to_string(msg).unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use std::sync::{Arc, RwLock};
+
+    fn matchmaker() -> Matchmaker {
+        let store = Arc::new(MemoryStore::default());
+        Matchmaker {
+            queue: VecDeque::new(),
+            start_rolls: Arc::new(RwLock::new(StartRoll::load(store))),
+        }
+    }
+
+    fn socket() -> (
+        mpsc::UnboundedSender<String>,
+        mpsc::UnboundedReceiver<String>,
+    ) {
+        mpsc::unbounded_channel()
+    }
+
+    //everything a socket has been sent so far
+    fn messages(player_rx: &mut mpsc::UnboundedReceiver<String>) -> Vec<QueueMessage> {
+        let mut messages = Vec::new();
+        while let Ok(msg) = player_rx.try_recv() {
+            messages.push(serde_json::from_str(&msg).unwrap());
+        }
+        messages
+    }
+
+    fn matched(messages: &[QueueMessage]) -> Option<&str> {
+        messages.iter().find_map(|msg| match msg {
+            QueueMessage::Matched { game_id } => Some(game_id.as_str()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn players_with_the_same_start_roll_are_paired() {
+        let mut matchmaker = matchmaker();
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let (a_tx, mut a_rx) = socket();
+        let (b_tx, mut b_rx) = socket();
+        let (c_tx, mut c_rx) = socket();
+        matchmaker.join(a, 100, a_tx);
+        matchmaker.join(b, 200, b_tx);
+        matchmaker.join(c, 100, c_tx);
+
+        let game_id = matched(&messages(&mut a_rx)).unwrap().to_string();
+        assert_eq!(matched(&messages(&mut c_rx)), Some(game_id.as_str()));
+        //b is still waiting, alone in their bracket
+        assert_eq!(matchmaker.queue.len(), 1);
+        assert!(matches!(
+            messages(&mut b_rx).last(),
+            Some(QueueMessage::Position {
+                position: 1,
+                waiting: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn matched_rooms_are_reserved_and_have_a_turn_clock() {
+        let mut matchmaker = matchmaker();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let (a_tx, mut a_rx) = socket();
+        let (b_tx, _b_rx) = socket();
+        matchmaker.join(a, 500, a_tx);
+        matchmaker.join(b, 500, b_tx);
+        let game_id = matched(&messages(&mut a_rx)).unwrap().to_string();
+
+        let start_rolls = matchmaker.start_rolls.read().unwrap();
+        let pending = &start_rolls.start_roll[&game_id];
+        assert_eq!(pending.start_roll(), 500);
+        assert_eq!(pending.reserved, [a, b]);
+        assert_eq!(pending.settings.turn_secs, Some(30));
+        assert_eq!(pending.settings.on_timeout, OnTimeout::AutoRoll);
+        //it survives a restart like any invite link
+        let store = &start_rolls.store;
+        assert!(store.load_start_rolls().unwrap().contains_key(&game_id));
+    }
+
+    #[test]
+    fn positions_are_counted_per_start_roll() {
+        let mut matchmaker = matchmaker();
+        let (a_tx, _a_rx) = socket();
+        let (b_tx, _b_rx) = socket();
+        let (c_tx, mut c_rx) = socket();
+        matchmaker.join(Uuid::new_v4(), 100, a_tx);
+        matchmaker.join(Uuid::new_v4(), 200, b_tx);
+        matchmaker.join(Uuid::new_v4(), 300, c_tx);
+
+        assert!(matches!(
+            messages(&mut c_rx).last(),
+            Some(QueueMessage::Position {
+                position: 1,
+                waiting: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn only_the_socket_that_queued_can_leave() {
+        let mut matchmaker = matchmaker();
+        let player_id = Uuid::new_v4();
+        let (queued_tx, mut queued_rx) = socket();
+        let (other_tx, _other_rx) = socket();
+        matchmaker.join(player_id, 100, queued_tx.clone());
+
+        matchmaker.cancel(&other_tx);
+        assert_eq!(matchmaker.queue.len(), 1);
+        matchmaker.cancel(&queued_tx);
+        assert!(matchmaker.queue.is_empty());
+        assert!(matches!(
+            messages(&mut queued_rx).last(),
+            Some(QueueMessage::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn queueing_again_from_another_tab_moves_the_place() {
+        let mut matchmaker = matchmaker();
+        let player_id = Uuid::new_v4();
+        let (first_tx, mut first_rx) = socket();
+        let (second_tx, _second_rx) = socket();
+        matchmaker.join(player_id, 100, first_tx);
+        matchmaker.join(player_id, 200, second_tx.clone());
+
+        assert!(messages(&mut first_rx)
+            .iter()
+            .any(|msg| matches!(msg, QueueMessage::Cancelled)));
+        assert_eq!(matchmaker.queue.len(), 1);
+        assert!(matchmaker.queue[0].player_tx.same_channel(&second_tx));
+        assert_eq!(matchmaker.queue[0].start_roll, 200);
+    }
+
+    #[test]
+    fn nobody_is_matched_with_themselves() {
+        let mut matchmaker = matchmaker();
+        let player_id = Uuid::new_v4();
+        let (first_tx, _first_rx) = socket();
+        let (second_tx, mut second_rx) = socket();
+        matchmaker.join(player_id, 100, first_tx);
+        matchmaker.join(player_id, 100, second_tx);
+
+        assert_eq!(matched(&messages(&mut second_rx)), None);
+        assert_eq!(matchmaker.queue.len(), 1);
+    }
+
+    #[test]
+    fn bad_start_rolls_are_refused() {
+        let mut matchmaker = matchmaker();
+        let (player_tx, mut player_rx) = socket();
+        matchmaker.join(Uuid::new_v4(), 0, player_tx);
+
+        assert!(matchmaker.queue.is_empty());
+        assert!(matches!(
+            messages(&mut player_rx)[..],
+            [QueueMessage::Error { .. }]
+        ));
+    }
+
+    #[test]
+    fn players_who_waited_too_long_time_out() {
+        let mut matchmaker = matchmaker();
+        let (late_tx, mut late_rx) = socket();
+        let (fresh_tx, mut fresh_rx) = socket();
+        matchmaker.join(Uuid::new_v4(), 100, late_tx);
+        matchmaker.join(Uuid::new_v4(), 200, fresh_tx);
+        matchmaker.queue[0].joined_at -= QUEUE_TIMEOUT_SECS * 1000 + 1;
+        messages(&mut fresh_rx);
+
+        matchmaker.time_out();
+        assert!(matches!(
+            messages(&mut late_rx).last(),
+            Some(QueueMessage::TimedOut)
+        ));
+        assert_eq!(matchmaker.queue.len(), 1);
+        //the ones left are told they moved up
+        assert!(matches!(
+            messages(&mut fresh_rx)[..],
+            [QueueMessage::Position {
+                position: 1,
+                waiting: 1
+            }]
+        ));
+    }
+}
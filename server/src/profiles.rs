@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use deathroll_protocol::{ErrorCode, Profile, AVATAR_CHOICES, MAX_NAME_CHARS, MIN_NAME_CHARS};
+
+use crate::{game_server::PlayerId, store::RoomStore};
+
+pub type SharedProfiles = Arc<RwLock<Profiles>>;
+
+//blocked at the start of any word of the name, "fucker" is out but "Scunthorpe" isn't
+const BLOCKED: [&str; 12] = [
+    "fuck", "shit", "cunt", "nigg", "fagg", "bitch", "whore", "slut", "rape", "hitler", "vagina",
+    "retard",
+];
+//only blocked when they are the whole word, they hide inside too many normal names
+const BLOCKED_WORDS: [&str; 8] = [
+    "ass", "fag", "cum", "dick", "tits", "nazi", "nazis", "penis",
+];
+//normal words that start with a blocked stem
+const ALLOWED_PREFIXES: [&str; 3] = ["rapeseed", "slutsk", "shitake"];
+
+//the alphabets with letters that pass for each other
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+}
+
+#[derive(Debug)]
+pub struct Profiles {
+    profiles: HashMap<PlayerId, Profile>,
+    store: Arc<dyn RoomStore>,
+}
+
+impl Profiles {
+    pub fn load(store: Arc<dyn RoomStore>) -> Self {
+        let profiles = store.load_profiles().unwrap_or_else(|err| {
+            println!("failed to load profiles: {err}");
+            HashMap::new()
+        });
+
+        Self { profiles, store }
+    }
+
+    pub fn get(&self, player_id: PlayerId) -> Option<&Profile> {
+        self.profiles.get(&player_id)
+    }
+
+    pub fn set(&mut self, player_id: PlayerId, profile: Profile) -> Result<Profile, ErrorCode> {
+        let profile = validate(profile)?;
+        self.profiles.insert(player_id, profile.clone());
+        if let Err(err) = self.store.save_profiles(&self.profiles) {
+            println!("failed to save profiles: {err}");
+        }
+
+        Ok(profile)
+    }
+}
+
+//trims and squeezes the name, refuses anything that could render as something else
+pub fn validate(profile: Profile) -> Result<Profile, ErrorCode> {
+    let name = profile
+        .name
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let chars = name.chars().count();
+    if !(MIN_NAME_CHARS..=MAX_NAME_CHARS).contains(&chars) {
+        return Err(ErrorCode::InvalidName);
+    }
+    //alphanumeric keeps out control, zero width and bidi characters
+    let allowed = |c: char| c.is_alphanumeric() || matches!(c, ' ' | '.' | '-' | '_');
+    if !name.chars().all(allowed) || mixed_script(&name) {
+        return Err(ErrorCode::InvalidName);
+    }
+    if blocked(&name) {
+        return Err(ErrorCode::NameNotAllowed);
+    }
+    if !AVATAR_CHOICES.contains(&profile.avatar.as_str()) {
+        return Err(ErrorCode::InvalidAvatar);
+    }
+
+    Ok(Profile {
+        name,
+        avatar: profile.avatar,
+    })
+}
+
+fn blocked(name: &str) -> bool {
+    let unleet = |c: char| match c {
+        '0' => 'o',
+        '1' => 'i',
+        '3' => 'e',
+        '4' => 'a',
+        '5' => 's',
+        '7' => 't',
+        c => c,
+    };
+
+    //words are split on anything that isn't a letter or digit and where camel case starts a new one
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut after_lowercase = false;
+    for c in name.chars() {
+        let splits = !c.is_alphanumeric() || (c.is_uppercase() && after_lowercase);
+        if splits && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        after_lowercase = c.is_lowercase();
+        if c.is_alphanumeric() {
+            word.extend(capital(c).to_lowercase().map(fold).map(unleet));
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    //"f u c k" or "s.h.i.t" spelled out a letter at a time counts as one word
+    let mut spelled = String::new();
+    for word in &words {
+        if word.chars().count() == 1 {
+            spelled.push_str(word);
+        } else {
+            spelled.push(' ');
+        }
+    }
+    words.extend(spelled.split_whitespace().map(str::to_string));
+
+    words.iter().any(|word| {
+        let stem = BLOCKED.iter().any(|stem| word.starts_with(stem))
+            && !ALLOWED_PREFIXES
+                .iter()
+                .any(|prefix| word.starts_with(prefix));
+        stem || BLOCKED_WORDS.contains(&word.as_str())
+    })
+}
+
+fn script(c: char) -> Option<Script> {
+    match c {
+        'a'..='z'
+        | 'A'..='Z'
+        | '\u{00C0}'..='\u{024F}'
+        | '\u{1E00}'..='\u{1EFF}'
+        | '\u{FF21}'..='\u{FF3A}'
+        | '\u{FF41}'..='\u{FF5A}' => Some(Script::Latin),
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{052F}' => Some(Script::Cyrillic),
+        _ => None,
+    }
+}
+
+//latin with a cyrillic or greek letter or two is a lookalike, nobody's real name mixes them
+fn mixed_script(name: &str) -> bool {
+    let mut scripts = name.chars().filter_map(script);
+    let Some(first) = scripts.next() else {
+        return false;
+    };
+    scripts.any(|script| script != first)
+}
+
+//greek capitals that read as a different latin letter than their lowercase
+fn capital(c: char) -> char {
+    match c {
+        'Η' => 'H',
+        'Ν' => 'N',
+        'Υ' => 'Y',
+        c => c,
+    }
+}
+
+//lowercase greek and cyrillic lookalikes and fullwidth letters read as the latin letter,
+//so a name spelled all in one of them is checked like the latin one
+fn fold(c: char) -> char {
+    match c {
+        'а' | 'α' => 'a',
+        'в' | 'β' => 'b',
+        'с' | 'ϲ' => 'c',
+        'ԁ' => 'd',
+        'е' | 'ё' | 'ε' => 'e',
+        'һ' | 'н' => 'h',
+        'і' | 'ї' | 'ι' => 'i',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'ӏ' => 'l',
+        'м' => 'm',
+        'п' | 'η' => 'n',
+        'о' | 'ο' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'г' => 'r',
+        'ѕ' => 's',
+        'т' | 'τ' => 't',
+        'υ' => 'u',
+        'ѵ' | 'ν' => 'v',
+        'ԝ' | 'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        'ζ' => 'z',
+        '\u{FF41}'..='\u{FF5A}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_words() {
+        let names = [
+            "fuck",
+            "Big Fucker",
+            "FUCK",
+            "sh1t",
+            "f u c k",
+            "s.h.i.t",
+            "B1tch",
+            "DickHead",
+            "ass",
+            "a55",
+            "Nazi",
+            "HitlerFan",
+            "the_whore",
+            //all cyrillic, all greek and fullwidth
+            "ѕһіт",
+            "ѕһітту",
+            "ΒΙΤϹΗ",
+            "ΝΑΖΙ",
+            "ｆｕｃｋ",
+            "Ｓｈｉｔ",
+        ];
+        for name in names {
+            assert!(blocked(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn names_with_blocked_words_inside() {
+        let names = [
+            "Scunthorpe",
+            "Cassandra",
+            "Grape",
+            "Therapist",
+            "Dickens",
+            "Assassin",
+            "Cumberbatch",
+            "Matsushita",
+            "Nazir",
+            "Shiitake",
+            "Sussex",
+            "Hancock",
+            "Penistone",
+            "Tom Titsworth",
+            "Rapeseed",
+            "Slutsky",
+            "Shitake",
+            "Дмитрий",
+            "Σωκράτης",
+            "a b c",
+        ];
+        for name in names {
+            assert!(!blocked(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn validate_squeezes_and_checks() {
+        let profile = |name: &str| Profile {
+            name: name.to_string(),
+            avatar: AVATAR_CHOICES[0].to_string(),
+        };
+
+        assert_eq!(
+            validate(profile("  Cassandra   Scunthorpe ")).unwrap().name,
+            "Cassandra Scunthorpe"
+        );
+        assert_eq!(
+            validate(profile("big fucker")).unwrap_err(),
+            ErrorCode::NameNotAllowed
+        );
+        assert_eq!(
+            validate(profile("bad\u{202E}name")).unwrap_err(),
+            ErrorCode::InvalidName
+        );
+    }
+
+    #[test]
+    fn lookalikes_from_another_alphabet_are_refused() {
+        let profile = |name: &str| Profile {
+            name: name.to_string(),
+            avatar: AVATAR_CHOICES[0].to_string(),
+        };
+
+        //a cyrillic а and с
+        for name in ["C\u{0430}ssandra", "fu\u{0441}k", "Big Fu\u{0441}ker"] {
+            assert_eq!(
+                validate(profile(name)).unwrap_err(),
+                ErrorCode::InvalidName,
+                "{name}"
+            );
+        }
+        //a greek ο
+        assert_eq!(
+            validate(profile("Sc\u{03BF}tt")).unwrap_err(),
+            ErrorCode::InvalidName
+        );
+        for name in ["Дмитрий", "Σωκράτης", "Zoë 42", "ｆｕｌｌ ｗｉｄｔｈ"]
+        {
+            assert_eq!(validate(profile(name)).unwrap().name, name);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use deathroll_protocol::{bracket_key, LeaderboardEntry};
+use serde::{Deserialize, Serialize};
+
+use crate::{game_server::PlayerId, public_id, store::RoomStore};
+
+pub type SharedRatings = Arc<Mutex<Ratings>>;
+
+pub const START_RATING: f64 = 1200.0;
+//points at stake in a game, split between the losers when more than two play
+const K_FACTOR: f64 = 32.0;
+//nobody drops below this, the winner only gains what the losers actually lost
+const RATING_FLOOR: f64 = 100.0;
+pub const LEADERBOARD_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Rating {
+    pub rating: f64,
+    pub games: u32,
+    pub wins: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: START_RATING,
+            games: 0,
+            wins: 0,
+        }
+    }
+}
+
+pub type Board = HashMap<PlayerId, Rating>;
+
+//every finished game counts on the global board and on its start roll's bracket
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RatingTable {
+    pub global: Board,
+    pub brackets: HashMap<String, Board>,
+}
+
+#[derive(Debug)]
+pub struct Ratings {
+    table: RatingTable,
+    store: Arc<dyn RoomStore>,
+    //held for a whole save, so saves land in the order they copied the table
+    saving: Arc<Mutex<()>>,
+}
+
+impl Ratings {
+    pub fn load(store: Arc<dyn RoomStore>) -> Self {
+        let table = store.load_ratings().unwrap_or_else(|err| {
+            println!("failed to load ratings: {err}");
+            RatingTable::default()
+        });
+
+        Self {
+            table,
+            store,
+            saving: Arc::default(),
+        }
+    }
+
+    //writes the table as it is now, without holding the ratings while the file is written
+    pub fn save(ratings: &SharedRatings) {
+        let (saving, store) = {
+            let ratings = ratings.lock().unwrap_or_else(PoisonError::into_inner);
+            (Arc::clone(&ratings.saving), Arc::clone(&ratings.store))
+        };
+        let _saving = saving.lock().unwrap_or_else(PoisonError::into_inner);
+        let table = ratings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .table
+            .clone();
+        if let Err(err) = store.save_ratings(&table) {
+            println!("failed to save ratings: {err}");
+        }
+    }
+
+    //`winner` beat everyone else who sat at the table, `save` writes it out
+    pub fn record(&mut self, start_roll: u32, winner: PlayerId, losers: &[PlayerId]) {
+        if losers.is_empty() {
+            return;
+        }
+        update(&mut self.table.global, winner, losers);
+        let bracket = self
+            .table
+            .brackets
+            .entry(bracket_key(start_roll))
+            .or_default();
+        update(bracket, winner, losers);
+    }
+
+    //best first, None is the global board
+    pub fn leaderboard(&self, bracket: Option<&str>, limit: usize) -> Vec<LeaderboardEntry> {
+        let board = match bracket {
+            Some(bracket) => self.table.brackets.get(bracket),
+            None => Some(&self.table.global),
+        };
+        let Some(board) = board else {
+            return Vec::new();
+        };
+
+        let mut ratings: Vec<(&PlayerId, &Rating)> = board.iter().collect();
+        ratings.sort_by(|(_, a), (_, b)| b.rating.total_cmp(&a.rating));
+
+        ratings
+            .into_iter()
+            .take(limit)
+            .map(|(player_id, rating)| LeaderboardEntry {
+                player: public_id(*player_id).to_string(),
+                rating: rating.rating.round() as u32,
+                games: rating.games,
+                wins: rating.wins,
+            })
+            .collect()
+    }
+}
+
+//elo, the winner plays each loser once with the ratings from before the game
+fn update(board: &mut Board, winner: PlayerId, losers: &[PlayerId]) {
+    let share = K_FACTOR / losers.len() as f64;
+    let winner_rating = board.entry(winner).or_default().rating;
+
+    let mut gained = 0.0;
+    for loser in losers {
+        let rating = board.entry(*loser).or_default();
+        let expected = 1.0 / (1.0 + 10f64.powf((rating.rating - winner_rating) / 400.0));
+        let delta = (share * (1.0 - expected)).min((rating.rating - RATING_FLOOR).max(0.0));
+        rating.rating -= delta;
+        rating.games += 1;
+        gained += delta;
+    }
+
+    let rating = board.entry(winner).or_default();
+    rating.rating += gained;
+    rating.games += 1;
+    rating.wins += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use uuid::Uuid;
+
+    fn players(count: usize) -> Vec<PlayerId> {
+        (0..count).map(|_| Uuid::new_v4()).collect()
+    }
+
+    fn rating(board: &Board, player_id: PlayerId) -> f64 {
+        board[&player_id].rating
+    }
+
+    fn assert_close(rating: f64, expected: f64) {
+        assert!((rating - expected).abs() < 0.01, "{rating} != {expected}");
+    }
+
+    #[test]
+    fn even_players_trade_half_the_k_factor() {
+        let [winner, loser] = players(2)[..] else {
+            unreachable!()
+        };
+        let mut board = Board::new();
+        update(&mut board, winner, &[loser]);
+
+        assert_close(rating(&board, winner), 1216.0);
+        assert_close(rating(&board, loser), 1184.0);
+        assert_eq!((board[&winner].games, board[&winner].wins), (1, 1));
+        assert_eq!((board[&loser].games, board[&loser].wins), (1, 0));
+    }
+
+    #[test]
+    fn an_upset_moves_more_points() {
+        let [winner, loser] = players(2)[..] else {
+            unreachable!()
+        };
+        let mut board = Board::new();
+        board.entry(winner).or_default().rating = 1000.0;
+        board.entry(loser).or_default().rating = 1400.0;
+        update(&mut board, winner, &[loser]);
+
+        //the loser was expected to win 10 to 1
+        assert_close(rating(&board, winner), 1000.0 + 32.0 * 10.0 / 11.0);
+        assert_close(rating(&board, loser), 1400.0 - 32.0 * 10.0 / 11.0);
+    }
+
+    #[test]
+    fn k_is_split_between_the_losers() {
+        let players = players(3);
+        let mut board = Board::new();
+        update(&mut board, players[0], &players[1..]);
+
+        assert_close(rating(&board, players[0]), 1216.0);
+        assert_close(rating(&board, players[1]), 1192.0);
+        assert_close(rating(&board, players[2]), 1192.0);
+    }
+
+    #[test]
+    fn ratings_stop_at_the_floor() {
+        let [winner, loser] = players(2)[..] else {
+            unreachable!()
+        };
+        let mut board = Board::new();
+        board.entry(winner).or_default().rating = 110.0;
+        board.entry(loser).or_default().rating = 110.0;
+        update(&mut board, winner, &[loser]);
+
+        assert_close(rating(&board, loser), RATING_FLOOR);
+        assert_close(rating(&board, winner), 120.0);
+        update(&mut board, winner, &[loser]);
+        assert_close(rating(&board, loser), RATING_FLOOR);
+    }
+
+    #[test]
+    fn saved_ratings_are_loaded_back() {
+        let store: Arc<dyn RoomStore> = Arc::new(MemoryStore::default());
+        let ratings: SharedRatings = Arc::new(Mutex::new(Ratings::load(Arc::clone(&store))));
+        let [winner, loser] = players(2)[..] else {
+            unreachable!()
+        };
+        ratings.lock().unwrap().record(100, winner, &[loser]);
+        //a game with nobody to beat doesn't count
+        ratings.lock().unwrap().record(100, winner, &[]);
+        Ratings::save(&ratings);
+
+        let loaded = Ratings::load(store);
+        assert_close(rating(&loaded.table.global, winner), 1216.0);
+        assert_close(
+            rating(&loaded.table.brackets[&bracket_key(100)], loser),
+            1184.0,
+        );
+        let leaderboard = loaded.leaderboard(None, LEADERBOARD_LIMIT);
+        assert_eq!(leaderboard[0].player, public_id(winner).to_string());
+        assert_eq!(leaderboard[0].rating, 1216);
+    }
+}
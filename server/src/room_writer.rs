@@ -0,0 +1,206 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use tokio::{
+    sync::{mpsc, oneshot},
+    task, time,
+};
+
+use crate::{
+    game_server::{GameId, GameState},
+    store::RoomStore,
+};
+
+//a burst of commands for one room ends up as a single write
+const SAVE_DEBOUNCE: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+enum RoomWrite {
+    Save(Box<GameState>),
+    Archive(Box<GameState>),
+    Remove,
+}
+
+#[derive(Debug)]
+enum WriterMsg {
+    Write(GameId, RoomWrite),
+    //a claimed or expired invite link
+    RemoveStartRoll(GameId),
+    //answered once everything sent before it is in the store
+    Flush(oneshot::Sender<()>),
+}
+
+//what piled up while the writer waited
+#[derive(Debug, Default)]
+struct Batch {
+    rooms: HashMap<GameId, RoomWrite>,
+    start_rolls: Vec<GameId>,
+}
+
+//room writes leave the game loop here, the store is written from a blocking thread
+//with only the latest write per room, one batch at a time so they land in order
+#[derive(Debug, Clone)]
+pub struct RoomWriter {
+    tx: mpsc::UnboundedSender<WriterMsg>,
+}
+
+impl RoomWriter {
+    pub fn spawn(store: Arc<dyn RoomStore>) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_rooms(store, rx));
+
+        Self { tx }
+    }
+
+    pub fn save(&self, game_id: &str, game_state: &GameState) {
+        let write = RoomWrite::Save(Box::new(game_state.clone()));
+        let _ = self.tx.send(WriterMsg::Write(game_id.to_string(), write));
+    }
+
+    pub fn archive(&self, game_id: &str, game_state: GameState) {
+        let write = RoomWrite::Archive(Box::new(game_state));
+        let _ = self.tx.send(WriterMsg::Write(game_id.to_string(), write));
+    }
+
+    pub fn remove(&self, game_id: &str) {
+        let _ = self
+            .tx
+            .send(WriterMsg::Write(game_id.to_string(), RoomWrite::Remove));
+    }
+
+    pub fn remove_start_roll(&self, game_id: &str) {
+        let _ = self
+            .tx
+            .send(WriterMsg::RemoveStartRoll(game_id.to_string()));
+    }
+
+    //a restarted game server waits for this before it loads the rooms back
+    pub async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.tx.send(WriterMsg::Flush(done_tx)).is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+}
+
+async fn write_rooms(store: Arc<dyn RoomStore>, mut rx: mpsc::UnboundedReceiver<WriterMsg>) {
+    let mut pending = Batch::default();
+    let mut flushed = Vec::new();
+
+    while let Some(msg) = rx.recv().await {
+        queue(msg, &mut pending, &mut flushed);
+        //a flush is waited on, it doesn't wait for more writes
+        if flushed.is_empty() {
+            time::sleep(SAVE_DEBOUNCE).await;
+        }
+        while let Ok(msg) = rx.try_recv() {
+            queue(msg, &mut pending, &mut flushed);
+        }
+
+        let batch = std::mem::take(&mut pending);
+        let store = Arc::clone(&store);
+        if let Err(err) = task::spawn_blocking(move || write_batch(&*store, batch)).await {
+            println!("room writer crashed: {err}");
+        }
+        for done in flushed.drain(..) {
+            let _ = done.send(());
+        }
+    }
+}
+
+fn queue(msg: WriterMsg, pending: &mut Batch, flushed: &mut Vec<oneshot::Sender<()>>) {
+    match msg {
+        WriterMsg::Write(game_id, write) => {
+            pending.rooms.insert(game_id, write);
+        }
+        WriterMsg::RemoveStartRoll(game_id) => pending.start_rolls.push(game_id),
+        WriterMsg::Flush(done) => flushed.push(done),
+    }
+}
+
+fn write_batch(store: &dyn RoomStore, batch: Batch) {
+    for game_id in batch.start_rolls {
+        if let Err(err) = store.remove_start_roll(&game_id) {
+            println!("failed to remove start roll {game_id}: {err}");
+        }
+    }
+    for (game_id, write) in batch.rooms {
+        let result = match &write {
+            RoomWrite::Save(game_state) => store.save_room(&game_id, game_state),
+            RoomWrite::Archive(game_state) => store.archive_room(&game_id, game_state),
+            RoomWrite::Remove => store.remove_room(&game_id),
+        };
+        if let Err(err) = result {
+            println!("failed to write room {game_id}: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{store::MemoryStore, PendingRoom};
+    use deathroll_core::Deathroll;
+    use deathroll_protocol::RoomSettings;
+    use uuid::Uuid;
+
+    fn room() -> GameState {
+        let rules = Deathroll::new(1000).unwrap();
+        GameState::new(rules, Uuid::new_v4(), RoomSettings::default(), Vec::new())
+    }
+
+    fn room_ids(store: &MemoryStore) -> Vec<GameId> {
+        let mut game_ids: Vec<GameId> = store.load_rooms().unwrap().into_keys().collect();
+        game_ids.sort();
+        game_ids
+    }
+
+    #[tokio::test]
+    async fn saves_wait_for_the_debounce_or_a_flush() {
+        let store = Arc::new(MemoryStore::default());
+        let writer = RoomWriter::spawn(store.clone());
+        writer.save("a", &room());
+
+        time::sleep(SAVE_DEBOUNCE / 5).await;
+        assert!(room_ids(&store).is_empty());
+        writer.flush().await;
+        assert_eq!(room_ids(&store), ["a"]);
+
+        writer.save("b", &room());
+        time::sleep(SAVE_DEBOUNCE * 2).await;
+        assert_eq!(room_ids(&store), ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn the_last_write_for_a_room_wins() {
+        let store = Arc::new(MemoryStore::default());
+        let writer = RoomWriter::spawn(store.clone());
+        writer.save("saved", &room());
+        writer.remove("saved");
+        writer.save("saved", &room());
+        writer.save("removed", &room());
+        writer.remove("removed");
+        writer.save("archived", &room());
+        writer.archive("archived", room());
+        writer.flush().await;
+        assert_eq!(room_ids(&store), ["saved"]);
+
+        //a later batch lands after the earlier one
+        writer.remove("saved");
+        writer.flush().await;
+        assert!(room_ids(&store).is_empty());
+    }
+
+    #[tokio::test]
+    async fn start_rolls_are_removed_with_the_batch() {
+        let store = Arc::new(MemoryStore::default());
+        let pending = PendingRoom::new("100".to_string(), RoomSettings::default());
+        store.save_start_roll("invite", &pending).unwrap();
+        let writer = RoomWriter::spawn(store.clone());
+
+        writer.remove_start_roll("invite");
+        writer.save("invite", &room());
+        writer.flush().await;
+        assert!(store.load_start_rolls().unwrap().is_empty());
+        assert_eq!(room_ids(&store), ["invite"]);
+    }
+}
@@ -0,0 +1,595 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    fmt::Debug,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use deathroll_protocol::{MatchRecord, Profile, Replay};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    game_server::{GameId, GameState, PlayerId},
+    ledger::LedgerEntry,
+    ratings::RatingTable,
+    PendingRoom,
+};
+
+const STORE_DIR_ENV: &str = "DEATHROLL_STORE_DIR";
+//closed rooms, finished games and replays kept by the memory store, the oldest are forgotten first
+const MEMORY_ARCHIVE_LIMIT: usize = 1000;
+
+//where rooms and unclaimed start rolls live between restarts
+pub trait RoomStore: Debug + Send + Sync {
+    fn load_rooms(&self) -> io::Result<HashMap<GameId, GameState>>;
+    fn save_room(&self, game_id: &str, game_state: &GameState) -> io::Result<()>;
+    fn remove_room(&self, game_id: &str) -> io::Result<()>;
+    //move a closed room out of the live set, it won't be loaded on the next boot
+    fn archive_room(&self, game_id: &str, game_state: &GameState) -> io::Result<()>;
+
+    fn load_start_rolls(&self) -> io::Result<HashMap<GameId, PendingRoom>>;
+    fn save_start_roll(&self, game_id: &str, pending: &PendingRoom) -> io::Result<()>;
+    fn remove_start_roll(&self, game_id: &str) -> io::Result<()>;
+
+    fn load_ledger(&self) -> io::Result<Vec<LedgerEntry>>;
+    //entries are never rewritten, a batch is appended in one go
+    fn append_ledger(&self, entries: &[LedgerEntry]) -> io::Result<()>;
+
+    fn load_ratings(&self) -> io::Result<RatingTable>;
+    fn save_ratings(&self, ratings: &RatingTable) -> io::Result<()>;
+
+    fn load_profiles(&self) -> io::Result<HashMap<PlayerId, Profile>>;
+    fn save_profiles(&self, profiles: &HashMap<PlayerId, Profile>) -> io::Result<()>;
+
+    //finished games, oldest first, like the ledger they are only ever appended
+    fn load_history(&self) -> io::Result<Vec<MatchRecord>>;
+    fn append_history(&self, records: &[MatchRecord]) -> io::Result<()>;
+    //how many finished games are kept, None keeps them all
+    fn history_limit(&self) -> Option<usize> {
+        None
+    }
+
+    fn load_replay(&self, id: &str) -> io::Result<Option<Replay>>;
+    fn save_replay(&self, replay: &Replay) -> io::Result<()>;
+}
+
+//set DEATHROLL_STORE_DIR to keep games on disk, otherwise they only live in memory
+pub fn from_env() -> Arc<dyn RoomStore> {
+    match env::var(STORE_DIR_ENV) {
+        Ok(dir) => match FileStore::open(&dir) {
+            Ok(store) => {
+                println!("storing rooms in {dir}");
+                Arc::new(store)
+            }
+            Err(err) => {
+                println!("can't open room store {dir}: {err}, falling back to memory");
+                Arc::new(MemoryStore::default())
+            }
+        },
+        Err(_) => Arc::new(MemoryStore::default()),
+    }
+}
+
+//rooms are kept serialized so both backends behave the same
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    rooms: Mutex<HashMap<GameId, String>>,
+    archive: Mutex<VecDeque<(GameId, String)>>,
+    start_rolls: Mutex<HashMap<GameId, PendingRoom>>,
+    ledger: Mutex<Vec<LedgerEntry>>,
+    ratings: Mutex<Option<String>>,
+    profiles: Mutex<HashMap<PlayerId, Profile>>,
+    //like the archive only the latest are kept
+    history: Mutex<VecDeque<MatchRecord>>,
+    replays: Mutex<VecDeque<(String, String)>>,
+}
+
+impl RoomStore for MemoryStore {
+    fn load_rooms(&self) -> io::Result<HashMap<GameId, GameState>> {
+        self.rooms
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(game_id, room)| Ok((game_id.clone(), serde_json::from_str(room)?)))
+            .collect()
+    }
+
+    fn save_room(&self, game_id: &str, game_state: &GameState) -> io::Result<()> {
+        let room = serde_json::to_string(game_state)?;
+        self.rooms
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(game_id.to_string(), room);
+        Ok(())
+    }
+
+    fn remove_room(&self, game_id: &str) -> io::Result<()> {
+        self.rooms
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(game_id);
+        Ok(())
+    }
+
+    fn archive_room(&self, game_id: &str, game_state: &GameState) -> io::Result<()> {
+        let room = serde_json::to_string(game_state)?;
+        self.rooms
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(game_id);
+
+        let mut archive = self.archive.lock().unwrap_or_else(PoisonError::into_inner);
+        archive.push_back((game_id.to_string(), room));
+        while archive.len() > MEMORY_ARCHIVE_LIMIT {
+            archive.pop_front();
+        }
+        Ok(())
+    }
+
+    fn load_start_rolls(&self) -> io::Result<HashMap<GameId, PendingRoom>> {
+        Ok(self
+            .start_rolls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+
+    fn save_start_roll(&self, game_id: &str, pending: &PendingRoom) -> io::Result<()> {
+        self.start_rolls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(game_id.to_string(), pending.clone());
+        Ok(())
+    }
+
+    fn remove_start_roll(&self, game_id: &str) -> io::Result<()> {
+        self.start_rolls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(game_id);
+        Ok(())
+    }
+
+    fn load_ledger(&self) -> io::Result<Vec<LedgerEntry>> {
+        Ok(self
+            .ledger
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+
+    fn append_ledger(&self, entries: &[LedgerEntry]) -> io::Result<()> {
+        self.ledger
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend_from_slice(entries);
+        Ok(())
+    }
+
+    fn load_ratings(&self) -> io::Result<RatingTable> {
+        match self
+            .ratings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+        {
+            Some(ratings) => Ok(serde_json::from_str(ratings)?),
+            None => Ok(RatingTable::default()),
+        }
+    }
+
+    fn save_ratings(&self, ratings: &RatingTable) -> io::Result<()> {
+        *self.ratings.lock().unwrap_or_else(PoisonError::into_inner) =
+            Some(serde_json::to_string(ratings)?);
+        Ok(())
+    }
+
+    fn load_profiles(&self) -> io::Result<HashMap<PlayerId, Profile>> {
+        Ok(self
+            .profiles
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+
+    fn save_profiles(&self, profiles: &HashMap<PlayerId, Profile>) -> io::Result<()> {
+        *self.profiles.lock().unwrap_or_else(PoisonError::into_inner) = profiles.clone();
+        Ok(())
+    }
+
+    fn load_history(&self) -> io::Result<Vec<MatchRecord>> {
+        let history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(history.iter().cloned().collect())
+    }
+
+    fn append_history(&self, records: &[MatchRecord]) -> io::Result<()> {
+        let mut history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        history.extend(records.iter().cloned());
+        while history.len() > MEMORY_ARCHIVE_LIMIT {
+            history.pop_front();
+        }
+        Ok(())
+    }
+
+    fn history_limit(&self) -> Option<usize> {
+        Some(MEMORY_ARCHIVE_LIMIT)
+    }
+
+    fn load_replay(&self, id: &str) -> io::Result<Option<Replay>> {
+        let replays = self.replays.lock().unwrap_or_else(PoisonError::into_inner);
+        match replays.iter().find(|(replay_id, _)| replay_id == id) {
+            Some((_, replay)) => Ok(Some(serde_json::from_str(replay)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_replay(&self, replay: &Replay) -> io::Result<()> {
+        let json = serde_json::to_string(replay)?;
+
+        let mut replays = self.replays.lock().unwrap_or_else(PoisonError::into_inner);
+        replays.push_back((replay.game.id.clone(), json));
+        while replays.len() > MEMORY_ARCHIVE_LIMIT {
+            replays.pop_front();
+        }
+        Ok(())
+    }
+}
+
+//one json file per room under <dir>/rooms and per start roll under <dir>/start_rolls,
+//closed rooms are moved to <dir>/archive, the gold ledger is <dir>/ledger.jsonl and
+//ratings and profiles are <dir>/ratings.json and <dir>/profiles.json, finished games
+//are appended to <dir>/history.jsonl and their replays are under <dir>/replays
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(dir.join("rooms"))?;
+        fs::create_dir_all(dir.join("archive"))?;
+        fs::create_dir_all(dir.join("start_rolls"))?;
+        fs::create_dir_all(dir.join("replays"))?;
+
+        Ok(Self { dir })
+    }
+
+    fn path(&self, kind: &str, game_id: &str) -> io::Result<PathBuf> {
+        //game ids come straight from the url, never let one escape the store dir
+        let valid = !game_id.is_empty()
+            && game_id.len() <= 64
+            && game_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid game id {game_id:?}"),
+            ));
+        }
+
+        Ok(self.dir.join(kind).join(format!("{game_id}.json")))
+    }
+
+    fn write<T: Serialize + ?Sized>(&self, kind: &str, game_id: &str, value: &T) -> io::Result<()> {
+        self.write_file(self.path(kind, game_id)?, value)
+    }
+
+    fn write_file<T: Serialize + ?Sized>(&self, path: PathBuf, value: &T) -> io::Result<()> {
+        //write then rename so a crash never leaves half a room behind
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(value)?)?;
+        fs::rename(tmp, path)
+    }
+
+    fn remove(&self, kind: &str, game_id: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(kind, game_id)?) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn read_all<T: DeserializeOwned>(&self, kind: &str) -> io::Result<HashMap<GameId, T>> {
+        let mut values = HashMap::new();
+        for entry in fs::read_dir(self.dir.join(kind))? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(game_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            match serde_json::from_slice(&fs::read(&path)?) {
+                Ok(value) => {
+                    values.insert(game_id.to_string(), value);
+                }
+                Err(err) => println!("skipping unreadable {}: {err}", path.display()),
+            }
+        }
+
+        Ok(values)
+    }
+
+    //one json value per line of <dir>/<file>
+    fn read_lines<T: DeserializeOwned>(&self, file: &str) -> io::Result<Vec<T>> {
+        let lines = match fs::File::open(self.dir.join(file)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            lines => lines?,
+        };
+
+        let mut values = Vec::new();
+        for line in BufReader::new(lines).lines() {
+            let line = line?;
+            //a crash mid append can leave a torn last line
+            match serde_json::from_str(&line) {
+                Ok(value) => values.push(value),
+                Err(err) => println!("skipping unreadable {file} line: {err}"),
+            }
+        }
+
+        Ok(values)
+    }
+
+    fn append_lines<T: Serialize>(&self, file: &str, values: &[T]) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(self.dir.join(file))?;
+
+        //after a torn last line start on a fresh one, otherwise the first value lands on
+        //the torn line and gets skipped along with it on the next boot
+        let mut lines = Vec::new();
+        if file.metadata()?.len() > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                lines.push(b'\n');
+            }
+        }
+        for value in values {
+            serde_json::to_writer(&mut lines, value)?;
+            lines.push(b'\n');
+        }
+
+        file.write_all(&lines)
+    }
+}
+
+impl RoomStore for FileStore {
+    fn load_rooms(&self) -> io::Result<HashMap<GameId, GameState>> {
+        self.read_all("rooms")
+    }
+
+    fn save_room(&self, game_id: &str, game_state: &GameState) -> io::Result<()> {
+        self.write("rooms", game_id, game_state)
+    }
+
+    fn remove_room(&self, game_id: &str) -> io::Result<()> {
+        self.remove("rooms", game_id)
+    }
+
+    fn archive_room(&self, game_id: &str, game_state: &GameState) -> io::Result<()> {
+        self.write("archive", game_id, game_state)?;
+        self.remove("rooms", game_id)
+    }
+
+    fn load_start_rolls(&self) -> io::Result<HashMap<GameId, PendingRoom>> {
+        self.read_all("start_rolls")
+    }
+
+    fn save_start_roll(&self, game_id: &str, pending: &PendingRoom) -> io::Result<()> {
+        self.write("start_rolls", game_id, pending)
+    }
+
+    fn remove_start_roll(&self, game_id: &str) -> io::Result<()> {
+        self.remove("start_rolls", game_id)
+    }
+
+    fn load_ledger(&self) -> io::Result<Vec<LedgerEntry>> {
+        self.read_lines("ledger.jsonl")
+    }
+
+    fn append_ledger(&self, entries: &[LedgerEntry]) -> io::Result<()> {
+        self.append_lines("ledger.jsonl", entries)
+    }
+
+    fn load_ratings(&self) -> io::Result<RatingTable> {
+        match fs::read(self.dir.join("ratings.json")) {
+            Ok(ratings) => Ok(serde_json::from_slice(&ratings)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(RatingTable::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save_ratings(&self, ratings: &RatingTable) -> io::Result<()> {
+        self.write_file(self.dir.join("ratings.json"), ratings)
+    }
+
+    fn load_profiles(&self) -> io::Result<HashMap<PlayerId, Profile>> {
+        match fs::read(self.dir.join("profiles.json")) {
+            Ok(profiles) => Ok(serde_json::from_slice(&profiles)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save_profiles(&self, profiles: &HashMap<PlayerId, Profile>) -> io::Result<()> {
+        self.write_file(self.dir.join("profiles.json"), profiles)
+    }
+
+    fn load_history(&self) -> io::Result<Vec<MatchRecord>> {
+        self.read_lines("history.jsonl")
+    }
+
+    fn append_history(&self, records: &[MatchRecord]) -> io::Result<()> {
+        self.append_lines("history.jsonl", records)
+    }
+
+    fn load_replay(&self, id: &str) -> io::Result<Option<Replay>> {
+        match fs::read(self.path("replays", id)?) {
+            Ok(replay) => Ok(Some(serde_json::from_slice(&replay)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save_replay(&self, replay: &Replay) -> io::Result<()> {
+        self.write("replays", &replay.game.id, replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ledger::LedgerReason,
+        ratings::{Rating, START_RATING},
+    };
+    use deathroll_core::Deathroll;
+    use deathroll_protocol::{MatchPlayer, RoomSettings};
+    use uuid::Uuid;
+
+    //a fresh store dir under the system temp dir
+    fn file_store() -> (FileStore, PathBuf) {
+        let dir = env::temp_dir().join(format!("deathroll-store-{}", Uuid::new_v4()));
+        (FileStore::open(&dir).unwrap(), dir)
+    }
+
+    fn entry(id: u64, amount: i64) -> LedgerEntry {
+        LedgerEntry {
+            id,
+            at: 0,
+            player_id: Uuid::nil(),
+            amount,
+            balance: amount as u64,
+            reason: LedgerReason::Allowance,
+            game_id: None,
+        }
+    }
+
+    fn room() -> GameState {
+        let rules = Deathroll::new(1000).unwrap();
+        GameState::new(rules, Uuid::new_v4(), RoomSettings::default(), Vec::new())
+    }
+
+    //rooms and start rolls keep their fields private, compare what gets written
+    fn json<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    fn record(id: &str) -> MatchRecord {
+        MatchRecord {
+            id: id.to_string(),
+            game_id: "room".to_string(),
+            players: vec![MatchPlayer {
+                id: "player".to_string(),
+                profile: Profile::for_seat(0),
+            }],
+            start_roll: 100,
+            rolls: Vec::new(),
+            winner: 0,
+            started_at: 1,
+            finished_at: 2,
+        }
+    }
+
+    #[test]
+    fn rooms_and_start_rolls_round_trip() {
+        let (store, dir) = file_store();
+        let (open, closed) = (room(), room());
+        store.save_room("open", &open).unwrap();
+        store.save_room("closed", &closed).unwrap();
+        store.archive_room("closed", &closed).unwrap();
+
+        let rooms = store.load_rooms().unwrap();
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(json(&rooms["open"]), json(&open));
+        assert!(dir.join("archive").join("closed.json").exists());
+        store.remove_room("open").unwrap();
+        //removing twice is fine, the writer may remove a room that was never saved
+        store.remove_room("open").unwrap();
+        assert!(store.load_rooms().unwrap().is_empty());
+
+        let pending = PendingRoom::new("500".to_string(), RoomSettings::default());
+        store.save_start_roll("invite", &pending).unwrap();
+        let start_rolls = store.load_start_rolls().unwrap();
+        assert_eq!(json(&start_rolls["invite"]), json(&pending));
+        store.remove_start_roll("invite").unwrap();
+        assert!(store.load_start_rolls().unwrap().is_empty());
+
+        assert!(store.save_room("../escape", &open).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ratings_and_profiles_round_trip() {
+        let (store, dir) = file_store();
+        assert!(store.load_ratings().unwrap().global.is_empty());
+        assert!(store.load_profiles().unwrap().is_empty());
+
+        let player_id = Uuid::new_v4();
+        let mut ratings = RatingTable::default();
+        let rating = Rating {
+            rating: START_RATING + 16.0,
+            games: 1,
+            wins: 1,
+        };
+        ratings.global.insert(player_id, rating);
+        store.save_ratings(&ratings).unwrap();
+        let profiles = HashMap::from([(player_id, Profile::for_seat(1))]);
+        store.save_profiles(&profiles).unwrap();
+
+        assert_eq!(json(&store.load_ratings().unwrap()), json(&ratings));
+        assert_eq!(store.load_profiles().unwrap(), profiles);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn history_and_replays_round_trip() {
+        let (store, dir) = file_store();
+        store.append_history(&[record("first")]).unwrap();
+        store.append_history(&[record("second")]).unwrap();
+        let history = store.load_history().unwrap();
+        assert_eq!(history, [record("first"), record("second")]);
+
+        let replay = Replay {
+            version: 1,
+            game: record("first"),
+            events: Vec::new(),
+        };
+        store.save_replay(&replay).unwrap();
+        let loaded = store.load_replay("first").unwrap().unwrap();
+        assert_eq!(loaded.game, replay.game);
+        assert!(store.load_replay("second").unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn an_append_after_a_torn_line_survives_a_reload() {
+        let (store, dir) = file_store();
+        store.append_ledger(&[entry(1, 100)]).unwrap();
+        //a crash halfway through the next append
+        let mut ledger = OpenOptions::new()
+            .append(true)
+            .open(dir.join("ledger.jsonl"))
+            .unwrap();
+        ledger.write_all(b"{\"id\":2,\"at\":").unwrap();
+
+        store.append_ledger(&[entry(3, 50)]).unwrap();
+
+        let ids: Vec<u64> = store
+            .load_ledger()
+            .unwrap()
+            .iter()
+            .map(|ledger_entry| ledger_entry.id)
+            .collect();
+        assert_eq!(ids, [1, 3]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,21 +2,40 @@ use axum::{
     extract::ws::{Message, WebSocket},
     response::Extension,
 };
+use deathroll_protocol::{GameMessage, QueueMsg, WsMsg, PROTOCOL_VERSION};
 use futures::{sink::SinkExt, stream::StreamExt};
-use tokio::sync::mpsc;
+use std::{
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc},
+    time::{self, Instant},
+};
 use uuid::Uuid;
 
 use crate::{
-    game_server::{GameMessage, GameServerHandle},
-    SharedState,
+    game_server::{Action, GameServerHandle},
+    lobby::SharedLobby,
+    matchmaking::MatchmakerHandle,
 };
 
-#[derive(serde::Serialize, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub enum WsMsg {
-    Ping,
-    Close,
-    Roll,
+//the lobby list is resent this often even when nothing changed so ages stay fresh
+const LOBBY_REFRESH: Duration = Duration::from_secs(15);
+//game sockets are pinged this often, one that hasn't answered with anything in
+//HEARTBEAT_TIMEOUT is half open and gets closed
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
+
+//tell a client built against another protocol version to reload, then hang up
+pub async fn reject_version(mut socket: WebSocket) {
+    let msg = GameMessage::VersionMismatch {
+        server_version: PROTOCOL_VERSION,
+    };
+    let _ = socket
+        .send(Message::Text(serde_json::to_string(&msg).unwrap()))
+        .await;
+    let _ = socket.close().await;
 }
 
 pub async fn handle_socket(
@@ -24,19 +43,23 @@ pub async fn handle_socket(
     server_tx: Extension<GameServerHandle>,
     game_id: String,
     player_id: Uuid,
-    state: SharedState,
+    last_seq: Option<u64>,
 ) {
     let game_id_clone = game_id.clone();
 
     let (client_tx, mut client_rx) = mpsc::unbounded_channel();
 
     let client_tx2 = client_tx.clone();
+    let mut restarts = server_tx.restarts(&game_id);
 
     server_tx
-        .handle_connect(client_tx.clone(), game_id, player_id, state)
+        .handle_connect(client_tx.clone(), game_id.clone(), player_id, last_seq)
         .await;
 
     let (mut sender, mut receiver) = socket.split();
+    //anything from the client counts, browsers answer our pings on their own
+    let last_seen = Mutex::new(Instant::now());
+    let mut heartbeat = time::interval_at(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL);
 
     tokio::select! {
 
@@ -44,7 +67,13 @@ pub async fn handle_socket(
 
 
 
-            while let Some(Ok(Message::Text(text)))  = receiver.next().await {
+            while let Some(Ok(message))  = receiver.next().await {
+                *last_seen.lock().unwrap_or_else(PoisonError::into_inner) = Instant::now();
+                let text = match message {
+                    Message::Text(text) => text,
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
                 let game_id_clone_loop = game_id_clone.clone();
 
 
@@ -52,21 +81,110 @@ pub async fn handle_socket(
 
                     match msg {
                         WsMsg::Ping => {client_tx2.send(serde_json::to_string(&GameMessage::Pong).unwrap()).unwrap()}
-                        WsMsg::Close => {server_tx.handle_disconnect(player_id, game_id_clone_loop)}
-                        WsMsg::Roll => {println!("received {:?}", text); server_tx.handle_send(player_id, game_id_clone_loop).await}
+                        WsMsg::Close => {server_tx.handle_disconnect(player_id, game_id_clone_loop, client_tx2.clone())}
+                        WsMsg::Join => {server_tx.handle_action(player_id, game_id_clone_loop, Action::Join)}
+                        WsMsg::Roll => {println!("received {:?}", text); server_tx.handle_action(player_id, game_id_clone_loop, Action::Roll)}
+                        WsMsg::RequestRematch => {server_tx.handle_action(player_id, game_id_clone_loop, Action::RequestRematch)}
+                        WsMsg::AcceptRematch => {server_tx.handle_action(player_id, game_id_clone_loop, Action::AcceptRematch)}
+                        WsMsg::Forfeit => {server_tx.handle_action(player_id, game_id_clone_loop, Action::Forfeit)}
+                        WsMsg::ClientSeed(seed) => {server_tx.handle_client_seed(player_id, game_id_clone_loop, seed)}
+                        WsMsg::OlderFeed { before } => {server_tx.handle_older_feed(player_id, game_id_clone_loop, client_tx2.clone(), before)}
                     }
                 }
 
             }
 
-            server_tx.handle_disconnect(player_id, game_id_clone);
-
     } => {}
         _handle_write = async {
-            while let Some(message) = client_rx.recv().await {
-                sender.send(Message::Text(message)).await.unwrap();
-
+            loop {
+                tokio::select! {
+                    message = client_rx.recv() => {
+                        let Some(message) = message else { break };
+                        if sender.send(Message::Text(message)).await.is_err() {
+                            break;
+                        }
+                    }
+                    //our session died with the old game server, hang up so the client reconnects
+                    _ = restarts.recv() => {
+                        let msg = serde_json::to_string(&GameMessage::ServerRestarted).unwrap();
+                        let _ = sender.send(Message::Text(msg)).await;
+                        let _ = sender.close().await;
+                        break;
+                    }
+                    _ = heartbeat.tick() => {
+                        let quiet = last_seen.lock().unwrap_or_else(PoisonError::into_inner).elapsed();
+                        if quiet > HEARTBEAT_TIMEOUT {
+                            println!("no heartbeat from {player_id} in {quiet:?}, closing its socket");
+                            let _ = sender.close().await;
+                            break;
+                        }
+                        if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                            break;
+                        }
+                    }
+                }
             }
     } => {}
         };
+
+    //whichever side gave up first, the game server hears about it
+    server_tx.handle_disconnect(player_id, game_id, client_tx);
+}
+
+//pushes the public room list on connect and after every change, the client never sends anything
+pub async fn lobby_socket(socket: WebSocket, lobby: SharedLobby) {
+    let mut changes = lobby.subscribe();
+    let mut refresh = time::interval(LOBBY_REFRESH);
+    let (mut sender, mut receiver) = socket.split();
+
+    loop {
+        tokio::select! {
+            change = changes.recv() => {
+                //missed changes don't matter, the whole list is sent anyway
+                if let Err(RecvError::Closed) = change {
+                    break;
+                }
+            }
+            _ = refresh.tick() => {}
+            msg = receiver.next() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        }
+
+        let rooms = serde_json::to_string(&lobby.rooms()).unwrap();
+        if sender.send(Message::Text(rooms)).await.is_err() {
+            break;
+        }
+    }
+}
+
+//one place in the queue per socket, leaving the page leaves the queue
+pub async fn queue_socket(socket: WebSocket, matchmaker: MatchmakerHandle, player_id: Uuid) {
+    let (player_tx, mut player_rx) = mpsc::unbounded_channel();
+    let (mut sender, mut receiver) = socket.split();
+
+    loop {
+        tokio::select! {
+            msg = receiver.next() => match msg {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(QueueMsg::Join { start_roll }) => {
+                        matchmaker.join(player_id, start_roll, player_tx.clone())
+                    }
+                    Ok(QueueMsg::Cancel) => matchmaker.cancel(player_tx.clone()),
+                    Err(_) => {}
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            msg = player_rx.recv() => {
+                let Some(msg) = msg else { break };
+                if sender.send(Message::Text(msg)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    matchmaker.cancel(player_tx);
 }
@@ -0,0 +1,403 @@
+use deathroll_protocol::{
+    avatar, FeedEvent, GameEvent, MatchPlayer, MatchRecord, Profile, Replay, SeriesRoll,
+    REPLAY_VERSION,
+};
+use uuid::Uuid;
+
+use crate::game_server::now;
+
+//character names get their own public ids, they can never collide with a site player's
+const WOWLOG_NAMESPACE: Uuid = Uuid::from_u128(0x2b8e_61d0_5c7f_4a93_9e14_d3a6_0f5b_c872);
+//every imported game shares this room id, there never was a room
+pub const WOWLOG_GAME_ID: &str = "wowlog";
+//a deathroll nobody answered for this long is over
+const CHAIN_TIMEOUT_MS: u64 = 5 * 60 * 1000;
+
+//one `Name rolls 57 (1-1000)` line
+#[derive(Debug, Clone)]
+struct LogRoll {
+    at: u64,
+    name: String,
+    roll: u32,
+    roll_between: u32,
+}
+
+//rolls that follow each other's range, e.g. (1-1000) 57 then (1-57) 12
+#[derive(Debug, Default)]
+struct Chain {
+    rolls: Vec<LogRoll>,
+    //in the order they first rolled, a deathroll has two
+    players: Vec<String>,
+}
+
+impl Chain {
+    fn continues(&self, roll: &LogRoll) -> bool {
+        let Some(last) = self.rolls.last() else {
+            return false;
+        };
+
+        last.roll == roll.roll_between
+            && last.name != roll.name
+            && (self.players.len() < 2 || self.players.contains(&roll.name))
+            && roll.at.saturating_sub(last.at) <= CHAIN_TIMEOUT_MS
+    }
+
+    fn push(&mut self, roll: LogRoll) {
+        if !self.players.contains(&roll.name) {
+            self.players.push(roll.name.clone());
+        }
+        self.rolls.push(roll);
+    }
+}
+
+//the year the log is assumed to be from when none is given
+pub fn this_year() -> i32 {
+    //close enough, only off around new year's eve
+    const YEAR_MS: f64 = 365.2425 * 24.0 * 60.0 * 60.0 * 1000.0;
+    1970 + (now() as f64 / YEAR_MS) as i32
+}
+
+pub fn player_id(name: &str) -> Uuid {
+    Uuid::new_v5(&WOWLOG_NAMESPACE, name.as_bytes())
+}
+
+//every finished deathroll in a WoWChatLog.txt, the log has no year so it's passed in
+pub fn parse(log: &str, year: i32) -> Vec<Replay> {
+    let mut open: Vec<Chain> = Vec::new();
+    let mut replays = Vec::new();
+
+    for roll in log.lines().filter_map(|line| parse_line(line, year)) {
+        open.retain(|chain| {
+            chain
+                .rolls
+                .last()
+                .is_some_and(|last| roll.at.saturating_sub(last.at) <= CHAIN_TIMEOUT_MS)
+        });
+
+        //two games can run side by side, a known pair beats a chain still waiting for its opponent
+        let index = open
+            .iter()
+            .rposition(|chain| chain.players.len() == 2 && chain.continues(&roll))
+            .or_else(|| open.iter().rposition(|chain| chain.continues(&roll)));
+        match index {
+            Some(index) if roll.roll == 1 => {
+                let mut chain = open.remove(index);
+                chain.push(roll);
+                replays.push(replay(chain));
+            }
+            Some(index) => open[index].push(roll),
+            //a 1 that answers nothing is just an unlucky /roll
+            None if roll.roll == 1 => {}
+            None => {
+                let mut chain = Chain::default();
+                chain.push(roll);
+                open.push(chain);
+            }
+        }
+    }
+
+    replays
+}
+
+//`4/12 21:03:44.512  Thrall rolls 57 (1-1000)`, the milliseconds are missing in older clients
+fn parse_line(line: &str, year: i32) -> Option<LogRoll> {
+    let mut words = line.split_whitespace();
+    let (date, time) = (words.next()?, words.next()?);
+    let words: Vec<&str> = words.collect();
+    //anything else on the line is chat, somebody typing "rolls" doesn't count
+    let [name, "rolls", roll, range] = words.as_slice() else {
+        return None;
+    };
+
+    let roll: u32 = roll.parse().ok()?;
+    let range = range.strip_prefix("(1-")?.strip_suffix(')')?;
+    let roll_between: u32 = range.parse().ok()?;
+    if roll == 0 || roll > roll_between {
+        return None;
+    }
+
+    Some(LogRoll {
+        at: timestamp(date, time, year)?,
+        name: name.to_string(),
+        roll,
+        roll_between,
+    })
+}
+
+//unix ms, the log's local time is taken as utc
+fn timestamp(date: &str, time: &str, year: i32) -> Option<u64> {
+    let (month, day) = date.split_once('/')?;
+    let (month, day): (u32, u32) = (month.parse().ok()?, day.parse().ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (time, ms) = time.split_once('.').unwrap_or((time, "0"));
+    let mut hms = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (hms.next()??, hms.next()??, hms.next()??);
+    let ms: u64 = ms.parse().ok()?;
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some((((days * 24 + hours) * 60 + minutes) * 60 + seconds) * 1000 + ms)
+}
+
+//days since 1970-01-01, Howard Hinnant's algorithm
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+//the same replay a game on the site gets, minus the seeds
+fn replay(chain: Chain) -> Replay {
+    let seat_of = |name: &str| {
+        chain
+            .players
+            .iter()
+            .position(|player| player == name)
+            .unwrap_or_default()
+    };
+    let (first, last) = (&chain.rolls[0], &chain.rolls[chain.rolls.len() - 1]);
+    let loser = seat_of(&last.name);
+    let winner = 1 - loser;
+    let mut score = vec![0; 2];
+    score[winner] = 1;
+
+    let mut events = vec![FeedEvent {
+        seq: 1,
+        at: first.at,
+        event: GameEvent::NewGame {
+            start_roll: first.roll_between,
+            start_seat: 0,
+        },
+    }];
+    for (nonce, roll) in chain.rolls.iter().enumerate() {
+        let seat = seat_of(&roll.name);
+        let nonce = nonce as u64;
+        let event = if roll.roll == 1 {
+            GameEvent::Died {
+                seat,
+                roll_between: roll.roll_between,
+                winner,
+                score: score.clone(),
+                nonce,
+            }
+        } else {
+            GameEvent::Rolled {
+                seat,
+                roll: roll.roll,
+                roll_between: roll.roll_between,
+                nonce,
+            }
+        };
+        events.push(FeedEvent {
+            seq: nonce + 2,
+            at: roll.at,
+            event,
+        });
+    }
+
+    //importing the same log twice finds the same games
+    let key = format!("{}:{}:{}", first.at, chain.players[0], chain.players[1]);
+    let game = MatchRecord {
+        id: Uuid::new_v5(&WOWLOG_NAMESPACE, key.as_bytes())
+            .simple()
+            .to_string(),
+        game_id: WOWLOG_GAME_ID.to_string(),
+        players: chain
+            .players
+            .iter()
+            .enumerate()
+            .map(|(seat, name)| MatchPlayer {
+                id: player_id(name).to_string(),
+                profile: Profile {
+                    name: name.clone(),
+                    avatar: avatar(seat).to_string(),
+                },
+            })
+            .collect(),
+        start_roll: first.roll_between,
+        rolls: chain
+            .rolls
+            .iter()
+            .map(|roll| SeriesRoll {
+                seat: seat_of(&roll.name),
+                roll: roll.roll,
+            })
+            .collect(),
+        winner,
+        started_at: first.at,
+        finished_at: last.at,
+    };
+
+    Replay {
+        version: REPLAY_VERSION,
+        game,
+        events,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rolls(replay: &Replay) -> Vec<(usize, u32)> {
+        replay
+            .game
+            .rolls
+            .iter()
+            .map(|roll| (roll.seat, roll.roll))
+            .collect()
+    }
+
+    fn names(replay: &Replay) -> Vec<&str> {
+        replay
+            .game
+            .players
+            .iter()
+            .map(|player| player.profile.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn chained_ranges_make_one_game() {
+        let log = "4/12 21:03:44.512  Thrall rolls 57 (1-1000)
+4/12 21:03:50.100  Jaina rolls 12 (1-57)
+4/12 21:03:55.000  Thrall rolls 3 (1-12)
+4/12 21:04:01.250  Jaina rolls 1 (1-3)";
+        let replays = parse(log, 2024);
+
+        assert_eq!(replays.len(), 1);
+        let replay = &replays[0];
+        assert_eq!(names(replay), ["Thrall", "Jaina"]);
+        assert_eq!(rolls(replay), [(0, 57), (1, 12), (0, 3), (1, 1)]);
+        assert_eq!(replay.game.start_roll, 1000);
+        assert_eq!(replay.game.winner, 0);
+        assert_eq!(replay.game.players[0].id, player_id("Thrall").to_string());
+        assert!(matches!(
+            replay.events.last().unwrap().event,
+            GameEvent::Died {
+                seat: 1,
+                winner: 0,
+                nonce: 3,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn same_log_same_ids() {
+        let log = "4/12 21:03:44  Thrall rolls 5 (1-100)
+4/12 21:03:50  Jaina rolls 1 (1-5)";
+
+        assert_eq!(parse(log, 2024)[0].game.id, parse(log, 2024)[0].game.id);
+    }
+
+    #[test]
+    fn interleaved_games_stay_apart() {
+        let log = "4/12 21:00:00  Thrall rolls 500 (1-1000)
+4/12 21:00:01  Sylvanas rolls 40 (1-100)
+4/12 21:00:02  Jaina rolls 200 (1-500)
+4/12 21:00:03  Anduin rolls 7 (1-40)
+4/12 21:00:04  Sylvanas rolls 1 (1-7)
+4/12 21:00:05  Thrall rolls 1 (1-200)";
+        let replays = parse(log, 2024);
+
+        assert_eq!(replays.len(), 2);
+        assert_eq!(names(&replays[0]), ["Sylvanas", "Anduin"]);
+        assert_eq!(rolls(&replays[0]), [(0, 40), (1, 7), (0, 1)]);
+        assert_eq!(replays[0].game.winner, 1);
+        assert_eq!(names(&replays[1]), ["Thrall", "Jaina"]);
+        assert_eq!(rolls(&replays[1]), [(0, 500), (1, 200), (0, 1)]);
+    }
+
+    #[test]
+    fn a_third_player_cant_join_a_pair() {
+        let log = "4/12 21:00:00  Thrall rolls 50 (1-100)
+4/12 21:00:01  Jaina rolls 10 (1-50)
+4/12 21:00:02  Anduin rolls 1 (1-10)";
+
+        assert!(parse(log, 2024).is_empty());
+    }
+
+    #[test]
+    fn a_stray_one_is_not_a_game() {
+        let log = "4/12 21:00:00  Thrall rolls 1 (1-100)
+4/12 21:00:01  Jaina rolls 1 (1-1000)";
+
+        assert!(parse(log, 2024).is_empty());
+    }
+
+    #[test]
+    fn idle_timeout_splits_games() {
+        //six minutes between the 12 and the answer to it
+        let log = "4/12 21:00:00  Thrall rolls 57 (1-1000)
+4/12 21:00:10  Jaina rolls 12 (1-57)
+4/12 21:06:10  Thrall rolls 1 (1-12)
+4/12 21:10:00  Thrall rolls 30 (1-100)
+4/12 21:14:59  Jaina rolls 1 (1-30)";
+        let replays = parse(log, 2024);
+
+        assert_eq!(replays.len(), 1);
+        assert_eq!(rolls(&replays[0]), [(0, 30), (1, 1)]);
+        assert_eq!(replays[0].game.start_roll, 100);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(2024, 2, 29), 19_782);
+        assert_eq!(days_from_civil(2024, 3, 1), 19_783);
+        assert_eq!(days_from_civil(2023, 3, 1), 19_417);
+
+        assert_eq!(timestamp("1/1", "00:00:00", 1970), Some(0));
+        assert_eq!(
+            timestamp("2/29", "21:03:44.512", 2024),
+            Some(19_782 * 86_400_000 + ((21 * 60 + 3) * 60 + 44) * 1000 + 512)
+        );
+        //the year only moves days from march on in a leap year
+        assert_eq!(
+            timestamp("3/1", "00:00:00", 2024).unwrap()
+                - timestamp("3/1", "00:00:00", 2023).unwrap(),
+            366 * 86_400_000
+        );
+        assert_eq!(timestamp("13/1", "00:00:00", 2024), None);
+        assert_eq!(timestamp("4/0", "00:00:00", 2024), None);
+        assert_eq!(timestamp("4/12", "21:03", 2024), None);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let lines = [
+            "",
+            "4/12",
+            "4/12 21:03:44",
+            "4/12 21:03:44  Thrall rolls",
+            "4/12 21:03:44  Thrall rolls 57",
+            "4/12 21:03:44  Thrall rolls 57 (1-1000",
+            "4/12 21:03:44  Thrall rolls 57 (2-1000)",
+            "4/12 21:03:44  Thrall rolls x (1-1000)",
+            "4/12 21:03:44  Thrall rolls 0 (1-1000)",
+            "4/12 21:03:44  Thrall rolls 1001 (1-1000)",
+            "4/12 21:03:44  [Guild] Thrall: Jaina rolls 57 (1-1000)",
+            "4/12 21:03:44  Thrall says rolls 57 (1-1000)",
+            "xx 21:03:44  Thrall rolls 57 (1-1000)",
+            "4/12 noon  Thrall rolls 57 (1-1000)",
+        ];
+        for line in lines {
+            assert!(parse_line(line, 2024).is_none(), "{line}");
+        }
+
+        let roll = parse_line("4/12 21:03:44  Thrall rolls 57 (1-1000)", 2024).unwrap();
+        assert_eq!(
+            (roll.name.as_str(), roll.roll, roll.roll_between),
+            ("Thrall", 57, 1000)
+        );
+    }
+}